@@ -0,0 +1,78 @@
+use bytes::Bytes;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use lexdata::{storage_to_value, value_to_storage, Aspect, Value};
+use rug::Integer;
+
+const SAMPLE: usize = 1000;
+
+fn samples() -> Vec<(&'static str, Aspect, Vec<Value>)> {
+    let ints = (0..SAMPLE as i64).map(|i| (i * 7919) % 2001 - 1000);
+    vec![
+        (
+            "int32",
+            Aspect::Int,
+            ints.clone().map(|i| Value::Int32(i as i32)).collect(),
+        ),
+        (
+            "int64",
+            Aspect::Long,
+            ints.clone().map(|i| Value::Int64(i << 32)).collect(),
+        ),
+        (
+            "float64",
+            Aspect::Double,
+            ints.clone()
+                .map(|i| Value::Float64(i as f64 / 3.0))
+                .collect(),
+        ),
+        (
+            "bigint",
+            Aspect::Integer,
+            ints.clone()
+                .map(|i| Value::BigInt(Integer::from(i) * Integer::from(u64::MAX)))
+                .collect(),
+        ),
+        (
+            "string",
+            Aspect::String,
+            ints.map(|i| Value::String(format!("key-{i}"))).collect(),
+        ),
+    ]
+}
+
+fn encode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("encode");
+    for (name, a, values) in samples() {
+        group.throughput(Throughput::Elements(values.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(name), &values, |b, values| {
+            b.iter(|| {
+                for v in values {
+                    black_box(value_to_storage(v.clone(), a).unwrap());
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+fn decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode");
+    for (name, a, values) in samples() {
+        let keys: Vec<Bytes> = values
+            .into_iter()
+            .map(|v| Bytes::from(value_to_storage(v, a).unwrap()))
+            .collect();
+        group.throughput(Throughput::Elements(keys.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(name), &keys, |b, keys| {
+            b.iter(|| {
+                for k in keys {
+                    black_box(storage_to_value(k.clone()).unwrap());
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, encode, decode);
+criterion_main!(benches);
@@ -9,14 +9,19 @@
 
 use std::str::from_utf8;
 
-use rug::Integer;
-//use std::cmp::Ordering;
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use bytes::Bytes;
-use chrono::{DateTime, NaiveDateTime};
+use chrono::{DateTime, FixedOffset, NaiveTime, SecondsFormat, Timelike, Utc};
+#[cfg(feature = "half")]
+use half::f16;
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
+use rug::{Integer, Rational};
+use std::cmp::Ordering;
+use std::fmt::{UpperExp, Write};
 use std::io::Cursor;
+use std::ops::{Bound, Range};
+use std::sync::{PoisonError, RwLock};
 
 // We need one of these for each strategy used to store our data
 #[derive(FromPrimitive, PartialEq, Eq, Debug, Clone, Copy)]
@@ -29,6 +34,22 @@ pub enum StorageType {
     BigInt,
     BigNum,
     DateTime,
+    Float16,
+    DecimalExponent,
+    Bitmask,
+    HexBinary,
+    Boolean,
+    Time,
+    Rational,
+    GMonth,
+    Custom,
+    Version,
+    Numeric,
+    DateTimeStamp,
+    UInt32,
+    SizedInt,
+    PrefixedString,
+    OrderedURI,
 }
 
 // Since XSD requires storage of the constraints on the data,
@@ -95,9 +116,33 @@ pub enum Aspect {
     // Individually encoded Boolean avoid storage element
     False,
     True,
+    // Half precision IEEE
+    Float16,
+    // Decimals stored by exponent and mantissa
+    DecimalExponent,
+    // Sets of flags
+    Bitmask,
+    // Exact fractions
+    Rational,
+    // Values of a registered custom codec, stored under its own tag
+    Custom,
+    // Dotted numeric versions such as 1.2.10
+    Version,
+    // Integers of every width and finite floats under one tag, so that
+    // they interleave
+    Numeric,
+    // Integers in a caller chosen width of 1 to 8 bytes
+    SizedInt,
+    // Strings preceded by their length, which may hold 0 bytes
+    PrefixedString,
+    // anyURIs ordered by their parsed components
+    OrderedURI,
 }
 
-#[derive(PartialEq, Debug)]
+// Non exhaustive, as the Float16 variant is only there with the half
+// feature and enabling it must not break matches elsewhere.
+#[derive(PartialEq, Debug, Clone)]
+#[non_exhaustive]
 pub enum Value {
     String(String),
     BigInt(Integer),
@@ -106,10 +151,118 @@ pub enum Value {
     Float32(f32),
     Float64(f64),
     Boolean(bool),
+    #[cfg(feature = "half")]
+    Float16(f16),
+    Bitmask(u64),
+    Rational(Rational),
+    Custom(u8, Box<Value>),
+    DateTime(DateTime<FixedOffset>),
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::String(s)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::String(s.to_string())
+    }
+}
+
+impl From<Integer> for Value {
+    fn from(i: Integer) -> Self {
+        Value::BigInt(i)
+    }
+}
+
+impl From<i32> for Value {
+    fn from(i: i32) -> Self {
+        Value::Int32(i)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(i: i64) -> Self {
+        Value::Int64(i)
+    }
+}
+
+impl From<f32> for Value {
+    fn from(f: f32) -> Self {
+        Value::Float32(f)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(f: f64) -> Self {
+        Value::Float64(f)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Boolean(b)
+    }
+}
+
+impl From<Rational> for Value {
+    fn from(r: Rational) -> Self {
+        Value::Rational(r)
+    }
+}
+
+impl From<DateTime<FixedOffset>> for Value {
+    fn from(dt: DateTime<FixedOffset>) -> Self {
+        Value::DateTime(dt)
+    }
+}
+
+#[cfg(feature = "half")]
+impl From<f16> for Value {
+    fn from(f: f16) -> Self {
+        Value::Float16(f)
+    }
+}
+
+// A decoded value borrowing from the buffer where that saves an
+// allocation. Bigints are left as their stored bytes until needed, and
+// values which must be built anyway are Owned.
+#[derive(PartialEq, Debug, Clone)]
+pub enum ValueRef<'a> {
+    String(&'a str),
+    BigInt(&'a [u8]),
+    Int32(i32),
+    Int64(i64),
+    Float32(f32),
+    Float64(f64),
+    Boolean(bool),
+    Owned(Value),
+}
+
+impl ValueRef<'_> {
+    pub fn to_value(&self) -> Result<Value, LexDataError> {
+        match self {
+            ValueRef::String(s) => Ok(Value::String(s.to_string())),
+            ValueRef::BigInt(bytes) => storage_to_bigint(bytes),
+            ValueRef::Int32(i) => Ok(Value::Int32(*i)),
+            ValueRef::Int64(i) => Ok(Value::Int64(*i)),
+            ValueRef::Float32(f) => Ok(Value::Float32(*f)),
+            ValueRef::Float64(f) => Ok(Value::Float64(*f)),
+            ValueRef::Boolean(b) => Ok(Value::Boolean(*b)),
+            ValueRef::Owned(v) => Ok(v.clone()),
+        }
+    }
 }
 
 pub fn aspect_storage(aspect: Aspect) -> StorageType {
-    match aspect {
+    try_aspect_storage(aspect).expect("Unimplemented aspect")
+}
+
+// The storage type of an aspect, or None if it has no codec yet
+fn try_aspect_storage(aspect: Aspect) -> Option<StorageType> {
+    let storage_type = match aspect {
         Aspect::String
         | Aspect::AnyURI
         | Aspect::Language
@@ -126,16 +279,282 @@ pub fn aspect_storage(aspect: Aspect) -> StorageType {
         Aspect::Decimal => StorageType::BigNum,
         Aspect::Float => StorageType::Float32,
         Aspect::Double => StorageType::Float64,
+        Aspect::Float16 => StorageType::Float16,
+        Aspect::DecimalExponent => StorageType::DecimalExponent,
+        Aspect::Bitmask => StorageType::Bitmask,
+        Aspect::HexBinary => StorageType::HexBinary,
+        Aspect::Boolean | Aspect::True | Aspect::False => StorageType::Boolean,
         Aspect::Long => StorageType::Int64,
+        Aspect::UnsignedInt => StorageType::UInt32,
+        Aspect::SizedInt => StorageType::SizedInt,
+        Aspect::PrefixedString => StorageType::PrefixedString,
+        Aspect::OrderedURI => StorageType::OrderedURI,
         Aspect::Int | Aspect::Short | Aspect::Byte => StorageType::Int32,
         Aspect::Integer | Aspect::PositiveInteger | Aspect::NonNegativeInteger => {
             StorageType::BigInt
         }
         Aspect::DateTime => StorageType::DateTime,
-        _ => panic!("Unimplemented aspect"),
+        Aspect::DateTimeStamp => StorageType::DateTimeStamp,
+        Aspect::Time => StorageType::Time,
+        Aspect::Rational => StorageType::Rational,
+        Aspect::GMonth => StorageType::GMonth,
+        Aspect::Custom => StorageType::Custom,
+        Aspect::Version => StorageType::Version,
+        Aspect::Numeric => StorageType::Numeric,
+        _ => return None,
+    };
+    Some(storage_type)
+}
+
+// Every implemented aspect stored as `ty`, in aspect byte order
+pub fn aspects_for_storage(ty: StorageType) -> Vec<Aspect> {
+    (0..=u8::MAX)
+        .filter_map(Aspect::from_u8)
+        .filter(|a| try_aspect_storage(*a) == Some(ty))
+        .collect()
+}
+
+const XSD_NAMESPACE: &str = "http://www.w3.org/2001/XMLSchema#";
+
+// The aspect for an XSD datatype IRI
+pub fn aspect_from_iri(iri: &str) -> Option<Aspect> {
+    let local = iri.strip_prefix(XSD_NAMESPACE)?;
+    let aspect = match local {
+        "string" => Aspect::String,
+        "boolean" => Aspect::Boolean,
+        "decimal" => Aspect::Decimal,
+        "integer" => Aspect::Integer,
+        "double" => Aspect::Double,
+        "float" => Aspect::Float,
+        "date" => Aspect::Date,
+        "time" => Aspect::Time,
+        "dateTime" => Aspect::DateTime,
+        "dateTimeStamp" => Aspect::DateTimeStamp,
+        "gYear" => Aspect::GYear,
+        "gMonth" => Aspect::GMonth,
+        "gDay" => Aspect::GDay,
+        "gYearMonth" => Aspect::GYearMonth,
+        "gMonthDay" => Aspect::GMonthDay,
+        "duration" => Aspect::Duration,
+        "yearMonthDuration" => Aspect::YearMonthDuration,
+        "dayTimeDuration" => Aspect::DayTimeDuration,
+        "byte" => Aspect::Byte,
+        "short" => Aspect::Short,
+        "int" => Aspect::Int,
+        "long" => Aspect::Long,
+        "unsignedByte" => Aspect::UnsignedByte,
+        "unsignedShort" => Aspect::UnsignedShort,
+        "unsignedInt" => Aspect::UnsignedInt,
+        "unsignedLong" => Aspect::UnsignedLong,
+        "positiveInteger" => Aspect::PositiveInteger,
+        "nonNegativeInteger" => Aspect::NonNegativeInteger,
+        "hexBinary" => Aspect::HexBinary,
+        "base64Binary" => Aspect::Base64Binary,
+        "anyURI" => Aspect::AnyURI,
+        "language" => Aspect::Language,
+        "normalizedString" => Aspect::NormalizedString,
+        "token" => Aspect::Token,
+        "NMTOKEN" => Aspect::NmToken,
+        "Name" => Aspect::Name,
+        "NCName" => Aspect::NCName,
+        "NOTATION" => Aspect::NOtation,
+        "QName" => Aspect::QName,
+        "ID" => Aspect::ID,
+        "IDREF" => Aspect::IdRef,
+        "ENTITY" => Aspect::Entity,
+        _ => return None,
+    };
+    Some(aspect)
+}
+
+// Rust reads inf, infinity and nan in any case, where XSD only spells
+// the special floats INF, +INF, -INF and NaN.
+fn is_xsd_float(lexical: &str) -> bool {
+    let unsigned = lexical.strip_prefix(['+', '-']).unwrap_or(lexical);
+    match unsigned.chars().next() {
+        Some(c) if c.is_ascii_alphabetic() => matches!(lexical, "INF" | "+INF" | "-INF" | "NaN"),
+        _ => true,
+    }
+}
+
+// Parse a lexical form into the Value that the aspect is stored from
+pub fn parse_value(lexical: &str, a: Aspect) -> Result<Value, LexDataError> {
+    let bad = || LexDataError::BadLexicalForm(format!("{lexical} is not a valid {a:?}"));
+    let storage_type = match try_aspect_storage(a) {
+        Some(storage_type) => storage_type,
+        None => {
+            return Err(LexDataError::UnexpectedAspect(format!(
+                "The aspect {a:?} is not implemented"
+            )))
+        }
+    };
+    match storage_type {
+        StorageType::String
+        | StorageType::BigNum
+        | StorageType::DateTime
+        | StorageType::DecimalExponent
+        | StorageType::HexBinary
+        | StorageType::Time
+        | StorageType::GMonth
+        | StorageType::Version
+        | StorageType::DateTimeStamp
+        | StorageType::PrefixedString
+        | StorageType::OrderedURI => Ok(Value::String(lexical.to_string())),
+        StorageType::Int32 => lexical.parse().map(Value::Int32).map_err(|_| bad()),
+        StorageType::Int64 | StorageType::SizedInt => {
+            lexical.parse().map(Value::Int64).map_err(|_| bad())
+        }
+        StorageType::UInt32 => lexical
+            .parse::<u32>()
+            .map(|i| Value::Int64(i.into()))
+            .map_err(|_| bad()),
+        StorageType::BigInt | StorageType::Numeric => {
+            lexical.parse().map(Value::BigInt).map_err(|_| bad())
+        }
+        StorageType::Float16 | StorageType::Float32 | StorageType::Float64
+            if !is_xsd_float(lexical) =>
+        {
+            Err(bad())
+        }
+        StorageType::Float32 => lexical.parse().map(Value::Float32).map_err(|_| bad()),
+        StorageType::Float64 => lexical.parse().map(Value::Float64).map_err(|_| bad()),
+        #[cfg(feature = "half")]
+        StorageType::Float16 => lexical.parse().map(Value::Float16).map_err(|_| bad()),
+        #[cfg(not(feature = "half"))]
+        StorageType::Float16 => Err(LexDataError::BadFloat16Layout(
+            "Float16 values need the half feature".to_string(),
+        )),
+        StorageType::Bitmask => lexical.parse().map(Value::Bitmask).map_err(|_| bad()),
+        StorageType::Rational => lexical.parse().map(Value::Rational).map_err(|_| bad()),
+        StorageType::Boolean => match lexical {
+            "true" | "1" => Ok(Value::Boolean(true)),
+            "false" | "0" => Ok(Value::Boolean(false)),
+            _ => Err(bad()),
+        },
+        StorageType::Custom => Err(LexDataError::UnexpectedAspect(
+            "Custom values are built as Value::Custom with a registered tag".to_string(),
+        )),
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum TypedInput {
+    String(String),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+}
+
+// Build the value an aspect takes from plain input and check it in one
+// step. Integers and floats go straight to the matching fixed widths,
+// range checked for the narrow integers and single precision floats;
+// anything else is read as a lexical form. The value is then encoded
+// once to check it, and the bytes dropped.
+pub fn typed_value(a: Aspect, input: TypedInput) -> Result<Value, LexDataError> {
+    let storage_type = try_aspect_storage(a);
+    let v = match input {
+        TypedInput::Int(i) if storage_type == Some(StorageType::Int32) => match i32::try_from(i) {
+            Ok(i) => Value::Int32(i),
+            Err(_) => {
+                return Err(LexDataError::OutOfRange(format!(
+                    "{i} is out of range for {a:?}"
+                )))
+            }
+        },
+        TypedInput::Int(i)
+            if matches!(
+                storage_type,
+                Some(StorageType::Int64 | StorageType::SizedInt)
+            ) =>
+        {
+            Value::Int64(i)
+        }
+        // range checked with the facets below
+        TypedInput::Int(i) if storage_type == Some(StorageType::UInt32) => Value::Int64(i),
+        TypedInput::Float(f) if storage_type == Some(StorageType::Float64) => Value::Float64(f),
+        TypedInput::Float(f) if storage_type == Some(StorageType::Float32) => {
+            if f.is_finite() && f.abs() > f64::from(f32::MAX) {
+                return Err(LexDataError::OutOfRange(format!(
+                    "{f} is out of range for {a:?}"
+                )));
+            }
+            Value::Float32(f as f32)
+        }
+        // Rust spells the special floats inf and NaN, XSD INF and NaN
+        TypedInput::Float(f) if !f.is_finite() => parse_value(&canonical_float(f), a)?,
+        TypedInput::String(s) => parse_value(&s, a)?,
+        TypedInput::Int(i) => parse_value(&i.to_string(), a)?,
+        TypedInput::Float(f) => parse_value(&f.to_string(), a)?,
+        TypedInput::Bool(b) => parse_value(&b.to_string(), a)?,
+    };
+    validate_facets(&v, a)?;
+    value_to_storage(v.clone(), a)?;
+    Ok(v)
+}
+
+// JSON numbers, strings and booleans are read as lexical forms of the
+// aspect, so a bigint or decimal given as a string loses nothing.
+#[cfg(feature = "serde_json")]
+pub fn value_from_json(j: &serde_json::Value, a: Aspect) -> Result<Value, LexDataError> {
+    match j {
+        serde_json::Value::String(s) => parse_value(s, a),
+        serde_json::Value::Number(n) => parse_value(&n.to_string(), a),
+        serde_json::Value::Bool(b) => parse_value(if *b { "true" } else { "false" }, a),
+        _ => Err(LexDataError::BadLexicalForm(format!(
+            "{j} is not a valid {a:?}"
+        ))),
+    }
+}
+
+// Bigints and rationals become strings to avoid precision loss, as do
+// floats JSON cannot hold (NaN and the infinities).
+#[cfg(feature = "serde_json")]
+pub fn value_to_json(v: &Value) -> serde_json::Value {
+    let float = |f: f64| match serde_json::Number::from_f64(f) {
+        Some(n) => serde_json::Value::Number(n),
+        None => serde_json::Value::String(canonical_float(f)),
+    };
+    match v {
+        Value::String(s) => serde_json::Value::String(s.clone()),
+        Value::BigInt(i) => serde_json::Value::String(i.to_string()),
+        Value::Rational(r) => serde_json::Value::String(r.to_string()),
+        Value::Int32(i) => serde_json::Value::from(*i),
+        Value::Int64(i) => serde_json::Value::from(*i),
+        Value::Bitmask(bits) => serde_json::Value::from(*bits),
+        Value::Float32(f) => float(f64::from(*f)),
+        Value::Float64(f) => float(*f),
+        #[cfg(feature = "half")]
+        Value::Float16(f) => float(f64::from(*f)),
+        Value::Boolean(b) => serde_json::Value::Bool(*b),
+        Value::Custom(_, v) => value_to_json(v),
+        Value::DateTime(dt) => {
+            serde_json::Value::String(dt.to_rfc3339_opts(SecondsFormat::AutoSi, false))
+        }
     }
 }
 
+// The front door for RDF literals: resolve the datatype, parse, check
+// the facets of the datatype and encode. Datatypes outside XSD are stored as strings if
+// `unknown_as_string` is set, and are an error otherwise.
+pub fn encode_literal(
+    lexical: &str,
+    datatype_iri: &str,
+    unknown_as_string: bool,
+) -> Result<Vec<u8>, LexDataError> {
+    let a = match aspect_from_iri(datatype_iri) {
+        Some(a) => a,
+        None if unknown_as_string => Aspect::String,
+        None => {
+            return Err(LexDataError::UnknownDatatype(format!(
+                "{datatype_iri} is not a known datatype"
+            )))
+        }
+    };
+    let v = parse_value(lexical, a)?;
+    validate_facets(&v, a)?;
+    value_to_storage(v, a)
+}
+
 fn aspect_byte(a: Aspect) -> u8 {
     a as u8
 }
@@ -144,32 +563,141 @@ pub fn byte_aspect(b: &u8) -> Aspect {
     FromPrimitive::from_u32(*b as u32).expect("Aspect byte has no aspect representation")
 }
 
+// The aspect a key decodes as, without decoding it. This is the
+// logical aspect storage_to_value reports: Boolean for the True and
+// False bytes, and the original aspect of a numeric key.
+pub fn peek_aspect(bytes: &[u8]) -> Result<Aspect, LexDataError> {
+    let first = match bytes.first() {
+        Some(first) => *first,
+        None => {
+            return Err(LexDataError::TruncatedInput(
+                "An empty buffer holds no value".to_string(),
+            ))
+        }
+    };
+    if first >= CUSTOM_TAG_MIN {
+        return Ok(Aspect::Custom);
+    }
+    if is_boolean_tag(first) {
+        return Ok(Aspect::Boolean);
+    }
+    match Aspect::from_u8(first) {
+        Some(Aspect::True | Aspect::False) => Ok(Aspect::Boolean),
+        Some(Aspect::Numeric) => {
+            let (_, size, idx) = size_decode(&bytes[1..]);
+            let tail = 1 + idx + size as usize;
+            let tail_size = numeric_tail_size(bytes.get(tail..).unwrap_or_default());
+            match bytes
+                .get(tail + tail_size - 1)
+                .and_then(|b| Aspect::from_u8(*b))
+            {
+                Some(a) => Ok(a),
+                None => Err(LexDataError::TruncatedInput(
+                    "The integer is missing its aspect".to_string(),
+                )),
+            }
+        }
+        Some(a) => Ok(a),
+        None => Err(LexDataError::UnexpectedAspect(format!(
+            "{first} is not an aspect byte"
+        ))),
+    }
+}
+
 #[derive(Debug)]
 pub enum LexDataError {
     UnexpectedAspect(String),
     BadFloat32Layout(String),
     BadFloat64Layout(String),
+    BadFloat16Layout(String),
+    BadBitmaskLayout(String),
     BadInt32Layout(String),
     BadInt64Layout(String),
     BadDateFormat(String),
+    BadDeltaLayout(String),
+    ChecksumMismatch(String),
+    TruncatedInput(String),
+    BadDecimalFormat(String),
+    OutOfRange(String),
+    ValueAspectMismatch {
+        value_kind: &'static str,
+        aspect: Aspect,
+    },
+    TrailingBytes(String),
+    BadName(String),
+    BadBigIntLayout(String),
+    UnsortedDictionary(String),
+    BadLexicalForm(String),
+    UnknownDatatype(String),
+    BadBinaryLayout(String),
+    BadRationalLayout(String),
+    FieldError {
+        index: usize,
+        source: Box<LexDataError>,
+    },
 }
 
 pub fn value_to_storage(v: Value, a: Aspect) -> Result<Vec<u8>, LexDataError> {
+    if !value_fits_aspect(&v, a) {
+        return Err(LexDataError::ValueAspectMismatch {
+            value_kind: value_kind(&v),
+            aspect: a,
+        });
+    }
+    if a == Aspect::Numeric {
+        let natural = match v {
+            Value::Int32(_) => Aspect::Int,
+            Value::Int64(_) => Aspect::Long,
+            Value::Float32(_) => Aspect::Float,
+            Value::Float64(_) => Aspect::Double,
+            _ => Aspect::Integer,
+        };
+        return encode_numeric(&v, natural);
+    }
     match v {
         Value::String(s) => {
             if a == Aspect::DateTime {
                 date_time_to_storage(s, a)
             } else if a == Aspect::Decimal {
                 bignum_to_storage(s, a)
+            } else if a == Aspect::DecimalExponent {
+                decimal_exponent_to_storage(s, a)
+            } else if a == Aspect::HexBinary {
+                hex_binary_to_storage(s, a)
+            } else if a == Aspect::Time {
+                lexical_time_to_storage(s, a)
+            } else if a == Aspect::GMonth {
+                g_month_to_storage(s, a)
+            } else if a == Aspect::Version {
+                version_to_storage(s, a)
+            } else if a == Aspect::DateTimeStamp {
+                chrono_to_storage(&parse_date_time(&s)?, a)
+            } else if a == Aspect::PrefixedString {
+                Ok(encode_string_length_prefixed(&s))
+            } else if a == Aspect::OrderedURI {
+                #[cfg(feature = "url")]
+                return encode_uri_ordered(&s);
+                #[cfg(not(feature = "url"))]
+                return Err(LexDataError::UnexpectedAspect(
+                    "OrderedURI keys need the url feature".to_string(),
+                ));
             } else {
                 string_to_storage(s, a)
             }
         }
         Value::Int32(i) => int32_to_storage(i, a),
         Value::BigInt(i) => bigint_to_storage(i, a),
+        Value::Int64(i) if aspect_storage(a) == StorageType::UInt32 => uint32_to_storage(i, a),
+        Value::Int64(i) if a == Aspect::SizedInt => int_to_storage_width(i, 8),
         Value::Int64(i) => int64_to_storage(i, a),
         Value::Float32(f) => float32_to_storage(f, a),
         Value::Float64(f) => float64_to_storage(f, a),
+        #[cfg(feature = "half")]
+        Value::Float16(f) => float16_to_storage(f, a),
+        Value::Bitmask(bits) => bitmask_to_storage(bits, 8, a),
+        Value::Rational(r) => rational_to_storage(r, a),
+        Value::Custom(tag, v) => custom_to_storage(tag, *v),
+        Value::DateTime(dt) => date_time_value_to_storage(&dt, a),
         Value::Boolean(b) => {
             if a == Aspect::Boolean {
                 let mut buf = Vec::with_capacity(1);
@@ -194,44 +722,630 @@ pub fn value_to_storage(v: Value, a: Aspect) -> Result<Vec<u8>, LexDataError> {
     }
 }
 
-fn string_to_storage(v: String, a: Aspect) -> Result<Vec<u8>, LexDataError> {
+fn value_kind(v: &Value) -> &'static str {
+    match v {
+        Value::String(_) => "String",
+        Value::BigInt(_) => "BigInt",
+        Value::Int32(_) => "Int32",
+        Value::Int64(_) => "Int64",
+        Value::Float32(_) => "Float32",
+        Value::Float64(_) => "Float64",
+        Value::Boolean(_) => "Boolean",
+        #[cfg(feature = "half")]
+        Value::Float16(_) => "Float16",
+        Value::Bitmask(_) => "Bitmask",
+        Value::Rational(_) => "Rational",
+        Value::Custom(..) => "Custom",
+        Value::DateTime(_) => "DateTime",
+    }
+}
+
+fn value_fits_aspect(v: &Value, a: Aspect) -> bool {
+    // Booleans are stored as the aspect byte itself
+    if let Value::Boolean(_) = v {
+        return a == Aspect::Boolean;
+    }
     let storage_type = aspect_storage(a);
-    if storage_type == StorageType::String {
-        let aspect_u8 = aspect_byte(a);
-        let string_bytes: &[u8] = &v.into_bytes();
-        let mut result = Vec::with_capacity(string_bytes.len() + 1);
-        result.push(aspect_u8);
-        result.extend(string_bytes);
-        Ok(result)
-    } else {
-        Err(LexDataError::UnexpectedAspect(format!(
+    match v {
+        Value::String(_) => matches!(
+            storage_type,
+            StorageType::String
+                | StorageType::BigNum
+                | StorageType::DateTime
+                | StorageType::DecimalExponent
+                | StorageType::HexBinary
+                | StorageType::Time
+                | StorageType::GMonth
+                | StorageType::Version
+                | StorageType::DateTimeStamp
+                | StorageType::PrefixedString
+                | StorageType::OrderedURI
+        ),
+        Value::BigInt(_) => {
+            storage_type == StorageType::BigInt || storage_type == StorageType::Numeric
+        }
+        Value::Int32(_) => {
+            storage_type == StorageType::Int32 || storage_type == StorageType::Numeric
+        }
+        // a bare integer is not a date time, see timestamp_to_storage
+        Value::Int64(_) => {
+            storage_type == StorageType::Int64
+                || storage_type == StorageType::UInt32
+                || storage_type == StorageType::SizedInt
+                || storage_type == StorageType::Numeric
+        }
+        Value::Float32(_) => {
+            storage_type == StorageType::Float32 || storage_type == StorageType::Numeric
+        }
+        Value::Float64(_) => {
+            storage_type == StorageType::Float64 || storage_type == StorageType::Numeric
+        }
+        Value::Boolean(_) => false,
+        #[cfg(feature = "half")]
+        Value::Float16(_) => storage_type == StorageType::Float16,
+        Value::Bitmask(_) => storage_type == StorageType::Bitmask,
+        Value::Rational(_) => storage_type == StorageType::Rational,
+        Value::Custom(..) => storage_type == StorageType::Custom,
+        Value::DateTime(_) => {
+            storage_type == StorageType::DateTime || storage_type == StorageType::DateTimeStamp
+        }
+    }
+}
+
+// Check that a string can be written under a string aspect. Every
+// encoder of strings goes through this, so the name aspects are
+// validated however the key is built. Decoding stops a string at the
+// first 0 byte, so a string holding one could not come back whole.
+fn check_string(v: &str, a: Aspect) -> Result<(), LexDataError> {
+    if try_aspect_storage(a) != Some(StorageType::String) {
+        return Err(LexDataError::UnexpectedAspect(format!(
             "The aspect {a:?} did not match String value type"
-        )))
+        )));
+    }
+    if v.contains('\0') {
+        return Err(LexDataError::BadLexicalForm(format!(
+            "{v:?} contains a 0 byte, which would end the string"
+        )));
+    }
+    let is_ncname_aspect = matches!(
+        a,
+        Aspect::NCName | Aspect::ID | Aspect::IdRef | Aspect::Entity
+    );
+    if is_ncname_aspect && !is_ncname(v) {
+        return Err(LexDataError::BadName(format!("{v} is not a valid {a:?}")));
+    }
+    Ok(())
+}
+
+fn string_to_storage(v: String, a: Aspect) -> Result<Vec<u8>, LexDataError> {
+    check_string(&v, a)?;
+    let string_bytes: &[u8] = &if a == Aspect::QName {
+        qname_to_bytes(&v)?
+    } else {
+        v.into_bytes()
+    };
+    let mut result = Vec::with_capacity(string_bytes.len() + 1);
+    result.push(aspect_byte(a));
+    result.extend(string_bytes);
+    Ok(result)
+}
+
+// Encode at most `max_bytes` of a string, cut back to a char boundary,
+// for bounded prefix indexes. This is lossy: the key decodes to the
+// truncated string. A truncated key sorts after every string below
+// the original that does not share the kept prefix, and at or before
+// the original itself. The truncated string must itself be valid for
+// the aspect.
+pub fn encode_string_truncated(
+    s: &str,
+    max_bytes: usize,
+    a: Aspect,
+) -> Result<Vec<u8>, LexDataError> {
+    let end = (0..=max_bytes.min(s.len()))
+        .rev()
+        .find(|i| s.is_char_boundary(*i))
+        .unwrap_or(0);
+    string_to_storage(s[..end].to_string(), a)
+}
+
+// Encode bytes that should be UTF-8 but may not be, such as text that
+// came from ill-formed UTF-16. This is lossy: every invalid sequence is
+// replaced by U+FFFD, so distinct inputs can share a key and the
+// original bytes cannot be recovered.
+pub fn string_to_storage_lossy(input: &[u8], a: Aspect) -> Result<Vec<u8>, LexDataError> {
+    string_to_storage(String::from_utf8_lossy(input).into_owned(), a)
+}
+
+// Encode a string with its characters reversed, for suffix indexes:
+// keys order by the reversed string, so a prefix scan over them finds
+// the strings ending in a given suffix. Reversal is by char, keeping
+// the bytes valid UTF-8. The string is checked for the aspect before
+// it is reversed. QNames are refused, as their keys are not plain text.
+pub fn encode_string_reversed(s: &str, a: Aspect) -> Result<Vec<u8>, LexDataError> {
+    check_string(s, a)?;
+    if a == Aspect::QName {
+        return Err(LexDataError::UnexpectedAspect(
+            "QName keys cannot be reversed".to_string(),
+        ));
+    }
+    let mut buf = Vec::with_capacity(s.len() + 1);
+    buf.push(aspect_byte(a));
+    buf.extend(s.chars().rev().collect::<String>().into_bytes());
+    Ok(buf)
+}
+
+pub fn decode_string_reversed(bytes: &[u8]) -> Result<(Value, Aspect), LexDataError> {
+    match decode_exact(bytes)? {
+        (Value::String(s), a) if aspect_storage(a) == StorageType::String => {
+            Ok((Value::String(s.chars().rev().collect()), a))
+        }
+        (_, a) => Err(LexDataError::UnexpectedAspect(format!(
+            "The aspect {a:?} is not a string aspect"
+        ))),
+    }
+}
+
+// Encode a string with its length in bytes ahead of it, under the
+// PrefixedString tag, so that it can be followed by further records in
+// an untrusted buffer and may itself contain 0 bytes. Keys of this form
+// order by length before content; see record_len.
+pub fn encode_string_length_prefixed(s: &str) -> Vec<u8> {
+    let size = encode_size(s.len() as u64);
+    let mut buf = Vec::with_capacity(1 + size.len() + s.len());
+    buf.push(aspect_byte(Aspect::PrefixedString));
+    buf.extend(size);
+    buf.extend(s.as_bytes());
+    buf
+}
+
+pub fn decode_string_length_prefixed(bytes: &[u8]) -> Result<Value, LexDataError> {
+    match decode_exact(bytes)? {
+        (v, Aspect::PrefixedString) => Ok(v),
+        (_, a) => Err(LexDataError::UnexpectedAspect(format!(
+            "The record is {a:?} rather than a length prefixed string"
+        ))),
+    }
+}
+
+// A length prefixed string after its tag, as a slice of the buffer
+fn prefixed_string(bytes: &[u8]) -> Result<&str, LexDataError> {
+    let (size, idx) = decode_size(bytes)?;
+    let text = match usize::try_from(size)
+        .ok()
+        .and_then(|size| bytes.get(idx..idx.checked_add(size)?))
+    {
+        Some(text) => text,
+        None => {
+            return Err(LexDataError::TruncatedInput(format!(
+                "The string needs {size} bytes but only {} remain",
+                bytes.len() - idx
+            )))
+        }
+    };
+    from_utf8(text)
+        .map_err(|e| LexDataError::BadLexicalForm(format!("The string is not valid UTF-8: {e}")))
+}
+
+// The XSD whiteSpace facet: normalizedString replaces tabs and line
+// breaks with spaces, and token and the types derived from it also
+// collapse runs of spaces and trim them. None for the other aspects.
+pub fn normalize_whitespace(s: &str, a: Aspect) -> Option<String> {
+    let replaced = || s.replace(['\t', '\n', '\r'], " ");
+    match a {
+        Aspect::NormalizedString => Some(replaced()),
+        Aspect::Token
+        | Aspect::Language
+        | Aspect::NmToken
+        | Aspect::Name
+        | Aspect::NCName
+        | Aspect::ID
+        | Aspect::IdRef
+        | Aspect::Entity => Some(
+            replaced()
+                .split(' ')
+                .filter(|w| !w.is_empty())
+                .collect::<Vec<_>>()
+                .join(" "),
+        ),
+        _ => None,
+    }
+}
+
+// Keys of whitespace faceted strings that order by the normalized
+// form but keep the input as given, as a u32 length and the text after
+// the normalized field.
+pub fn encode_whitespace_preserving(s: &str, a: Aspect) -> Result<Vec<u8>, LexDataError> {
+    let normalized = match normalize_whitespace(s, a) {
+        Some(normalized) => normalized,
+        None => {
+            return Err(LexDataError::UnexpectedAspect(format!(
+                "The aspect {a:?} has no whitespace facet"
+            )))
+        }
+    };
+    let mut buf = field_to_storage(Value::String(normalized), a)?;
+    push_original(&mut buf, s)?;
+    Ok(buf)
+}
+
+// Decode to the text as originally given
+pub fn decode_whitespace_preserving(bytes: &[u8]) -> Result<(Value, Aspect), LexDataError> {
+    let (_, a, size) = decode_one(bytes)?;
+    if normalize_whitespace("", a).is_none() {
+        return Err(LexDataError::UnexpectedAspect(format!(
+            "The aspect {a:?} has no whitespace facet"
+        )));
+    }
+    let original = original_after(bytes, size)?;
+    Ok((Value::String(original.to_string()), a))
+}
+
+// Keys of anyURIs that order by their parsed components, scheme, host,
+// path and query, each ended by a 0 byte, rather than by the raw text.
+// The URI as written follows as a u32 length and the text, as for
+// encode_with_original, and is what the key decodes to.
+#[cfg(feature = "url")]
+pub fn encode_uri_ordered(s: &str) -> Result<Vec<u8>, LexDataError> {
+    let uri = url::Url::parse(s)
+        .map_err(|e| LexDataError::BadLexicalForm(format!("{s} is not a valid URI: {e}")))?;
+    let components = [
+        uri.scheme(),
+        uri.host_str().unwrap_or_default(),
+        uri.path(),
+        uri.query().unwrap_or_default(),
+    ];
+    let mut buf = vec![aspect_byte(Aspect::OrderedURI)];
+    for component in components {
+        buf.extend(component.as_bytes());
+        buf.push(0);
+    }
+    push_original(&mut buf, s)?;
+    Ok(buf)
+}
+
+pub fn decode_uri_ordered(bytes: &[u8]) -> Result<Value, LexDataError> {
+    match decode_exact(bytes)? {
+        (v, Aspect::OrderedURI) => Ok(v),
+        (_, a) => Err(LexDataError::UnexpectedAspect(format!(
+            "The record is {a:?} rather than an ordered URI"
+        ))),
+    }
+}
+
+// The URI as written after the tag of an ordered URI key, and the
+// length of the key without its tag.
+fn ordered_uri(bytes: &[u8]) -> Result<(&str, usize), LexDataError> {
+    let mut size = 0;
+    for _ in 0..4 {
+        size += match bytes[size..].iter().position(|b| *b == 0) {
+            Some(p) => p + 1,
+            None => {
+                return Err(LexDataError::TruncatedInput(
+                    "The URI components are not terminated".to_string(),
+                ))
+            }
+        };
+    }
+    original_at(bytes, size)
+}
+
+// XML names without a colon. Start with a letter or underscore, then
+// letters, digits, '.', '-' or '_'.
+pub fn is_ncname(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) if c.is_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_alphanumeric() || c == '.' || c == '-' || c == '_')
+        }
+        _ => false,
+    }
+}
+
+// A QName is stored as its prefix, a 0 byte, then its local part, so
+// names sort by prefix first. A bare local name has an empty prefix.
+fn qname_to_bytes(qname: &str) -> Result<Vec<u8>, LexDataError> {
+    let (prefix, local) = qname.split_once(':').unwrap_or(("", qname));
+    if (qname.contains(':') && !is_ncname(prefix)) || !is_ncname(local) {
+        return Err(LexDataError::BadName(format!(
+            "{qname} is not a valid QName"
+        )));
+    }
+    let mut buf = Vec::with_capacity(qname.len() + 1);
+    buf.extend(prefix.as_bytes());
+    buf.push(0);
+    buf.extend(local.as_bytes());
+    Ok(buf)
+}
+
+fn qname_from_bytes(bytes: &[u8]) -> Result<Value, LexDataError> {
+    let split = string_length(bytes);
+    if split == bytes.len() {
+        return Err(LexDataError::BadName(
+            "QName is missing its prefix separator".to_string(),
+        ));
+    }
+    let rest = &bytes[split + 1..];
+    let local = &rest[..string_length(rest)];
+    let (prefix, local) = match (from_utf8(&bytes[..split]), from_utf8(local)) {
+        (Ok(prefix), Ok(local)) => (prefix, local),
+        _ => return Err(LexDataError::BadName("QName is not valid utf8".to_string())),
+    };
+    if prefix.is_empty() {
+        Ok(Value::String(local.to_string()))
+    } else {
+        Ok(Value::String(format!("{prefix}:{local}")))
     }
 }
 
 fn date_time_to_storage(s: String, a: Aspect) -> Result<Vec<u8>, LexDataError> {
-    match DateTime::parse_from_rfc3339(&s) {
-        Ok(date_time) => {
-            let timestamp = date_time.timestamp();
-            int64_to_storage(timestamp, a)
+    let timestamp = parse_date_time(&s)?.timestamp();
+    int64_to_storage(timestamp, a)
+}
+
+// RFC 3339 with the XSD end of day: 24:00:00 is midnight of the next
+// day. A leap second (60) is rejected, since the instants are stored
+// as Unix seconds which have no room for it and clamping would make
+// two distinct lexical times collide.
+fn parse_date_time(s: &str) -> Result<DateTime<FixedOffset>, LexDataError> {
+    let bad = |e: chrono::ParseError| LexDataError::BadDateFormat(format!("{s}: {e}"));
+    let end_of_day = s
+        .find('T')
+        .map(|t| t + 1)
+        .filter(|t| s[*t..].starts_with("24:00:00"));
+    let date_time = match end_of_day {
+        Some(t) => {
+            // only a zero fraction is allowed after 24:00:00
+            let rest = &s[t + 8..];
+            let rest = rest
+                .strip_prefix('.')
+                .map_or(rest, |f| f.trim_start_matches('0'));
+            let midnight = format!("{}00:00:00{rest}", &s[..t]);
+            let date_time = DateTime::parse_from_rfc3339(&midnight).map_err(bad)?;
+            match date_time.checked_add_signed(chrono::Duration::days(1)) {
+                Some(date_time) => date_time,
+                None => return Err(LexDataError::BadDateFormat(format!("{s} is out of range"))),
+            }
+        }
+        None => DateTime::parse_from_rfc3339(s).map_err(bad)?,
+    };
+    if date_time.nanosecond() >= 1_000_000_000 {
+        return Err(LexDataError::BadDateFormat(format!(
+            "{s} is a leap second, which cannot be stored"
+        )));
+    }
+    Ok(date_time)
+}
+
+// Date time stamps keep everything a DateTime<FixedOffset> holds: the
+// instant as seconds since 1970 and nanoseconds, then the offset in
+// seconds, each sign flipped so that keys sort by instant and then by
+// offset. Leap seconds are rejected as for date times.
+pub fn chrono_to_storage(dt: &DateTime<FixedOffset>, a: Aspect) -> Result<Vec<u8>, LexDataError> {
+    if try_aspect_storage(a) != Some(StorageType::DateTimeStamp) {
+        return Err(LexDataError::UnexpectedAspect(format!(
+            "The aspect {a:?} did not match DateTimeStamp value type"
+        )));
+    }
+    let nanos = dt.timestamp_subsec_nanos();
+    if nanos >= 1_000_000_000 {
+        return Err(LexDataError::BadDateFormat(format!(
+            "{dt} is a leap second, which cannot be stored"
+        )));
+    }
+    let mut buf = Vec::with_capacity(17);
+    buf.push(aspect_byte(a));
+    buf.write_i64::<BigEndian>(dt.timestamp()).unwrap();
+    buf[1] ^= BYTE_SIGN_MASK;
+    buf.write_u32::<BigEndian>(nanos).unwrap();
+    buf.write_i32::<BigEndian>(dt.offset().local_minus_utc())
+        .unwrap();
+    buf[13] ^= BYTE_SIGN_MASK;
+    Ok(buf)
+}
+
+// Decode a date time key to chrono. Date time stamps come back exactly
+// as stored; plain date times hold whole seconds and come back in UTC.
+pub fn storage_to_chrono(bytes: &[u8]) -> Result<DateTime<FixedOffset>, LexDataError> {
+    let a = match bytes.first() {
+        Some(b) => byte_aspect(b),
+        None => {
+            return Err(LexDataError::TruncatedInput(
+                "An empty buffer holds no value".to_string(),
+            ))
+        }
+    };
+    let out_of_range = || LexDataError::BadDateFormat("The instant is out of range".to_string());
+    match try_aspect_storage(a) {
+        Some(StorageType::DateTime) => {
+            if bytes.len() < 9 {
+                return Err(LexDataError::TruncatedInput(format!(
+                    "DateTime values need 8 bytes after the aspect but only {} were given",
+                    bytes.len() - 1
+                )));
+            }
+            let secs = storage_to_timestamp(&bytes[1..])?;
+            match DateTime::from_timestamp(secs, 0) {
+                Some(dt) => Ok(dt.fixed_offset()),
+                None => Err(out_of_range()),
+            }
+        }
+        Some(StorageType::DateTimeStamp) => {
+            let payload = fixed_width_payload(&bytes[1..], 16)?;
+            let mut rdr = Cursor::new(payload);
+            let secs = rdr.read_i64::<BigEndian>().unwrap() ^ i64::MIN;
+            let nanos = rdr.read_u32::<BigEndian>().unwrap();
+            let offset = rdr.read_i32::<BigEndian>().unwrap() ^ i32::MIN;
+            let offset = FixedOffset::east_opt(offset).ok_or_else(|| {
+                LexDataError::BadDateFormat(format!("{offset} seconds is not a valid offset"))
+            })?;
+            match DateTime::from_timestamp(secs, nanos) {
+                Some(dt) if nanos < 1_000_000_000 => Ok(dt.with_timezone(&offset)),
+                _ => Err(out_of_range()),
+            }
+        }
+        _ => Err(LexDataError::UnexpectedAspect(format!(
+            "The aspect {a:?} is not a date time"
+        ))),
+    }
+}
+
+// A chrono date time under either date time aspect. A plain DateTime
+// keeps whole seconds, as it does for the lexical form.
+fn date_time_value_to_storage(
+    dt: &DateTime<FixedOffset>,
+    a: Aspect,
+) -> Result<Vec<u8>, LexDataError> {
+    match try_aspect_storage(a) {
+        Some(StorageType::DateTime) if dt.timestamp_subsec_nanos() >= 1_000_000_000 => Err(
+            LexDataError::BadDateFormat(format!("{dt} is a leap second, which cannot be stored")),
+        ),
+        Some(StorageType::DateTime) => int64_to_storage(dt.timestamp(), a),
+        _ => chrono_to_storage(dt, a),
+    }
+}
+
+// A date time as the typed Value::DateTime, read with the same rules
+// as the lexical form stored by value_to_storage.
+pub fn date_time_value(s: &str) -> Result<Value, LexDataError> {
+    parse_date_time(s).map(Value::DateTime)
+}
+
+// Decode a date time key as Value::DateTime, as storage_to_value does,
+// rejecting keys of any other aspect.
+pub fn decode_date_time(bytes: &[u8]) -> Result<(Value, Aspect), LexDataError> {
+    let dt = storage_to_chrono(bytes)?;
+    let size = storage_size_checked(bytes)?;
+    if size < bytes.len() {
+        return Err(LexDataError::TrailingBytes(format!(
+            "The date time takes {size} bytes but the buffer has {}",
+            bytes.len()
+        )));
+    }
+    Ok((Value::DateTime(dt), byte_aspect(&bytes[0])))
+}
+
+// Encode seconds since 1970 as a date time, for callers that already
+// hold an instant rather than its lexical form.
+pub fn timestamp_to_storage(secs: i64, a: Aspect) -> Result<Vec<u8>, LexDataError> {
+    if try_aspect_storage(a) != Some(StorageType::DateTime) {
+        return Err(LexDataError::UnexpectedAspect(format!(
+            "The aspect {a:?} did not match DateTime value type"
+        )));
+    }
+    if DateTime::from_timestamp(secs, 0).is_none() {
+        return Err(LexDataError::BadDateFormat(format!(
+            "The timestamp {secs} is out of range"
+        )));
+    }
+    int64_to_storage(secs, a)
+}
+
+// Stores the input exactly as given after the instant, as for
+// encode_with_original, so that keys still sort by instant but decode
+// to the original spelling.
+pub fn date_time_to_storage_verbatim(s: String, a: Aspect) -> Result<Vec<u8>, LexDataError> {
+    let mut buf = date_time_to_storage(s.clone(), a)?;
+    push_original(&mut buf, &s)?;
+    Ok(buf)
+}
+
+pub fn storage_to_date_time_verbatim(bytes: &[u8]) -> Result<Value, LexDataError> {
+    let (_, a, size) = decode_one(bytes)?;
+    if aspect_storage(a) != StorageType::DateTime {
+        return Err(LexDataError::UnexpectedAspect(format!(
+            "The aspect {a:?} is not a date time"
+        )));
+    }
+    let original = original_after(bytes, size)?;
+    Ok(Value::String(original.to_string()))
+}
+
+// Decode a date time with a chrono format string, in UTC.
+pub fn storage_to_date_time_fmt(bytes: &[u8], fmt: &str) -> Result<Value, LexDataError> {
+    let i = storage_to_timestamp(bytes)?;
+    let dt = match DateTime::from_timestamp(i, 0) {
+        Some(dt) => dt,
+        None => {
+            return Err(LexDataError::BadDateFormat(format!(
+                "The timestamp {i} is out of range"
+            )))
         }
-        Err(parse_error) => Err(LexDataError::BadDateFormat(parse_error.to_string())),
+    };
+    let mut formatted = String::new();
+    match write!(formatted, "{}", dt.format(fmt)) {
+        Ok(()) => Ok(Value::String(formatted)),
+        Err(_) => Err(LexDataError::BadDateFormat(format!(
+            "{fmt} is not a valid date time format"
+        ))),
+    }
+}
+
+// Date times stored as signed seconds from a chosen epoch through the
+// bigint codec, so that data clustered far from 1970 stays small. The
+// key is an integer of a BigInt aspect such as Integer, and decodes as
+// that offset unless read back with storage_to_datetime_epoch.
+pub fn datetime_to_storage_epoch(
+    s: &str,
+    epoch: DateTime<FixedOffset>,
+    a: Aspect,
+) -> Result<Vec<u8>, LexDataError> {
+    if try_aspect_storage(a) != Some(StorageType::BigInt) {
+        return Err(LexDataError::UnexpectedAspect(format!(
+            "Date times from an epoch are stored as BigInt, which {a:?} is not"
+        )));
     }
+    let offset = parse_date_time(s)?.timestamp() - epoch.timestamp();
+    bigint_to_storage(Integer::from(offset), a)
 }
 
-fn storage_to_date_time(bytes: &[u8]) -> Result<Value, LexDataError> {
-    match storage_to_int64(bytes) {
-        Ok(Value::Int64(i)) => {
-            let dt = NaiveDateTime::from_timestamp(i, 0);
-            Ok(Value::String(dt.format("%Y-%m-%dT%H:%M:%SZ").to_string()))
+// Decode a key written by datetime_to_storage_epoch with the same epoch
+pub fn storage_to_datetime_epoch(
+    bytes: &[u8],
+    epoch: DateTime<FixedOffset>,
+) -> Result<Value, LexDataError> {
+    let offset = match decode_exact(bytes)? {
+        (Value::BigInt(offset), _) => offset,
+        (_, a) => {
+            return Err(LexDataError::UnexpectedAspect(format!(
+                "Date times from an epoch are stored as BigInt, not {a:?}"
+            )))
         }
-        Ok(_) => panic!("Imposible return value from storage_to_int64"),
-        Err(err) => Err(err),
+    };
+    let timestamp = offset
+        .to_i64()
+        .and_then(|offset| epoch.timestamp().checked_add(offset));
+    match timestamp.and_then(|t| DateTime::from_timestamp(t, 0)) {
+        Some(dt) => Ok(Value::String(dt.format("%Y-%m-%dT%H:%M:%SZ").to_string())),
+        None => Err(LexDataError::BadDateFormat(format!(
+            "{offset} seconds from {epoch} is out of range"
+        ))),
+    }
+}
+
+// The seconds since the epoch of a stored date time, for arithmetic
+// on instants without going through the string form.
+pub fn storage_to_timestamp(bytes: &[u8]) -> Result<i64, LexDataError> {
+    match storage_to_int64(fixed_width_payload(bytes, 8)?)? {
+        Value::Int64(i) => Ok(i),
+        _ => panic!("Imposible return value from storage_to_int64"),
     }
 }
 
-const BYTE_SIGN_MASK: u8 = 0b1000_0000;
+/// Fixed width integers are stored big endian with the sign bit of the
+/// most significant byte flipped, so negatives sort below positives.
+///
+/// ```
+/// use lexdata::{value_to_storage, Aspect, Value, BYTE_SIGN_MASK};
+///
+/// let i: i32 = -2;
+/// let mut expected = i.to_be_bytes();
+/// expected[0] ^= BYTE_SIGN_MASK;
+/// assert_eq!(expected, [0x7f, 0xff, 0xff, 0xfe]);
+///
+/// let stored = value_to_storage(Value::Int32(i), Aspect::Int).unwrap();
+/// assert_eq!(stored[1..], expected);
+/// ```
+pub const BYTE_SIGN_MASK: u8 = 0b1000_0000;
 fn int32_to_storage(i: i32, a: Aspect) -> Result<Vec<u8>, LexDataError> {
     let storage_type = aspect_storage(a);
     if storage_type == StorageType::Int32 {
@@ -249,16 +1363,15 @@ fn int32_to_storage(i: i32, a: Aspect) -> Result<Vec<u8>, LexDataError> {
 }
 
 fn storage_to_int32(bytes: &[u8]) -> Result<Value, LexDataError> {
-    let mut vec = bytes.to_vec();
-    vec[0] ^= BYTE_SIGN_MASK;
-    let mut rdr = Cursor::new(vec);
-    let i_result = rdr.read_i32::<BigEndian>();
-    if let Ok(i) = i_result {
-        Ok(Value::Int32(i))
-    } else {
-        Err(LexDataError::BadInt32Layout(
-            "Unable to read bytes of float from storage!".to_string(),
-        ))
+    read_int32(bytes).map(Value::Int32)
+}
+
+fn read_int32(bytes: &[u8]) -> Result<i32, LexDataError> {
+    match bytes.get(..4).and_then(|b| <[u8; 4]>::try_from(b).ok()) {
+        Some(b) => Ok(i32::from_be_bytes(b) ^ i32::MIN),
+        None => Err(LexDataError::BadInt32Layout(
+            "Unable to read bytes of int from storage!".to_string(),
+        )),
     }
 }
 
@@ -278,47 +1391,217 @@ fn int64_to_storage(i: i64, a: Aspect) -> Result<Vec<u8>, LexDataError> {
     }
 }
 
-fn storage_to_int64(bytes: &[u8]) -> Result<Value, LexDataError> {
-    let mut vec = bytes.to_vec();
-    vec[0] ^= BYTE_SIGN_MASK;
-    let mut rdr = Cursor::new(vec);
-    let i_result = rdr.read_i64::<BigEndian>();
-    if let Ok(i) = i_result {
-        Ok(Value::Int64(i))
-    } else {
-        Err(LexDataError::BadInt64Layout(
-            "Unable to read bytes of float from storage!".to_string(),
-        ))
+// Rewrite an Int32 key as an Int64 key under Aspect::Long, by sign
+// extending the stored bytes directly.
+pub fn widen_int32_to_int64(bytes: &[u8]) -> Result<Vec<u8>, LexDataError> {
+    if bytes.len() < 5 {
+        return Err(LexDataError::TruncatedInput(format!(
+            "Int32 values need 5 bytes but only {} were given",
+            bytes.len()
+        )));
+    }
+    let a = byte_aspect(&bytes[0]);
+    if aspect_storage(a) != StorageType::Int32 {
+        return Err(LexDataError::UnexpectedAspect(format!(
+            "The aspect {a:?} did not match Int32 value type"
+        )));
     }
+    let high = bytes[1] ^ BYTE_SIGN_MASK;
+    let fill = if high & BYTE_SIGN_MASK == 0 {
+        0x00
+    } else {
+        0xff
+    };
+    let mut wtr = Vec::with_capacity(9);
+    wtr.push(aspect_byte(Aspect::Long));
+    wtr.extend([fill ^ BYTE_SIGN_MASK, fill, fill, fill, high]);
+    wtr.extend(&bytes[2..5]);
+    Ok(wtr)
 }
 
-const TERMINAL: u8 = 0;
-const FIRST_SIGN: u8 = 0b1000_0000u8;
-const FIRST_TERMINAL: u8 = 0b0000_0000u8;
-const CONTINUATION: u8 = 0b1000_0000u8;
-const FIRST_CONTINUATION: u8 = 0b0100_0000u8;
-const BASE_MASK: u8 = !CONTINUATION;
-const FIRST_MASK: u8 = !(FIRST_SIGN | FIRST_CONTINUATION);
-const FIRST_MAX: u8 = FIRST_CONTINUATION;
-const NEGATIVE_ZERO: u8 = 0b0111_1111;
-// Leave in reverse order for the convenience of the caller
-fn size_encode(size: u32) -> Vec<u8> {
+// Store a bigint known to be bounded in the compact Int64 layout
+pub fn bigint_to_int64_storage(i: &Integer, a: Aspect) -> Result<Vec<u8>, LexDataError> {
+    if try_aspect_storage(a) != Some(StorageType::Int64) {
+        return Err(LexDataError::UnexpectedAspect(format!(
+            "The aspect {a:?} did not match Int64 value type"
+        )));
+    }
+    match i.to_i64() {
+        Some(i) => int64_to_storage(i, a),
+        None => Err(LexDataError::OutOfRange(format!(
+            "{i} does not fit in {a:?}"
+        ))),
+    }
+}
+
+fn storage_to_int64(bytes: &[u8]) -> Result<Value, LexDataError> {
+    read_int64(bytes).map(Value::Int64)
+}
+
+fn read_int64(bytes: &[u8]) -> Result<i64, LexDataError> {
+    match bytes.get(..8).and_then(|b| <[u8; 8]>::try_from(b).ok()) {
+        Some(b) => Ok(i64::from_be_bytes(b) ^ i64::MIN),
+        None => Err(LexDataError::BadInt64Layout(
+            "Unable to read bytes of int from storage!".to_string(),
+        )),
+    }
+}
+
+// Unsigned ints have no negatives to order, so they are plain big
+// endian. They are held in an Int64 value, which covers their range.
+fn uint32_to_storage(i: i64, a: Aspect) -> Result<Vec<u8>, LexDataError> {
+    if aspect_storage(a) != StorageType::UInt32 {
+        return Err(LexDataError::UnexpectedAspect(format!(
+            "The aspect {a:?} did not match UInt32 value type"
+        )));
+    }
+    let i = u32::try_from(i)
+        .map_err(|_| LexDataError::OutOfRange(format!("{i} is out of range for {a:?}")))?;
+    let mut wtr = Vec::with_capacity(5);
+    wtr.push(aspect_byte(a));
+    wtr.write_u32::<BigEndian>(i).unwrap();
+    Ok(wtr)
+}
+
+fn storage_to_uint32(bytes: &[u8]) -> Result<Value, LexDataError> {
+    read_uint32(bytes).map(|i| Value::Int64(i.into()))
+}
+
+fn read_uint32(bytes: &[u8]) -> Result<u32, LexDataError> {
+    match bytes.get(..4).and_then(|b| <[u8; 4]>::try_from(b).ok()) {
+        Some(b) => Ok(u32::from_be_bytes(b)),
+        None => Err(LexDataError::BadInt32Layout(
+            "Unable to read bytes of unsigned int from storage!".to_string(),
+        )),
+    }
+}
+
+// Integers in a caller chosen width of 1 to 8 bytes under the SizedInt
+// tag, as the width and then the bytes laid out like Int32 and Int64:
+// big endian with the sign bit flipped. Keys of one width sort
+// numerically, and narrower keys sort before wider ones. A bare Int64
+// value is written 8 bytes wide.
+pub fn int_to_storage_width(i: i64, width: usize) -> Result<Vec<u8>, LexDataError> {
+    check_int_width(width)?;
+    let bits = 8 * width as u32;
+    if width < 8 && (i < -(1 << (bits - 1)) || i >= 1 << (bits - 1)) {
+        return Err(LexDataError::OutOfRange(format!(
+            "{i} does not fit in {width} bytes"
+        )));
+    }
+    let mut wtr = Vec::with_capacity(2 + width);
+    wtr.push(aspect_byte(Aspect::SizedInt));
+    wtr.push(width as u8);
+    wtr.extend(&i.to_be_bytes()[8 - width..]);
+    wtr[2] ^= BYTE_SIGN_MASK;
+    Ok(wtr)
+}
+
+pub fn storage_to_int_width(bytes: &[u8]) -> Result<i64, LexDataError> {
+    match bytes.first() {
+        Some(first) if *first == aspect_byte(Aspect::SizedInt) => read_sized_int(&bytes[1..]),
+        Some(first) => Err(LexDataError::UnexpectedAspect(format!(
+            "The byte {first} is not the SizedInt tag"
+        ))),
+        None => Err(LexDataError::TruncatedInput(
+            "An empty buffer holds no value".to_string(),
+        )),
+    }
+}
+
+// A sized integer after its tag: the width, then the bytes
+fn read_sized_int(bytes: &[u8]) -> Result<i64, LexDataError> {
+    let width = match bytes.first() {
+        Some(width) => *width as usize,
+        None => {
+            return Err(LexDataError::TruncatedInput(
+                "The integer has no width".to_string(),
+            ))
+        }
+    };
+    check_int_width(width)?;
+    let payload = fixed_width_payload(&bytes[1..], width)?;
+    let high = payload[0] ^ BYTE_SIGN_MASK;
+    // sign extend from the high byte
+    let fill = if high & BYTE_SIGN_MASK == 0 {
+        0x00
+    } else {
+        0xff
+    };
+    let mut be = [fill; 8];
+    be[8 - width] = high;
+    be[9 - width..].copy_from_slice(&payload[1..]);
+    Ok(i64::from_be_bytes(be))
+}
+
+fn check_int_width(width: usize) -> Result<(), LexDataError> {
+    if (1..=8).contains(&width) {
+        Ok(())
+    } else {
+        Err(LexDataError::OutOfRange(format!(
+            "{width} is not an integer width between 1 and 8 bytes"
+        )))
+    }
+}
+
+// Bigints are prefixed by their byte count, written as a chain of
+// bytes. The first byte is 1cxxxxxx and each following byte cxxxxxxx,
+// where c marks that another byte follows. For negative numbers the
+// whole prefix and magnitude are complemented, so the sign bit of the
+// first byte is 0 and longer negatives sort first.
+//
+// A chain of two bytes holds sizes below 8192, and a longer chain
+// would start no higher than it, so larger sizes take a long form
+// instead: LONG_SIZE, then 1 followed by the number of bytes of the
+// size in 7 bits, then the size big endian. The second byte of a
+// chain starting at LONG_SIZE is always below 0x80, so long sizes sort
+// above every chain and, having at least as many bytes as any smaller
+// long size, above each other.
+
+/// Chain bit clear on a byte after the first: the size ends here.
+pub const TERMINAL: u8 = 0;
+/// Sign bit of the first size byte, set for non-negative numbers.
+pub const FIRST_SIGN: u8 = 0b1000_0000u8;
+/// Chain bit clear on the first size byte.
+pub const FIRST_TERMINAL: u8 = 0b0000_0000u8;
+/// Chain bit on bytes after the first: another size byte follows.
+pub const CONTINUATION: u8 = 0b1000_0000u8;
+/// Chain bit on the first size byte: another size byte follows.
+pub const FIRST_CONTINUATION: u8 = 0b0100_0000u8;
+/// Size bits of bytes after the first.
+pub const BASE_MASK: u8 = !CONTINUATION;
+/// Size bits of the first size byte.
+pub const FIRST_MASK: u8 = !(FIRST_SIGN | FIRST_CONTINUATION);
+const FIRST_MAX: u8 = FIRST_CONTINUATION;
+const LONG_SIZE: u8 = FIRST_SIGN | FIRST_CONTINUATION | FIRST_MASK;
+const LONG_SIZE_MIN: u64 = 1 << 13;
+/// Size byte for a negative number with a zero integer part, as in -0.5.
+pub const NEGATIVE_ZERO: u8 = 0b0111_1111;
+// Leave in reverse order for the convenience of the caller
+fn size_encode(size: u64) -> Vec<u8> {
     if size == 0 {
-        return vec![NEGATIVE_ZERO]; // just the positive sign bit (allows negative zero)
+        return vec![FIRST_SIGN]; // just the positive sign bit (NEGATIVE_ZERO is its complement)
+    }
+    if size >= LONG_SIZE_MIN {
+        let width = 8 - size.leading_zeros() as usize / 8;
+        let mut v: Vec<u8> = size.to_le_bytes()[..width].to_vec();
+        v.push(CONTINUATION | width as u8);
+        v.push(LONG_SIZE);
+        return v;
     }
     let mut remainder = size;
     let mut v = vec![];
     let mut last = true;
     while remainder > 0 {
-        if remainder >= CONTINUATION as u32 {
+        if remainder >= CONTINUATION as u64 {
             let continued = if last { TERMINAL } else { CONTINUATION };
-            let byte = continued | ((remainder & BASE_MASK as u32) as u8);
+            let byte = continued | ((remainder & BASE_MASK as u64) as u8);
             v.push(byte);
-        } else if remainder >= FIRST_MAX as u32 {
+        } else if remainder >= FIRST_MAX as u64 {
             // special case where we fit in 7 bits but not 6
             // and we need a zero padded initial byte.
             let continued = if last { TERMINAL } else { CONTINUATION };
-            let byte = continued | ((remainder & BASE_MASK as u32) as u8);
+            let byte = continued | ((remainder & BASE_MASK as u64) as u8);
             v.push(byte);
             let byte = FIRST_SIGN | FIRST_CONTINUATION;
             v.push(byte)
@@ -328,7 +1611,7 @@ fn size_encode(size: u32) -> Vec<u8> {
             } else {
                 FIRST_CONTINUATION
             };
-            let byte = FIRST_SIGN | continued | ((remainder & FIRST_MASK as u32) as u8);
+            let byte = FIRST_SIGN | continued | ((remainder & FIRST_MASK as u64) as u8);
             v.push(byte)
         }
         remainder >>= 7;
@@ -337,15 +1620,21 @@ fn size_encode(size: u32) -> Vec<u8> {
     v
 }
 
-fn size_decode(v: &[u8]) -> (bool, u32, usize) {
-    let mut size: u32 = 0;
-    let mut sign = true;
+fn size_decode(v: &[u8]) -> (bool, u64, usize) {
+    let sign = v.first().is_none_or(|b| b & FIRST_SIGN != 0);
+    let byte = |i: usize| if sign { v[i] } else { !v[i] };
+    if v.len() > 1 && byte(0) == LONG_SIZE && byte(1) & CONTINUATION != 0 {
+        // a short buffer gives the bytes it has, as a chain does
+        let end = v.len().min(2 + (byte(1) & BASE_MASK) as usize);
+        let size = (2..end).fold(0_u64, |size, i| size << 8 | u64::from(byte(i)));
+        return (sign, size, end);
+    }
+    let mut size: u64 = 0;
     for (i, elt) in v.iter().enumerate() {
         let vi = *elt as u8;
         if i == 0 {
-            sign = vi & FIRST_SIGN != 0;
             let vi = if sign { vi } else { !vi };
-            let val = (vi & FIRST_MASK) as u32;
+            let val = (vi & FIRST_MASK) as u64;
             if vi & FIRST_CONTINUATION == 0 {
                 return (sign, val, i + 1);
             } else {
@@ -353,7 +1642,7 @@ fn size_decode(v: &[u8]) -> (bool, u32, usize) {
             }
         } else {
             let vi = if sign { vi } else { !vi };
-            let val = (vi & BASE_MASK) as u32;
+            let val = (vi & BASE_MASK) as u64;
             if vi & CONTINUATION == 0 {
                 return (sign, size + val, i + 1);
             } else {
@@ -365,12 +1654,47 @@ fn size_decode(v: &[u8]) -> (bool, u32, usize) {
     (sign, size, v.len())
 }
 
+// The length prefix used for large integers, most significant byte
+// first, for callers storing their own lengths. Larger sizes sort
+// higher at every length, so the prefix orders what it is put before.
+pub fn encode_size(n: u64) -> Vec<u8> {
+    let mut bytes = size_encode(n);
+    bytes.reverse();
+    bytes
+}
+
+// The size and the number of bytes it took
+pub fn decode_size(bytes: &[u8]) -> Result<(u64, usize), LexDataError> {
+    let (is_pos, size, idx) = size_decode(bytes);
+    if idx == 0 {
+        return Err(LexDataError::TruncatedInput(
+            "An empty buffer holds no size".to_string(),
+        ));
+    }
+    if !is_pos {
+        return Err(LexDataError::OutOfRange(
+            "The bytes do not hold a u64 size".to_string(),
+        ));
+    }
+    // a cut size never ends as a whole one does
+    if encode_size(size) != bytes[..idx] {
+        return Err(if idx == bytes.len() {
+            LexDataError::TruncatedInput("The size is not terminated".to_string())
+        } else {
+            LexDataError::OutOfRange("The bytes do not hold a u64 size".to_string())
+        });
+    }
+    Ok((size, idx))
+}
+
+// Bytes are taken from the magnitude arithmetically rather than from
+// native limbs, so the layout does not depend on the host.
 fn bigint_to_storage(bigint: Integer, a: Aspect) -> Result<Vec<u8>, LexDataError> {
     let is_neg = bigint < 0;
     let mut int = bigint.abs();
     let size = int.significant_bits() + 1;
     let num_bytes = (size / 8) + u32::from(size % 8 != 0);
-    let size_bytes = size_encode(num_bytes);
+    let size_bytes = size_encode(u64::from(num_bytes));
     let mut number_vec = Vec::with_capacity(size_bytes.len() + num_bytes as usize + 1);
     for _ in 0..num_bytes {
         let byte = int.to_u8_wrapping();
@@ -391,11 +1715,12 @@ fn bigint_to_storage(bigint: Integer, a: Aspect) -> Result<Vec<u8>, LexDataError
 
 fn storage_to_bigint(bytes: &[u8]) -> Result<Value, LexDataError> {
     let (is_pos, size, idx) = size_decode(bytes);
+    check_canonical_bigint(bytes, is_pos, size as usize, idx)?;
     let mut int = Integer::new();
     if size == 0 {
         return Ok(Value::BigInt(int));
     }
-    for (i, b) in bytes[idx..(size + 1) as usize].iter().enumerate() {
+    for (i, b) in bytes[idx..idx + size as usize].iter().enumerate() {
         int += if is_pos { *b } else { !*b };
         if i < size as usize - 1 {
             int <<= 8;
@@ -407,6 +1732,52 @@ fn storage_to_bigint(bytes: &[u8]) -> Result<Value, LexDataError> {
     Ok(Value::BigInt(int))
 }
 
+// Every integer has exactly one encoding, so that equal values have
+// equal bytes. The size must be written as size_encode would write
+// it, and the magnitude must be exactly as wide as bigint_to_storage
+// makes it: its top bit is always clear, and a leading zero byte is
+// only there to provide that bit.
+fn check_canonical_bigint(
+    bytes: &[u8],
+    is_pos: bool,
+    size: usize,
+    idx: usize,
+) -> Result<(), LexDataError> {
+    if bytes.len() < idx + size {
+        return Err(LexDataError::TruncatedInput(format!(
+            "The integer needs {} bytes but only {} were given",
+            idx + size,
+            bytes.len()
+        )));
+    }
+    if size == 0 {
+        // only the negative zero of a decimal's integer part
+        return if bytes[0] == NEGATIVE_ZERO {
+            Ok(())
+        } else {
+            Err(LexDataError::BadBigIntLayout(
+                "An empty integer must be a negative zero".to_string(),
+            ))
+        };
+    }
+    let uncomplement = |b: &u8| if is_pos { *b } else { !*b };
+    let mut size_bytes = size_encode(size as u64);
+    size_bytes.reverse();
+    let magnitude: Vec<u8> = bytes[idx..idx + size].iter().map(uncomplement).collect();
+    let is_padded = magnitude.len() > 1 && magnitude[0] == 0 && magnitude[1] & 0x80 == 0;
+    let is_zero = magnitude.iter().all(|b| *b == 0);
+    if bytes[..idx].iter().map(uncomplement).ne(size_bytes)
+        || magnitude[0] & 0x80 != 0
+        || is_padded
+        || (is_zero && !is_pos)
+    {
+        return Err(LexDataError::BadBigIntLayout(
+            "The integer is not canonically encoded".to_string(),
+        ));
+    }
+    Ok(())
+}
+
 fn encode_fraction(fraction: Option<&str>) -> Vec<u8> {
     if let Some(f) = fraction {
         if f.is_empty() {
@@ -475,14 +1846,37 @@ fn decode_fraction(fraction_vec: &[u8]) -> String {
     }
 }
 
+// The bytes of a stored fraction up to the first without a continuation
+// bit, or one more than there are when every byte continues. Negative
+// fractions are stored complemented.
+fn fraction_size(fraction: &[u8], is_pos: bool) -> usize {
+    match fraction
+        .iter()
+        .position(|b| (if is_pos { *b } else { !*b }) & 1 == 0)
+    {
+        Some(p) => p + 1,
+        None => fraction.len() + 1,
+    }
+}
+
 fn bignum_to_storage(bignum: String, a: Aspect) -> Result<Vec<u8>, LexDataError> {
     let storage_type = aspect_storage(a);
     if storage_type == StorageType::BigNum {
+        // the same forms canonical_decimal reads, such as -.5 and +1
+        canonical_decimal(&bignum)?;
         let mut parts = bignum.split('.');
         let bigint = parts.next().unwrap_or(&bignum);
         let fraction = parts.next();
-        let integer_part = bigint.parse::<Integer>().unwrap();
         let is_neg = bignum.starts_with('-');
+        let digits = bigint.trim_start_matches(['+', '-']);
+        let mut integer_part = if digits.is_empty() {
+            Integer::new()
+        } else {
+            digits.parse::<Integer>().unwrap()
+        };
+        if is_neg {
+            integer_part = -integer_part;
+        }
         let prefix = bigint_to_storage(integer_part.clone(), a)?;
         let mut prefix = if integer_part == 0 && is_neg {
             let aspect_u8 = aspect_byte(a);
@@ -508,12 +1902,53 @@ fn bignum_to_storage(bignum: String, a: Aspect) -> Result<Vec<u8>, LexDataError>
     }
 }
 
+// Decimals with redundant zeros removed before encoding, so equal
+// values share a key. Unlike bignum_to_storage this does not keep the
+// significance of "1.50", which decodes as "1.5".
+pub fn decimal_to_storage_canonical(s: &str, a: Aspect) -> Result<Vec<u8>, LexDataError> {
+    bignum_to_storage(canonical_decimal(s)?, a)
+}
+
+// Rewrite a stored key in canonical form without going through Value,
+// for migrating keys written by the lossless decimal path. Decimals
+// lose their redundant zeros; every other key is already canonical
+// and comes back unchanged.
+pub fn recanonicalize(bytes: &[u8]) -> Result<Vec<u8>, LexDataError> {
+    let a = match bytes.first().and_then(|b| Aspect::from_u8(*b)) {
+        Some(a) if try_aspect_storage(a) == Some(StorageType::BigNum) => a,
+        _ => return Ok(bytes.to_vec()),
+    };
+    match storage_to_bignum(&bytes[1..])? {
+        Value::String(s) => decimal_to_storage_canonical(&s, a),
+        _ => panic!("bignum storage must return a string"),
+    }
+}
+
 fn storage_to_bignum(bytes: &[u8]) -> Result<Value, LexDataError> {
+    let (is_neg, int, fraction) = storage_to_decimal_parts(bytes)?;
+    let sign = if is_neg { "-" } else { "" };
+    let decimal = if fraction.is_empty() {
+        format!("{sign}{int}")
+    } else {
+        format!("{sign}{int}.{fraction}")
+    };
+    Ok(Value::String(decimal))
+}
+
+// The sign, integer magnitude and fraction digits of a stored decimal,
+// with the fraction's trailing zeros as written, so its scale is the
+// length of the fraction.
+pub fn storage_to_decimal_parts(bytes: &[u8]) -> Result<(bool, Integer, String), LexDataError> {
     let end = bytes.len();
     let int = storage_to_bigint(&bytes[0..end])?;
     let (is_pos, size, idx) = size_decode(&bytes[0..end]);
     let start = size as usize + idx;
-    let fraction_bytes = &bytes[start..end];
+    let fraction_bytes = bytes.get(start..end).unwrap_or_default();
+    if fraction_size(fraction_bytes, is_pos) > fraction_bytes.len() {
+        return Err(LexDataError::TruncatedInput(
+            "The decimal fraction runs past the end of the buffer".to_string(),
+        ));
+    }
     let fraction = if is_pos {
         decode_fraction(fraction_bytes)
     } else {
@@ -527,17 +1962,183 @@ fn storage_to_bignum(bytes: &[u8]) -> Result<Value, LexDataError> {
         Value::BigInt(int) => int,
         _ => panic!("bigint storage must return bigint"),
     };
-    let decimal = if fraction.is_empty() {
-        format!("{int:}")
+    // a negative zero integer part is only kept with a fraction
+    let is_neg = !is_pos && (int != 0 || !fraction.is_empty());
+    Ok((is_neg, int.abs(), fraction))
+}
+
+// Compare two decimal keys by their integer parts alone, as a coarse
+// pre-filter for range scans. The integer part is a self-delimiting
+// prefix of the key, so unequal parts decide the order of the keys and
+// Equal leaves it to the fractions. The integer part of a negative
+// fraction is a negative zero, which sorts below zero.
+pub fn decimal_integer_cmp(a: &[u8], b: &[u8]) -> Result<Ordering, LexDataError> {
+    Ok(decimal_integer_part(a)?.cmp(decimal_integer_part(b)?))
+}
+
+fn decimal_integer_part(bytes: &[u8]) -> Result<&[u8], LexDataError> {
+    match bytes.first().and_then(|b| Aspect::from_u8(*b)) {
+        Some(a) if try_aspect_storage(a) == Some(StorageType::BigNum) => (),
+        _ => {
+            return Err(LexDataError::UnexpectedAspect(
+                "The key is not a decimal".to_string(),
+            ))
+        }
+    }
+    let (_, size, idx) = size_decode(&bytes[1..]);
+    let end = 1 + idx + size as usize;
+    match bytes.get(1..end) {
+        Some(int) if idx > 0 => Ok(int),
+        _ => Err(LexDataError::TruncatedInput(format!(
+            "The integer part needs {end} bytes but the key has {}",
+            bytes.len()
+        ))),
+    }
+}
+
+const EXPONENT_NEGATIVE: u8 = 0x40;
+const EXPONENT_ZERO: u8 = 0x80;
+const EXPONENT_POSITIVE: u8 = 0xc0;
+// Decimals in scientific form: a sign tag, the exponent of the
+// normalized mantissa d.ddd as a sign-flipped i32, and then the
+// mantissa digits in pairs (10a+b+1) ending with 0x00. Negatives
+// complement everything after the tag. Magnitudes such as 1E400 sort
+// correctly without ever being expanded into digits.
+fn decimal_exponent_to_storage(s: String, a: Aspect) -> Result<Vec<u8>, LexDataError> {
+    let storage_type = aspect_storage(a);
+    if storage_type != StorageType::DecimalExponent {
+        return Err(LexDataError::UnexpectedAspect(format!(
+            "The aspect {a:?} did not match DecimalExponent value type"
+        )));
+    }
+    let (is_neg, mantissa, exponent) = parse_scientific(&s)?;
+    let mut buf = vec![aspect_byte(a)];
+    if mantissa.is_empty() {
+        buf.push(EXPONENT_ZERO);
+        return Ok(buf);
+    }
+    buf.push(if is_neg {
+        EXPONENT_NEGATIVE
     } else {
-        let sign = if int == 0 && !is_pos { "-" } else { "" };
-        format!("{sign:}{int:}.{fraction:}")
+        EXPONENT_POSITIVE
+    });
+    let mut body = Vec::with_capacity(5 + mantissa.len() / 2 + 1);
+    body.write_i32::<BigEndian>(exponent).unwrap();
+    body[0] ^= BYTE_SIGN_MASK;
+    for pair in mantissa.as_bytes().chunks(2) {
+        let high = pair[0] - b'0';
+        let low = pair.get(1).map_or(0, |d| d - b'0');
+        body.push(10 * high + low + 1);
+    }
+    body.push(0x00);
+    if is_neg {
+        for byte in body.iter_mut() {
+            *byte = !*byte
+        }
+    }
+    buf.extend(body);
+    Ok(buf)
+}
+
+// Split a decimal, optionally with an exponent, into its sign, its
+// significant digits without leading or trailing zeros, and the
+// exponent of the first digit. Zero has no digits.
+fn parse_scientific(s: &str) -> Result<(bool, String, i32), LexDataError> {
+    let bad = || LexDataError::BadDecimalFormat(format!("{s} is not a valid decimal"));
+    let (number, exponent) = match s.find(['e', 'E']) {
+        Some(i) => (&s[..i], s[i + 1..].parse::<i64>().map_err(|_| bad())?),
+        None => (s, 0),
     };
-    Ok(Value::String(decimal))
+    let (is_neg, unsigned) = match number.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, number.strip_prefix('+').unwrap_or(number)),
+    };
+    let (int_part, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+    if (int_part.is_empty() && fraction.is_empty())
+        || !int_part.bytes().all(|b| b.is_ascii_digit())
+        || !fraction.bytes().all(|b| b.is_ascii_digit())
+    {
+        return Err(bad());
+    }
+    let digits = format!("{int_part}{fraction}");
+    let significant = digits.trim_start_matches('0');
+    let mantissa = significant.trim_end_matches('0').to_string();
+    if mantissa.is_empty() {
+        return Ok((false, mantissa, 0));
+    }
+    let exponent = exponent
+        .checked_add(significant.len() as i64 - 1 - fraction.len() as i64)
+        .and_then(|e| i32::try_from(e).ok())
+        .ok_or_else(bad)?;
+    Ok((is_neg, mantissa, exponent))
+}
+
+fn scientific_string(is_neg: bool, mantissa: &str, exponent: i32) -> String {
+    if mantissa.is_empty() {
+        return "0.0E0".to_string();
+    }
+    let sign = if is_neg { "-" } else { "" };
+    let (lead, rest) = mantissa.split_at(1);
+    let rest = if rest.is_empty() { "0" } else { rest };
+    format!("{sign}{lead}.{rest}E{exponent}")
+}
+
+fn storage_to_decimal_exponent(bytes: &[u8]) -> Result<Value, LexDataError> {
+    let is_neg = match bytes.first() {
+        Some(&EXPONENT_ZERO) => return Ok(Value::String(scientific_string(false, "", 0))),
+        Some(&EXPONENT_NEGATIVE) => true,
+        Some(&EXPONENT_POSITIVE) => false,
+        Some(tag) => {
+            return Err(LexDataError::BadDecimalFormat(format!(
+                "{tag} is not a decimal sign tag"
+            )))
+        }
+        None => {
+            return Err(LexDataError::TruncatedInput(
+                "Decimal is missing its sign tag".to_string(),
+            ))
+        }
+    };
+    let body: Vec<u8> = bytes[1..]
+        .iter()
+        .map(|b| if is_neg { !*b } else { *b })
+        .collect();
+    if body.len() < 4 {
+        return Err(LexDataError::TruncatedInput(
+            "Decimal is missing its exponent".to_string(),
+        ));
+    }
+    let exponent = i32::from_be_bytes([body[0] ^ BYTE_SIGN_MASK, body[1], body[2], body[3]]);
+    let mut mantissa = String::new();
+    for byte in body[4..].iter() {
+        match byte {
+            0 => {
+                let mantissa = mantissa.trim_end_matches('0');
+                return Ok(Value::String(scientific_string(is_neg, mantissa, exponent)));
+            }
+            1..=100 => {
+                let pair = byte - 1;
+                mantissa.push(char::from(b'0' + pair / 10));
+                mantissa.push(char::from(b'0' + pair % 10));
+            }
+            _ => {
+                return Err(LexDataError::BadDecimalFormat(format!(
+                    "{byte} is not a mantissa digit pair"
+                )))
+            }
+        }
+    }
+    Err(LexDataError::TruncatedInput(
+        "Decimal mantissa is not terminated".to_string(),
+    ))
 }
 
-const F32_SIGN_MASK: u32 = 0x8000_0000;
-const F32_COMPLEMENT: u32 = 0xffff_ffff;
+/// Floats are stored big endian with non-negative values having the
+/// sign bit flipped and negative values having every bit flipped, so
+/// the bytes sort in numeric order.
+pub const F32_SIGN_MASK: u32 = 0x8000_0000;
+/// Complement applied to negative f32 bits.
+pub const F32_COMPLEMENT: u32 = 0xffff_ffff;
 fn float32_to_storage(f: f32, a: Aspect) -> Result<Vec<u8>, LexDataError> {
     let storage_type = aspect_storage(a);
     if storage_type == StorageType::Float32 {
@@ -559,6 +2160,10 @@ fn float32_to_storage(f: f32, a: Aspect) -> Result<Vec<u8>, LexDataError> {
 }
 
 fn storage_to_float32(bytes: &[u8]) -> Result<Value, LexDataError> {
+    read_float32(bytes).map(Value::Float32)
+}
+
+fn read_float32(bytes: &[u8]) -> Result<f32, LexDataError> {
     let mut rdr = Cursor::new(bytes);
     let f_result = rdr.read_f32::<BigEndian>();
     if let Ok(f) = f_result {
@@ -567,7 +2172,7 @@ fn storage_to_float32(bytes: &[u8]) -> Result<Value, LexDataError> {
         } else {
             f32::from_bits(f.to_bits() ^ F32_COMPLEMENT)
         };
-        Ok(Value::Float32(g))
+        Ok(g)
     } else {
         Err(LexDataError::BadFloat32Layout(
             "Unable to read bytes of float from storage!".to_string(),
@@ -575,18 +2180,21 @@ fn storage_to_float32(bytes: &[u8]) -> Result<Value, LexDataError> {
     }
 }
 
-const F64_SIGN_MASK: u64 = 0x8000_0000_0000_0000;
-const F64_COMPLEMENT: u64 = 0xffff_ffff_ffff_ffff;
+/// The f64 counterpart of [`F32_SIGN_MASK`].
+pub const F64_SIGN_MASK: u64 = 0x8000_0000_0000_0000;
+/// Complement applied to negative f64 bits.
+pub const F64_COMPLEMENT: u64 = 0xffff_ffff_ffff_ffff;
 fn float64_to_storage(f: f64, a: Aspect) -> Result<Vec<u8>, LexDataError> {
     let storage_type = aspect_storage(a);
     if storage_type == StorageType::Float64 {
         let aspect_u8 = aspect_byte(a);
-        let g: f64 = if f.signum() == -1.0 {
+        // test the sign bit, as signum is NaN for NaN
+        let g: f64 = if f.to_bits() & F64_SIGN_MASK > 0 {
             f64::from_bits(f.to_bits() ^ F64_COMPLEMENT)
         } else {
             f64::from_bits(f.to_bits() ^ F64_SIGN_MASK)
         };
-        let mut wtr = Vec::with_capacity(5);
+        let mut wtr = Vec::with_capacity(9);
         wtr.push(aspect_u8);
         wtr.write_f64::<BigEndian>(g).unwrap();
         Ok(wtr)
@@ -597,16 +2205,57 @@ fn float64_to_storage(f: f64, a: Aspect) -> Result<Vec<u8>, LexDataError> {
     }
 }
 
+// An unsigned key that orders like the float: flip the sign bit of
+// positives and invert negatives. Every NaN maps to the key of the
+// positive quiet NaN, which sorts above +INF.
+pub fn f64_order_key(f: f64) -> u64 {
+    let bits = if f.is_nan() {
+        f64::NAN.to_bits()
+    } else {
+        f.to_bits()
+    };
+    if bits & F64_SIGN_MASK > 0 {
+        bits ^ F64_COMPLEMENT
+    } else {
+        bits ^ F64_SIGN_MASK
+    }
+}
+
+pub fn f64_from_order_key(key: u64) -> f64 {
+    if key & F64_SIGN_MASK > 0 {
+        f64::from_bits(key ^ F64_SIGN_MASK)
+    } else {
+        f64::from_bits(key ^ F64_COMPLEMENT)
+    }
+}
+
+// For columns holding both xsd:float and xsd:double. Every value is
+// widened (exactly) to f64 and tagged Double, so both interleave by
+// value instead of sorting in separate aspect regions.
+pub fn float_to_storage_unified(f: f64, a: Aspect) -> Result<Vec<u8>, LexDataError> {
+    if a == Aspect::Float || a == Aspect::Double {
+        float64_to_storage(f, Aspect::Double)
+    } else {
+        Err(LexDataError::UnexpectedAspect(format!(
+            "The aspect {a:?} is not a float aspect"
+        )))
+    }
+}
+
 fn storage_to_float64(bytes: &[u8]) -> Result<Value, LexDataError> {
+    read_float64(bytes).map(Value::Float64)
+}
+
+fn read_float64(bytes: &[u8]) -> Result<f64, LexDataError> {
     let mut rdr = Cursor::new(bytes);
     let f_result = rdr.read_f64::<BigEndian>();
     if let Ok(f) = f_result {
-        let g: f64 = if f.signum() == -1.0 {
+        let g: f64 = if f.to_bits() & F64_SIGN_MASK > 0 {
             f64::from_bits(f.to_bits() ^ F64_SIGN_MASK)
         } else {
             f64::from_bits(f.to_bits() ^ F64_COMPLEMENT)
         };
-        Ok(Value::Float64(g))
+        Ok(g)
     } else {
         Err(LexDataError::BadFloat64Layout(
             "Unable to read bytes of float from storage!".to_string(),
@@ -614,623 +2263,6563 @@ fn storage_to_float64(bytes: &[u8]) -> Result<Value, LexDataError> {
     }
 }
 
-pub fn string_from_bytes(bytes: Bytes) -> Value {
-    let string = from_utf8(bytes.as_ref())
-        .expect("The database should not store strings in non utf8 format");
-    Value::String(string.to_owned())
+/// The f16 counterpart of [`F32_SIGN_MASK`].
+#[cfg(feature = "half")]
+pub const F16_SIGN_MASK: u16 = 0x8000;
+/// Complement applied to negative f16 bits.
+#[cfg(feature = "half")]
+pub const F16_COMPLEMENT: u16 = 0xffff;
+#[cfg(feature = "half")]
+fn float16_to_storage(f: f16, a: Aspect) -> Result<Vec<u8>, LexDataError> {
+    let storage_type = aspect_storage(a);
+    if storage_type == StorageType::Float16 {
+        let aspect_u8 = aspect_byte(a);
+        let g: u16 = if f.to_bits() & F16_SIGN_MASK > 0 {
+            f.to_bits() ^ F16_COMPLEMENT
+        } else {
+            f.to_bits() ^ F16_SIGN_MASK
+        };
+        let mut wtr = Vec::with_capacity(3);
+        wtr.push(aspect_u8);
+        wtr.write_u16::<BigEndian>(g).unwrap();
+        Ok(wtr)
+    } else {
+        Err(LexDataError::UnexpectedAspect(format!(
+            "The aspect {a:?} did not match Float16 value type"
+        )))
+    }
 }
 
-pub fn storage_to_value(bytes: Bytes) -> Result<(Value, Aspect), LexDataError> {
-    let aspect_byte = bytes[0];
-    let aspect: Aspect = byte_aspect(&aspect_byte);
-    if aspect == Aspect::True {
-        Ok((Value::Boolean(true), Aspect::Boolean))
-    } else if aspect == Aspect::False {
-        Ok((Value::Boolean(false), Aspect::Boolean))
+#[cfg(feature = "half")]
+fn storage_to_float16(bytes: &[u8]) -> Result<Value, LexDataError> {
+    let mut rdr = Cursor::new(bytes);
+    let f_result = rdr.read_u16::<BigEndian>();
+    if let Ok(f) = f_result {
+        let g: u16 = if f & F16_SIGN_MASK > 0 {
+            f ^ F16_SIGN_MASK
+        } else {
+            f ^ F16_COMPLEMENT
+        };
+        Ok(Value::Float16(f16::from_bits(g)))
     } else {
-        let ty = aspect_storage(aspect);
-        match ty {
-            StorageType::String => Ok((string_from_bytes(bytes.slice(1..)), aspect)),
-            StorageType::Int32 => storage_to_int32(&bytes.slice(1..)).map(|r| (r, aspect)),
-            StorageType::Int64 => storage_to_int64(&bytes.slice(1..)).map(|r| (r, aspect)),
-            StorageType::Float32 => storage_to_float32(&bytes.slice(1..)).map(|r| (r, aspect)),
-            StorageType::Float64 => storage_to_float64(&bytes.slice(1..)).map(|r| (r, aspect)),
-            StorageType::BigInt => storage_to_bigint(&bytes.slice(1..)).map(|r| (r, aspect)),
-            StorageType::BigNum => storage_to_bignum(&bytes.slice(1..)).map(|r| (r, aspect)),
-            StorageType::DateTime => storage_to_date_time(&bytes.slice(1..)).map(|r| (r, aspect)),
-        }
+        Err(LexDataError::BadFloat16Layout(
+            "Unable to read bytes of float from storage!".to_string(),
+        ))
     }
 }
 
-pub fn string_length(bytes: &[u8]) -> usize {
-    let mut count = 0_usize;
-    for b in bytes.iter() {
-        if *b == 0 {
-            break;
-        }
-        count += 1;
+#[cfg(not(feature = "half"))]
+fn storage_to_float16(_bytes: &[u8]) -> Result<Value, LexDataError> {
+    Err(LexDataError::BadFloat16Layout(
+        "Float16 values need the half feature".to_string(),
+    ))
+}
+
+// A set of flags as an unsigned big-endian integer of a fixed width,
+// preceded by that width. Masks of the same width sort numerically.
+pub fn bitmask_to_storage(
+    bits: u64,
+    width_bytes: usize,
+    a: Aspect,
+) -> Result<Vec<u8>, LexDataError> {
+    if try_aspect_storage(a) != Some(StorageType::Bitmask) {
+        return Err(LexDataError::UnexpectedAspect(format!(
+            "The aspect {a:?} did not match Bitmask value type"
+        )));
     }
-    count
+    if !(1..=8).contains(&width_bytes) {
+        return Err(LexDataError::OutOfRange(format!(
+            "A bitmask must be between 1 and 8 bytes wide, not {width_bytes}"
+        )));
+    }
+    if width_bytes < 8 && bits >> (8 * width_bytes) != 0 {
+        return Err(LexDataError::OutOfRange(format!(
+            "The bitmask {bits:#x} does not fit in {width_bytes} bytes"
+        )));
+    }
+    let mut wtr = Vec::with_capacity(2 + width_bytes);
+    wtr.push(aspect_byte(a));
+    wtr.push(width_bytes as u8);
+    wtr.extend(&bits.to_be_bytes()[8 - width_bytes..]);
+    Ok(wtr)
 }
 
-pub fn storage_size(bytes: Bytes) -> usize {
-    let a = byte_aspect(&bytes[0]);
+pub fn storage_to_bitmask(bytes: &[u8]) -> Result<u64, LexDataError> {
+    let width = match bytes.first() {
+        Some(width) if (1..=8).contains(width) => *width as usize,
+        Some(width) => {
+            return Err(LexDataError::BadBitmaskLayout(format!(
+                "{width} is not a valid bitmask width"
+            )))
+        }
+        None => {
+            return Err(LexDataError::TruncatedInput(
+                "Bitmask is missing its width".to_string(),
+            ))
+        }
+    };
+    let mask = fixed_width_payload(&bytes[1..], width)?;
+    Ok(mask.iter().fold(0, |bits, b| (bits << 8) | u64::from(*b)))
+}
+
+const BINARY_ESCAPE: u8 = 0xff;
+// Binary content is stored as its raw bytes, with each 0 byte escaped
+// as 0x00 0xff and the whole terminated by 0x00 0x00. Values of the
+// same length order by content, and a value sorts before any longer
+// value it is a prefix of: "" < "00" < "0000" < "01" < "FF".
+fn hex_binary_to_storage(s: String, a: Aspect) -> Result<Vec<u8>, LexDataError> {
     let storage_type = aspect_storage(a);
-    match storage_type {
-        StorageType::String => 1 + string_length(&bytes[1..bytes.len()]),
-        StorageType::Int32 => 5,
-        StorageType::Int64 => 9,
-        StorageType::Float32 => 5,
-        StorageType::Float64 => 9,
-        StorageType::BigInt => {
-            let (_, size, idx) = size_decode(&bytes[1..bytes.len()]);
-            size as usize + idx + 1
+    if storage_type != StorageType::HexBinary {
+        return Err(LexDataError::UnexpectedAspect(format!(
+            "The aspect {a:?} did not match HexBinary value type"
+        )));
+    }
+    let binary = match hex_to_bytes(&s) {
+        Some(binary) => binary,
+        None => {
+            return Err(LexDataError::BadLexicalForm(format!(
+                "{s} is not valid hexBinary"
+            )))
         }
-        StorageType::BigNum => {
-            let (_, size, idx) = size_decode(&bytes[1..bytes.len()]);
-            let offset = size as usize + idx + 1;
-            let mut count = 0_usize;
-            for i in offset..bytes.len() {
-                count += 1;
-                if bytes[i] & 1 != 1 {
-                    break;
+    };
+    let mut buf = Vec::with_capacity(binary.len() + 3);
+    buf.push(aspect_byte(a));
+    for b in binary {
+        buf.push(b);
+        if b == 0 {
+            buf.push(BINARY_ESCAPE);
+        }
+    }
+    buf.extend([0, 0]);
+    Ok(buf)
+}
+
+fn hex_to_bytes(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    s.as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            let high = char::from(pair[0]).to_digit(16)?;
+            let low = char::from(pair[1]).to_digit(16)?;
+            Some((high * 16 + low) as u8)
+        })
+        .collect()
+}
+
+// Decodes to the canonical, upper case, hex form
+fn storage_to_hex_binary(bytes: &[u8]) -> Result<Value, LexDataError> {
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    let mut iter = bytes.iter();
+    while let Some(b) = iter.next() {
+        if *b == 0 {
+            match iter.next() {
+                Some(&BINARY_ESCAPE) => {}
+                Some(0) => return Ok(Value::String(hex)),
+                _ => {
+                    return Err(LexDataError::BadBinaryLayout(
+                        "A 0 byte must be escaped or end the value".to_string(),
+                    ))
                 }
             }
-            count + offset
         }
-        StorageType::DateTime => 9,
+        write!(hex, "{b:02X}").unwrap();
     }
+    Err(LexDataError::TruncatedInput(
+        "Binary value is not terminated".to_string(),
+    ))
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+// The size of an escaped binary value starting after the aspect byte
+fn binary_size(bytes: &[u8]) -> usize {
+    let mut i = 1;
+    while i < bytes.len() {
+        if bytes[i] == 0 {
+            if bytes.get(i + 1) == Some(&0) {
+                return i + 2;
+            }
+            i += 2;
+        } else {
+            i += 1;
+        }
+    }
+    bytes.len()
+}
 
-    fn round_trip(v: Value, a: Aspect) -> (Value, Aspect) {
-        let storage = value_to_storage(v, a);
-        let bytes = Bytes::from(storage.unwrap());
-        storage_to_value(bytes).unwrap()
+const NANOS_PER_SECOND: i64 = 1_000_000_000;
+const NANOS_PER_DAY: i64 = 86_400 * NANOS_PER_SECOND;
+const TIME_ZONE_NONE: u8 = 0;
+const TIME_ZONE_UTC: u8 = 1;
+const TIME_ZONE_OFFSET: u8 = 2;
+// -00:00 is the same instant as +00:00, but is kept as written
+const TIME_ZONE_NEGATIVE_ZERO: u8 = 3;
+const MAX_OFFSET_MINUTES: i16 = 14 * 60;
+
+// Times of day are stored as nanoseconds since midnight normalized to
+// UTC, as a sign flipped i64 since an offset can move the instant out
+// of the day. A tag follows for the timezone as written: none, Z, or
+// an offset kept in minutes so the original can be reproduced.
+fn lexical_time_to_storage(s: String, a: Aspect) -> Result<Vec<u8>, LexDataError> {
+    let (nanos, tag, minutes) = parse_time(&s)?;
+    time_nanos_to_storage(nanos, tag, minutes, a)
+}
+
+// A chrono time has no timezone, so it is stored without one
+pub fn time_to_storage(t: NaiveTime, a: Aspect) -> Result<Vec<u8>, LexDataError> {
+    let nanos = i64::from(t.num_seconds_from_midnight()) * NANOS_PER_SECOND;
+    let fraction = i64::from(t.nanosecond());
+    if fraction >= NANOS_PER_SECOND {
+        return Err(LexDataError::BadDateFormat(format!("{t} is a leap second")));
     }
+    time_nanos_to_storage(nanos + fraction, TIME_ZONE_NONE, 0, a)
+}
 
-    #[test]
-    fn string_round_trip() {
-        let res = round_trip(Value::String("test".to_string()), Aspect::String);
-        let (v, a) = res;
-        assert_eq!(v, Value::String("test".to_string()));
-        assert_eq!(a, Aspect::String);
-        let res = round_trip(Value::String("test".to_string()), Aspect::Token);
-        let (v, a) = res;
-        assert_eq!(v, Value::String("test".to_string()));
-        assert_eq!(a, Aspect::Token);
-        let res = round_trip(Value::String("test".to_string()), Aspect::ID);
-        let (v, a) = res;
-        assert_eq!(v, Value::String("test".to_string()));
-        assert_eq!(a, Aspect::ID);
+fn time_nanos_to_storage(
+    nanos: i64,
+    tag: u8,
+    minutes: i16,
+    a: Aspect,
+) -> Result<Vec<u8>, LexDataError> {
+    if aspect_storage(a) != StorageType::Time {
+        return Err(LexDataError::UnexpectedAspect(format!(
+            "The aspect {a:?} did not match Time value type"
+        )));
     }
+    let mut buf = Vec::with_capacity(12);
+    buf.push(aspect_byte(a));
+    buf.extend((nanos - offset_nanos(minutes)).to_be_bytes());
+    buf[1] ^= BYTE_SIGN_MASK;
+    timezone_to_storage(&mut buf, tag, minutes);
+    Ok(buf)
+}
 
-    #[test]
-    fn string_ordering() {
-        let strings = [
-            "entertain",
-            "zig",
-            "pangolin",
-            "penguin",
-            "peaches",
-            "plums",
-            "pears",
-            "apple",
-            "candy",
-        ];
-        let mut byte_vec = Vec::with_capacity(strings.len());
+fn offset_nanos(minutes: i16) -> i64 {
+    i64::from(minutes) * 60 * NANOS_PER_SECOND
+}
+
+// Nanoseconds since midnight with the timezone tag and offset
+fn parse_time(s: &str) -> Result<(i64, u8, i16), LexDataError> {
+    let (time, tag, minutes) = split_timezone(s)?;
+    let bad = || LexDataError::BadDateFormat(format!("{s} is not a valid time"));
+    let b = time.as_bytes();
+    if b.len() < 8 || b[2] != b':' || b[5] != b':' {
+        return Err(bad());
+    }
+    let field = |r: Range<usize>| {
+        time.get(r)
+            .filter(|d| d.bytes().all(|c| c.is_ascii_digit()))
+            .and_then(|d| d.parse::<i64>().ok())
+    };
+    let seconds = match (field(0..2), field(3..5), field(6..8)) {
+        (Some(h), Some(m), Some(sec)) if h < 24 && m < 60 && sec < 60 => h * 3600 + m * 60 + sec,
+        _ => return Err(bad()),
+    };
+    let fraction = match &time[8..] {
+        "" => 0,
+        f => {
+            let digits = f
+                .strip_prefix('.')
+                .filter(|d| (1..=9).contains(&d.len()) && d.bytes().all(|c| c.is_ascii_digit()))
+                .ok_or_else(bad)?;
+            digits.parse::<i64>().unwrap() * 10_i64.pow(9 - digits.len() as u32)
+        }
+    };
+    Ok((seconds * NANOS_PER_SECOND + fraction, tag, minutes))
+}
+
+// Split a trailing Z or [+-]HH:MM timezone off a date or time
+fn split_timezone(s: &str) -> Result<(&str, u8, i16), LexDataError> {
+    if let Some(rest) = s.strip_suffix('Z') {
+        return Ok((rest, TIME_ZONE_UTC, 0));
+    }
+    let split = s.len().saturating_sub(6);
+    let tz = match s.get(split..) {
+        Some(tz) if tz.len() == 6 && tz.starts_with(['+', '-']) && tz.as_bytes()[3] == b':' => tz,
+        _ => return Ok((s, TIME_ZONE_NONE, 0)),
+    };
+    let digits = |d: &str| d.bytes().all(|c| c.is_ascii_digit()) && d.len() == 2;
+    let (hours, mins) = (&tz[1..3], &tz[4..6]);
+    let minutes = match (digits(hours), digits(mins)) {
+        (true, true) => hours.parse::<i16>().unwrap() * 60 + mins.parse::<i16>().unwrap(),
+        _ => MAX_OFFSET_MINUTES + 1,
+    };
+    if minutes > MAX_OFFSET_MINUTES || mins.parse::<i16>().unwrap_or(60) >= 60 {
+        return Err(LexDataError::BadDateFormat(format!(
+            "{tz} is not a valid timezone offset"
+        )));
+    }
+    match (tz.starts_with('-'), minutes) {
+        (true, 0) => Ok((&s[..split], TIME_ZONE_NEGATIVE_ZERO, 0)),
+        (true, _) => Ok((&s[..split], TIME_ZONE_OFFSET, -minutes)),
+        _ => Ok((&s[..split], TIME_ZONE_OFFSET, minutes)),
+    }
+}
+
+fn timezone_to_storage(buf: &mut Vec<u8>, tag: u8, minutes: i16) {
+    buf.push(tag);
+    if tag == TIME_ZONE_OFFSET {
+        let mut offset = minutes.to_be_bytes();
+        offset[0] ^= BYTE_SIGN_MASK;
+        buf.extend(offset);
+    }
+}
+
+fn storage_to_timezone(bytes: &[u8]) -> Result<(u8, i16), LexDataError> {
+    match bytes.first() {
+        Some(&TIME_ZONE_NONE) | Some(&TIME_ZONE_UTC) | Some(&TIME_ZONE_NEGATIVE_ZERO) => {
+            Ok((bytes[0], 0))
+        }
+        Some(&TIME_ZONE_OFFSET) => match bytes.get(1..3) {
+            Some(offset) => Ok((
+                TIME_ZONE_OFFSET,
+                i16::from_be_bytes([offset[0], offset[1]]) ^ i16::MIN,
+            )),
+            None => Err(LexDataError::TruncatedInput(
+                "Timezone offset is missing".to_string(),
+            )),
+        },
+        Some(tag) => Err(LexDataError::BadDateFormat(format!(
+            "{tag} is not a timezone tag"
+        ))),
+        None => Err(LexDataError::TruncatedInput(
+            "Timezone tag is missing".to_string(),
+        )),
+    }
+}
+
+fn timezone_size(tag: Option<&u8>) -> usize {
+    if tag == Some(&TIME_ZONE_OFFSET) {
+        3
+    } else {
+        1
+    }
+}
+
+fn timezone_string(tag: u8, minutes: i16) -> String {
+    match tag {
+        TIME_ZONE_UTC => "Z".to_string(),
+        TIME_ZONE_NEGATIVE_ZERO => "-00:00".to_string(),
+        TIME_ZONE_OFFSET => {
+            let sign = if minutes < 0 { '-' } else { '+' };
+            let minutes = minutes.abs();
+            format!("{sign}{:02}:{:02}", minutes / 60, minutes % 60)
+        }
+        _ => String::new(),
+    }
+}
+
+fn time_string(nanos: i64) -> String {
+    let seconds = nanos / NANOS_PER_SECOND;
+    let fraction = nanos % NANOS_PER_SECOND;
+    let mut s = format!(
+        "{:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    );
+    if fraction != 0 {
+        let digits = format!("{fraction:09}");
+        write!(s, ".{}", digits.trim_end_matches('0')).unwrap();
+    }
+    s
+}
+
+fn storage_to_time(bytes: &[u8]) -> Result<Value, LexDataError> {
+    let (nanos, tag, minutes) = storage_to_time_nanos(bytes)?;
+    Ok(Value::String(
+        time_string(nanos) + &timezone_string(tag, minutes),
+    ))
+}
+
+// The time of day as written, dropping any timezone
+pub fn storage_to_naive_time(bytes: &[u8]) -> Result<NaiveTime, LexDataError> {
+    let (nanos, _, _) = storage_to_time_nanos(bytes)?;
+    let seconds = (nanos / NANOS_PER_SECOND) as u32;
+    let fraction = (nanos % NANOS_PER_SECOND) as u32;
+    Ok(
+        NaiveTime::from_num_seconds_from_midnight_opt(seconds, fraction)
+            .expect("a time within the day is a valid NaiveTime"),
+    )
+}
+
+// Local nanoseconds since midnight with the timezone tag and offset
+fn storage_to_time_nanos(bytes: &[u8]) -> Result<(i64, u8, i16), LexDataError> {
+    let instant = match bytes.get(..8).and_then(|b| <[u8; 8]>::try_from(b).ok()) {
+        Some(b) => i64::from_be_bytes(b) ^ i64::MIN,
+        None => {
+            return Err(LexDataError::TruncatedInput(
+                "Time values need 8 bytes for the time of day".to_string(),
+            ))
+        }
+    };
+    let (tag, minutes) = storage_to_timezone(&bytes[8..])?;
+    let nanos = instant + offset_nanos(minutes);
+    if !(0..NANOS_PER_DAY).contains(&nanos) {
+        return Err(LexDataError::BadDateFormat(
+            "The stored time is outside of the day".to_string(),
+        ));
+    }
+    Ok((nanos, tag, minutes))
+}
+
+// Months are stored as a single byte followed by the timezone as for
+// times. They order by month, then by timezone as written, since a
+// month has no instant to normalize.
+fn g_month_to_storage(s: String, a: Aspect) -> Result<Vec<u8>, LexDataError> {
+    if aspect_storage(a) != StorageType::GMonth {
+        return Err(LexDataError::UnexpectedAspect(format!(
+            "The aspect {a:?} did not match GMonth value type"
+        )));
+    }
+    let (month, tag, minutes) = parse_g_month(&s)?;
+    let mut buf = Vec::with_capacity(5);
+    buf.push(aspect_byte(a));
+    buf.push(month);
+    timezone_to_storage(&mut buf, tag, minutes);
+    Ok(buf)
+}
+
+fn parse_g_month(s: &str) -> Result<(u8, u8, i16), LexDataError> {
+    let (month, tag, minutes) = split_timezone(s)?;
+    let month = month
+        .strip_prefix("--")
+        .filter(|m| m.len() == 2 && m.bytes().all(|c| c.is_ascii_digit()))
+        .and_then(|m| m.parse::<u8>().ok())
+        .filter(|m| (1..=12).contains(m));
+    match month {
+        Some(month) => Ok((month, tag, minutes)),
+        None => Err(LexDataError::BadDateFormat(format!(
+            "{s} is not a valid gMonth"
+        ))),
+    }
+}
+
+fn storage_to_g_month(bytes: &[u8]) -> Result<Value, LexDataError> {
+    let month = match bytes.first() {
+        Some(month) if (1..=12).contains(month) => month,
+        Some(month) => {
+            return Err(LexDataError::BadDateFormat(format!(
+                "{month} is not a month"
+            )))
+        }
+        None => {
+            return Err(LexDataError::TruncatedInput(
+                "gMonth is missing its month".to_string(),
+            ))
+        }
+    };
+    let (tag, minutes) = storage_to_timezone(&bytes[1..])?;
+    Ok(Value::String(format!(
+        "--{month:02}{}",
+        timezone_string(tag, minutes)
+    )))
+}
+
+// An xsd:duration as written, component by component
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub struct ParsedDuration {
+    pub is_negative: bool,
+    pub years: u64,
+    pub months: u64,
+    pub days: u64,
+    pub hours: u64,
+    pub minutes: u64,
+    pub seconds: u64,
+    pub nanos: u32,
+}
+
+// -PnYnMnDTnHnMn.nS with any components left out, but at least one
+// given and none after a T left empty. Seconds keep up to nine
+// fraction digits.
+pub fn parse_duration(s: &str) -> Result<ParsedDuration, LexDataError> {
+    let bad = || LexDataError::BadDateFormat(format!("{s} is not a valid duration"));
+    let (is_negative, rest) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s),
+    };
+    let rest = rest.strip_prefix('P').ok_or_else(bad)?;
+    let (date, time) = match rest.split_once('T') {
+        Some((date, time)) => (date, Some(time)),
+        None => (rest, None),
+    };
+    if time == Some("") || (date.is_empty() && time.is_none()) {
+        return Err(bad());
+    }
+    let number = |digits: &str| {
+        digits
+            .parse::<u64>()
+            .ok()
+            .filter(|_| digits.bytes().all(|b| b.is_ascii_digit()))
+            .ok_or_else(bad)
+    };
+    let mut d = ParsedDuration {
+        is_negative,
+        ..ParsedDuration::default()
+    };
+    let mut rest = date;
+    for (designator, field) in [
+        ('Y', &mut d.years),
+        ('M', &mut d.months),
+        ('D', &mut d.days),
+    ] {
+        if let Some((digits, after)) = rest.split_once(designator) {
+            *field = number(digits)?;
+            rest = after;
+        }
+    }
+    if !rest.is_empty() {
+        return Err(bad());
+    }
+    let mut rest = time.unwrap_or_default();
+    for (designator, field) in [('H', &mut d.hours), ('M', &mut d.minutes)] {
+        if let Some((digits, after)) = rest.split_once(designator) {
+            *field = number(digits)?;
+            rest = after;
+        }
+    }
+    if let Some(seconds) = rest.strip_suffix('S') {
+        let (whole, fraction) = seconds.split_once('.').unwrap_or((seconds, "0"));
+        if fraction.is_empty() || fraction.len() > 9 {
+            return Err(bad());
+        }
+        d.seconds = number(whole)?;
+        d.nanos = number(&format!("{fraction:0<9}"))? as u32;
+    } else if !rest.is_empty() {
+        return Err(bad());
+    }
+    Ok(d)
+}
+
+// Durations are only partially ordered in XSD: P1M may be 28 to 31
+// days, so it is neither above nor below P30D. For a stable display
+// order this key compares the total months and then the total time
+// with days of 24 hours, each a sign flipped i128. It puts P1M after
+// P100D and gives PT60S and PT1M the same key, which is not the XSD
+// order, so it is not meant for range queries. The key is untagged: it
+// carries no aspect byte and is not a value storage_to_value decodes.
+pub fn duration_sort_key(d: &ParsedDuration) -> Vec<u8> {
+    let months = i128::from(d.years) * 12 + i128::from(d.months);
+    let days = i128::from(d.days);
+    let seconds = ((days * 24 + i128::from(d.hours)) * 60 + i128::from(d.minutes)) * 60
+        + i128::from(d.seconds);
+    let nanos = seconds * 1_000_000_000 + i128::from(d.nanos);
+    let sign = if d.is_negative { -1 } else { 1 };
+    let mut buf = Vec::with_capacity(32);
+    for total in [months * sign, nanos * sign] {
+        let mut bytes = total.to_be_bytes();
+        bytes[0] ^= BYTE_SIGN_MASK;
+        buf.extend(bytes);
+    }
+    buf
+}
+
+const RATIONAL_END_ODD: u8 = 0x00;
+const RATIONAL_TERM: u8 = 0x01;
+const RATIONAL_END_EVEN: u8 = 0x02;
+// Rationals are stored as the terms of their continued fraction
+// [a0; a1, ..., an], with a0 the floor and every later term positive.
+// A larger term at an even position gives a larger value, and at an
+// odd position a smaller one, so odd terms are stored negated. Each
+// term is a bigint, later ones preceded by RATIONAL_TERM, and the
+// value ends with a marker standing in for an infinite next term:
+// above RATIONAL_TERM at an even position and below it at an odd one.
+// The last term of a fraction is always at least 2, which keeps the
+// representation unique.
+fn rational_to_storage(r: Rational, a: Aspect) -> Result<Vec<u8>, LexDataError> {
+    if aspect_storage(a) != StorageType::Rational {
+        return Err(LexDataError::UnexpectedAspect(format!(
+            "The aspect {a:?} did not match Rational value type"
+        )));
+    }
+    let terms = continued_fraction(r);
+    let n = terms.len();
+    let mut buf = vec![aspect_byte(a)];
+    for (i, term) in terms.into_iter().enumerate() {
+        if i > 0 {
+            buf.push(RATIONAL_TERM);
+        }
+        let term = if i % 2 == 1 { -term } else { term };
+        buf.extend(&bigint_to_storage(term, a)?[1..]);
+    }
+    buf.push(rational_end(n));
+    Ok(buf)
+}
+
+// The terminator after n terms, i.e. in place of term n
+fn rational_end(n: usize) -> u8 {
+    if n % 2 == 1 {
+        RATIONAL_END_ODD
+    } else {
+        RATIONAL_END_EVEN
+    }
+}
+
+fn continued_fraction(r: Rational) -> Vec<Integer> {
+    let (mut p, mut q) = r.into_numer_denom();
+    let mut terms = Vec::new();
+    loop {
+        let (term, rem) = p.div_rem_floor(q.clone());
+        terms.push(term);
+        if rem == 0 {
+            return terms;
+        }
+        p = q;
+        q = rem;
+    }
+}
+
+// A bigint term and the number of bytes it takes
+fn rational_term(bytes: &[u8]) -> Result<(Integer, usize), LexDataError> {
+    if bytes.is_empty() {
+        return Err(LexDataError::TruncatedInput(
+            "Rational is missing a term".to_string(),
+        ));
+    }
+    let (_, size, idx) = size_decode(bytes);
+    match storage_to_bigint(bytes)? {
+        Value::BigInt(term) => Ok((term, idx + size as usize)),
+        _ => panic!("bigint storage must return bigint"),
+    }
+}
+
+fn storage_to_rational(bytes: &[u8]) -> Result<Value, LexDataError> {
+    // convergents h/k of the terms read so far
+    let (mut h, mut h_prev) = (Integer::from(1), Integer::new());
+    let (mut k, mut k_prev) = (Integer::new(), Integer::from(1));
+    let mut n = 0;
+    let mut pos = 0;
+    loop {
+        let (term, size) = rational_term(&bytes[pos..])?;
+        let term = if n % 2 == 1 { -term } else { term };
+        if n > 0 && term < 1 {
+            return Err(LexDataError::BadRationalLayout(
+                "Terms after the first must be positive".to_string(),
+            ));
+        }
+        let h_next = term.clone() * h.clone() + h_prev;
+        let k_next = term * k.clone() + k_prev;
+        (h_prev, h, k_prev, k) = (h, h_next, k, k_next);
+        n += 1;
+        pos += size;
+        match bytes.get(pos) {
+            Some(&RATIONAL_TERM) => pos += 1,
+            Some(end) if *end == rational_end(n) => break,
+            Some(marker) => {
+                return Err(LexDataError::BadRationalLayout(format!(
+                    "{marker} is not a term or end marker after {n} terms"
+                )))
+            }
+            None => {
+                return Err(LexDataError::TruncatedInput(
+                    "Rational is not terminated".to_string(),
+                ))
+            }
+        }
+    }
+    Ok(Value::Rational(Rational::from((h, k))))
+}
+
+// The size of a rational starting at its aspect byte
+fn rational_size(bytes: &[u8]) -> Result<usize, LexDataError> {
+    let mut pos = 1;
+    loop {
+        pos = sized_end(bytes, pos)?;
+        match bytes.get(pos) {
+            Some(&RATIONAL_TERM) => pos += 1,
+            Some(_) => return Ok(pos + 1),
+            None => {
+                return Err(LexDataError::TruncatedInput(
+                    "Rational is not terminated".to_string(),
+                ))
+            }
+        }
+    }
+}
+
+// The end of the size prefixed magnitude at `pos`, which must lie
+// within the buffer.
+fn sized_end(bytes: &[u8], pos: usize) -> Result<usize, LexDataError> {
+    let rest = match bytes.get(pos..) {
+        Some(rest) if !rest.is_empty() => rest,
+        _ => {
+            return Err(LexDataError::TruncatedInput(
+                "The size prefix is missing".to_string(),
+            ))
+        }
+    };
+    let (_, size, idx) = size_decode(rest);
+    match usize::try_from(size)
+        .ok()
+        .and_then(|size| (pos + idx).checked_add(size))
+    {
+        Some(end) if end <= bytes.len() => Ok(end),
+        Some(end) => Err(LexDataError::TruncatedInput(format!(
+            "The value needs {end} bytes but the buffer has {}",
+            bytes.len()
+        ))),
+        None => Err(LexDataError::OutOfRange(format!(
+            "A magnitude of {size} bytes cannot be addressed"
+        ))),
+    }
+}
+
+const VERSION_END: u8 = 0x00;
+const VERSION_COMPONENT: u8 = 0x01;
+// Versions are stored as their dot separated components, each a
+// non-negative bigint preceded by VERSION_COMPONENT, and end with
+// VERSION_END. Components compare numerically, and a version sorts
+// before any longer one it is a prefix of. Leading zeros are not
+// kept, so 1.02 is stored as 1.2.
+fn version_to_storage(s: String, a: Aspect) -> Result<Vec<u8>, LexDataError> {
+    if aspect_storage(a) != StorageType::Version {
+        return Err(LexDataError::UnexpectedAspect(format!(
+            "The aspect {a:?} did not match Version value type"
+        )));
+    }
+    let mut buf = vec![aspect_byte(a)];
+    for component in s.split('.') {
+        if component.is_empty() || !component.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(LexDataError::BadLexicalForm(format!(
+                "{s} is not a dotted numeric version"
+            )));
+        }
+        let n: Integer = component
+            .parse()
+            .expect("a run of digits parses as an integer");
+        buf.push(VERSION_COMPONENT);
+        buf.extend(&bigint_to_storage(n, a)?[1..]);
+    }
+    buf.push(VERSION_END);
+    Ok(buf)
+}
+
+fn storage_to_version(bytes: &[u8]) -> Result<Value, LexDataError> {
+    let mut components = Vec::new();
+    let mut pos = 0;
+    loop {
+        match bytes.get(pos) {
+            Some(&VERSION_COMPONENT) => pos += 1,
+            Some(&VERSION_END) if !components.is_empty() => break,
+            Some(marker) => {
+                return Err(LexDataError::BadBigIntLayout(format!(
+                    "{marker} is not a version component or end marker"
+                )))
+            }
+            None => {
+                return Err(LexDataError::TruncatedInput(
+                    "Version is not terminated".to_string(),
+                ))
+            }
+        }
+        let (component, size) = rational_term(&bytes[pos..])?;
+        if component < 0 {
+            return Err(LexDataError::BadBigIntLayout(
+                "Version components cannot be negative".to_string(),
+            ));
+        }
+        components.push(component.to_string());
+        pos += size;
+    }
+    Ok(Value::String(components.join(".")))
+}
+
+// The size of a version starting at its aspect byte
+fn version_size(bytes: &[u8]) -> Result<usize, LexDataError> {
+    let mut pos = 1;
+    while bytes.get(pos) == Some(&VERSION_COMPONENT) {
+        pos = sized_end(bytes, pos + 1)?;
+    }
+    match bytes.get(pos) {
+        Some(&VERSION_END) => Ok(pos + 1),
+        Some(marker) => Err(LexDataError::BadBigIntLayout(format!(
+            "{marker} is not a version component or end marker"
+        ))),
+        None => Err(LexDataError::TruncatedInput(
+            "Version is not terminated".to_string(),
+        )),
+    }
+}
+
+const NUMERIC_FRACTION: u8 = 0xff;
+// Every aspect byte is the most significant byte of its keys, so an
+// Int always sorts below a Long whatever their values. Under the
+// Numeric tag integers of every aspect are stored as a bigint followed
+// by the aspect they were given as, so that they interleave by value
+// and decode back to their own width.
+//
+// Finite floats join them as their exact floor, then for a non-integer
+// NUMERIC_FRACTION and the binary digits of the fraction, seven to a
+// byte with a continuation bit as for decimal fractions, and then the
+// aspect. NUMERIC_FRACTION lies above every aspect byte, so 5.5 sorts
+// after every 5 and before 6. An integral float ties with the integers
+// of its value and is ordered among them by its aspect. NaN and the
+// infinities are rejected, and -0.0 is stored as 0.
+//
+// A numeric key decodes to the value and the aspect it was given as,
+// not Aspect::Numeric, so the key is rebuilt with encode_numeric(&v, a);
+// value_to_storage(v, a) would write the aspect's own layout instead.
+pub fn encode_numeric(v: &Value, a: Aspect) -> Result<Vec<u8>, LexDataError> {
+    let (int, fraction) = match (v, try_aspect_storage(a)) {
+        (Value::Int32(i), Some(StorageType::Int32)) => (Integer::from(*i), String::new()),
+        (Value::Int64(i), Some(StorageType::Int64)) => (Integer::from(*i), String::new()),
+        (Value::BigInt(i), Some(StorageType::BigInt)) => (i.clone(), String::new()),
+        (Value::Float32(f), Some(StorageType::Float32)) => float_floor_fraction(f64::from(*f))?,
+        (Value::Float64(f), Some(StorageType::Float64)) => float_floor_fraction(*f)?,
+        _ => {
+            return Err(LexDataError::ValueAspectMismatch {
+                value_kind: value_kind(v),
+                aspect: a,
+            })
+        }
+    };
+    validate_facets(v, a)?;
+    let mut buf = bigint_to_storage(int, Aspect::Numeric)?;
+    if !fraction.is_empty() {
+        buf.push(NUMERIC_FRACTION);
+        let chunks: Vec<&[u8]> = fraction.as_bytes().chunks(7).collect();
+        for (i, chunk) in chunks.iter().enumerate() {
+            let digits = format!("{:0<7}", from_utf8(chunk).unwrap());
+            let continuation = u8::from(i + 1 < chunks.len());
+            buf.push((u8::from_str_radix(&digits, 2).unwrap() << 1) | continuation);
+        }
+    }
+    buf.push(aspect_byte(a));
+    Ok(buf)
+}
+
+// The floor of a finite float and the binary digits of what remains,
+// without trailing zeros, both exact.
+fn float_floor_fraction(f: f64) -> Result<(Integer, String), LexDataError> {
+    if !f.is_finite() {
+        return Err(LexDataError::OutOfRange(format!(
+            "{f} has no place among numeric keys"
+        )));
+    }
+    let bits = f.to_bits();
+    let exponent = ((bits >> 52) & 0x7ff) as i32;
+    let significand = bits & ((1 << 52) - 1);
+    let (significand, exponent) = match exponent {
+        0 => (significand, -1074),
+        _ => (significand | (1 << 52), exponent - 1075),
+    };
+    if significand == 0 {
+        return Ok((Integer::new(), String::new()));
+    }
+    let shift = significand.trailing_zeros() as i32;
+    let mut int = Integer::from(significand >> shift);
+    if f < 0.0 {
+        int = -int;
+    }
+    let exponent = exponent + shift;
+    if exponent >= 0 {
+        int <<= exponent as u32;
+        return Ok((int, String::new()));
+    }
+    let places = exponent.unsigned_abs();
+    let mut denom = Integer::from(1);
+    denom <<= places;
+    let (floor, rem) = int.div_rem_floor(denom);
+    let fraction = format!(
+        "{:0>width$}",
+        rem.to_string_radix(2),
+        width = places as usize
+    );
+    Ok((floor, fraction))
+}
+
+// The float with the given floor and binary fraction digits
+fn float_from_floor_fraction(floor: Integer, fraction: &str) -> Result<f64, LexDataError> {
+    let places = fraction.len() as u32;
+    if places > 1074 {
+        return Err(LexDataError::OutOfRange(
+            "The fraction is finer than any float".to_string(),
+        ));
+    }
+    let mut int = floor;
+    int <<= places;
+    if !fraction.is_empty() {
+        int += Integer::from_str_radix(fraction, 2).unwrap();
+    }
+    // 2^-places, built from its bits so that it is exact
+    let scale = if places <= 1022 {
+        f64::from_bits(u64::from(1023 - places) << 52)
+    } else {
+        f64::from_bits(1 << (1074 - places))
+    };
+    Ok(int.to_f64() * scale)
+}
+
+// The bytes after the integer of a numeric key: a fraction if there is
+// one, and the aspect. One past the buffer when the fraction never ends.
+fn numeric_tail_size(tail: &[u8]) -> usize {
+    match tail.first() {
+        Some(&NUMERIC_FRACTION) => {
+            let chunks = tail[1..]
+                .iter()
+                .position(|b| b & 1 == 0)
+                .map_or(tail.len(), |p| p + 1);
+            chunks + 2
+        }
+        _ => 1,
+    }
+}
+
+fn storage_to_numeric(bytes: &[u8]) -> Result<(Value, Aspect), LexDataError> {
+    let (int, size) = rational_term(bytes)?;
+    let tail = &bytes[size..];
+    let tail_size = numeric_tail_size(tail);
+    let mut fraction = String::new();
+    if tail_size > 1 {
+        for b in tail.iter().take(tail_size - 1).skip(1) {
+            write!(fraction, "{:07b}", b >> 1).unwrap();
+        }
+        fraction.truncate(fraction.trim_end_matches('0').len());
+    }
+    let a = match tail.get(tail_size - 1) {
+        Some(b) => match Aspect::from_u8(*b) {
+            Some(a) => a,
+            None => {
+                return Err(LexDataError::UnexpectedAspect(format!(
+                    "{b} is not an aspect byte"
+                )))
+            }
+        },
+        None => {
+            return Err(LexDataError::TruncatedInput(
+                "The integer is missing its aspect".to_string(),
+            ))
+        }
+    };
+    let storage_type = try_aspect_storage(a);
+    let is_float = matches!(
+        storage_type,
+        Some(StorageType::Float32 | StorageType::Float64)
+    );
+    if !fraction.is_empty() && !is_float {
+        return Err(LexDataError::UnexpectedAspect(format!(
+            "The aspect {a:?} cannot hold a fraction"
+        )));
+    }
+    let v = match storage_type {
+        Some(StorageType::Int32) => int.to_i32().map(Value::Int32),
+        Some(StorageType::Int64) => int.to_i64().map(Value::Int64),
+        Some(StorageType::BigInt) => Some(Value::BigInt(int)),
+        Some(StorageType::Float32) => Some(Value::Float32(
+            float_from_floor_fraction(int, &fraction)? as f32,
+        )),
+        Some(StorageType::Float64) => {
+            Some(Value::Float64(float_from_floor_fraction(int, &fraction)?))
+        }
+        _ => {
+            return Err(LexDataError::UnexpectedAspect(format!(
+                "The aspect {a:?} is not a numeric aspect"
+            )))
+        }
+    };
+    match v {
+        Some(v) => {
+            validate_facets(&v, a)?;
+            Ok((v, a))
+        }
+        None => Err(LexDataError::OutOfRange(format!(
+            "The integer does not fit in {a:?}"
+        ))),
+    }
+}
+
+// Tags from here up are reserved for custom codecs and lie above every
+// aspect byte. A custom key is its tag followed by whatever the codec
+// writes, so how values of one tag sort is up to the codec. The last
+// byte, 0xff, is never a tag, so that it bounds every custom key.
+pub const CUSTOM_TAG_MIN: u8 = 0xc0;
+pub const CUSTOM_TAG_MAX: u8 = 0xfe;
+
+pub type CustomEncode = fn(&Value) -> Result<Vec<u8>, LexDataError>;
+pub type CustomDecode = fn(&[u8]) -> Result<(Value, usize), LexDataError>;
+
+#[derive(Clone, Copy)]
+struct CustomCodec {
+    encode: CustomEncode,
+    decode: CustomDecode,
+}
+
+static CUSTOM_CODECS: RwLock<[Option<CustomCodec>; 64]> = RwLock::new([None; 64]);
+
+// Register a codec for a tag in the custom range, replacing any codec
+// already registered for it. `encode` writes the bytes after the tag,
+// and `decode` is handed the bytes after the tag and returns the value
+// with the number of bytes it read, so custom values can be fields of
+// a compound key. The codecs are shared by the whole process, so they
+// are best registered once at start up, before any keys are read.
+pub fn register_custom(
+    tag: u8,
+    encode: CustomEncode,
+    decode: CustomDecode,
+) -> Result<(), LexDataError> {
+    if tag < CUSTOM_TAG_MIN {
+        return Err(LexDataError::OutOfRange(format!(
+            "{tag:#04x} is an aspect tag, custom tags start at {CUSTOM_TAG_MIN:#04x}"
+        )));
+    }
+    if tag > CUSTOM_TAG_MAX {
+        return Err(LexDataError::OutOfRange(format!(
+            "{tag:#04x} bounds the custom tags and cannot be registered"
+        )));
+    }
+    let mut codecs = CUSTOM_CODECS
+        .write()
+        .unwrap_or_else(PoisonError::into_inner);
+    codecs[(tag - CUSTOM_TAG_MIN) as usize] = Some(CustomCodec { encode, decode });
+    Ok(())
+}
+
+fn custom_codec(tag: u8) -> Result<CustomCodec, LexDataError> {
+    let codec = match tag.checked_sub(CUSTOM_TAG_MIN) {
+        Some(i) => CUSTOM_CODECS.read().unwrap_or_else(PoisonError::into_inner)[i as usize],
+        None => None,
+    };
+    codec.ok_or_else(|| {
+        LexDataError::UnexpectedAspect(format!("No custom codec is registered for {tag:#04x}"))
+    })
+}
+
+fn custom_to_storage(tag: u8, v: Value) -> Result<Vec<u8>, LexDataError> {
+    let codec = custom_codec(tag)?;
+    let mut buf = vec![tag];
+    buf.extend((codec.encode)(&v)?);
+    Ok(buf)
+}
+
+// A custom value starting at its tag, with the size of its encoding.
+// The size comes from the codec, so it is checked against the buffer.
+fn storage_to_custom(bytes: &[u8]) -> Result<(Value, usize), LexDataError> {
+    let codec = custom_codec(bytes[0])?;
+    let (v, size) = (codec.decode)(&bytes[1..])?;
+    if size >= bytes.len() {
+        return Err(LexDataError::TruncatedInput(format!(
+            "The codec for {:#04x} read {size} bytes but only {} follow the tag",
+            bytes[0],
+            bytes.len() - 1
+        )));
+    }
+    Ok((Value::Custom(bytes[0], Box::new(v)), size + 1))
+}
+
+// The True and False aspect bytes fix where booleans sort among the
+// other aspects. A boolean may instead be placed under a tag of its
+// own, followed by 0 or 1: BOOLEAN_TAG_FIRST sorts below every aspect,
+// and the tags from BOOLEAN_TAG_MIN up to the custom tags above them.
+pub const BOOLEAN_TAG_FIRST: u8 = 0x00;
+pub const BOOLEAN_TAG_MIN: u8 = 0x80;
+
+fn is_boolean_tag(tag: u8) -> bool {
+    tag == BOOLEAN_TAG_FIRST || (BOOLEAN_TAG_MIN..CUSTOM_TAG_MIN).contains(&tag)
+}
+
+pub fn encode_boolean_placed(b: bool, tag: u8) -> Result<Vec<u8>, LexDataError> {
+    if !is_boolean_tag(tag) {
+        return Err(LexDataError::UnexpectedAspect(format!(
+            "{tag:#04x} is not a boolean placement tag"
+        )));
+    }
+    Ok(vec![tag, u8::from(b)])
+}
+
+fn storage_to_placed_boolean(bytes: &[u8]) -> Result<bool, LexDataError> {
+    match bytes.get(1) {
+        Some(0) => Ok(false),
+        Some(1) => Ok(true),
+        Some(b) => Err(LexDataError::BadLexicalForm(format!(
+            "{b} is not a placed boolean"
+        ))),
+        None => Err(LexDataError::TruncatedInput(
+            "The placed boolean has no value".to_string(),
+        )),
+    }
+}
+
+pub fn string_from_bytes(bytes: Bytes) -> Value {
+    let string = from_utf8(bytes.as_ref())
+        .expect("The database should not store strings in non utf8 format");
+    Value::String(string.to_owned())
+}
+
+pub fn storage_to_value(bytes: Bytes) -> Result<(Value, Aspect), LexDataError> {
+    let aspect_byte = bytes[0];
+    if is_boolean_tag(aspect_byte) {
+        return storage_to_placed_boolean(&bytes).map(|b| (Value::Boolean(b), Aspect::Boolean));
+    }
+    if aspect_byte >= CUSTOM_TAG_MIN {
+        return storage_to_custom(&bytes).map(|(v, _)| (v, Aspect::Custom));
+    }
+    let aspect: Aspect = byte_aspect(&aspect_byte);
+    if aspect == Aspect::True {
+        Ok((Value::Boolean(true), Aspect::Boolean))
+    } else if aspect == Aspect::False {
+        Ok((Value::Boolean(false), Aspect::Boolean))
+    } else {
+        let ty = aspect_storage(aspect);
+        match ty {
+            StorageType::String if aspect == Aspect::QName => {
+                qname_from_bytes(&bytes[1..]).map(|r| (r, aspect))
+            }
+            StorageType::String => {
+                // a delimited string field ends at its 0 byte
+                let end = 1 + string_length(&bytes[1..]);
+                Ok((string_from_bytes(bytes.slice(1..end)), aspect))
+            }
+            StorageType::Int32 => storage_to_int32(&bytes.slice(1..))
+                .and_then(|r| check_int32_range(r, aspect))
+                .map(|r| (r, aspect)),
+            StorageType::Int64 => storage_to_int64(&bytes.slice(1..)).map(|r| (r, aspect)),
+            StorageType::UInt32 => storage_to_uint32(&bytes[1..]).map(|r| (r, aspect)),
+            StorageType::SizedInt => read_sized_int(&bytes[1..]).map(|i| (Value::Int64(i), aspect)),
+            StorageType::PrefixedString => {
+                prefixed_string(&bytes[1..]).map(|s| (Value::String(s.to_string()), aspect))
+            }
+            StorageType::OrderedURI => {
+                ordered_uri(&bytes[1..]).map(|(s, _)| (Value::String(s.to_string()), aspect))
+            }
+            StorageType::Float32 => storage_to_float32(&bytes.slice(1..)).map(|r| (r, aspect)),
+            StorageType::Float64 => storage_to_float64(&bytes.slice(1..)).map(|r| (r, aspect)),
+            StorageType::Float16 => storage_to_float16(&bytes.slice(1..)).map(|r| (r, aspect)),
+            StorageType::BigInt => storage_to_bigint(&bytes.slice(1..)).map(|r| (r, aspect)),
+            StorageType::BigNum => storage_to_bignum(&bytes.slice(1..)).map(|r| (r, aspect)),
+            StorageType::DateTime | StorageType::DateTimeStamp => {
+                storage_to_chrono(&bytes).map(|dt| (Value::DateTime(dt), aspect))
+            }
+            StorageType::Time => storage_to_time(&bytes[1..]).map(|r| (r, aspect)),
+            StorageType::Rational => storage_to_rational(&bytes[1..]).map(|r| (r, aspect)),
+            StorageType::GMonth => storage_to_g_month(&bytes[1..]).map(|r| (r, aspect)),
+            StorageType::Version => storage_to_version(&bytes[1..]).map(|r| (r, aspect)),
+            StorageType::Numeric => storage_to_numeric(&bytes[1..]),
+            StorageType::DecimalExponent => {
+                storage_to_decimal_exponent(&bytes.slice(1..)).map(|r| (r, aspect))
+            }
+            StorageType::Bitmask => {
+                storage_to_bitmask(&bytes.slice(1..)).map(|r| (Value::Bitmask(r), aspect))
+            }
+            StorageType::HexBinary => storage_to_hex_binary(&bytes.slice(1..)).map(|r| (r, aspect)),
+            // the inline form, a Boolean tag and a 0 or 1 byte
+            StorageType::Boolean => match bytes.get(1) {
+                Some(0) => Ok((Value::Boolean(false), aspect)),
+                Some(1) => Ok((Value::Boolean(true), aspect)),
+                Some(b) => Err(LexDataError::OutOfRange(format!(
+                    "{b} is not an inline boolean"
+                ))),
+                None => Err(LexDataError::TruncatedInput(
+                    "Inline boolean is missing its value".to_string(),
+                )),
+            },
+            StorageType::Custom => Err(LexDataError::UnexpectedAspect(
+                "Custom values are stored under their own tag".to_string(),
+            )),
+        }
+    }
+}
+
+// Short and Byte share the Int32 layout, so a corrupt buffer could
+// hold a value outside their range.
+fn check_int32_range(v: Value, a: Aspect) -> Result<Value, LexDataError> {
+    let (min, max) = match a {
+        Aspect::Short => (i32::from(i16::MIN), i32::from(i16::MAX)),
+        Aspect::Byte => (i32::from(i8::MIN), i32::from(i8::MAX)),
+        _ => return Ok(v),
+    };
+    match v {
+        Value::Int32(i) if i < min || i > max => Err(LexDataError::OutOfRange(format!(
+            "{i} is out of range for {a:?}"
+        ))),
+        _ => Ok(v),
+    }
+}
+
+// The XSD facets of the derived integer aspects. Decoding does not
+// re-check these by default as it costs a comparison per bigint.
+pub fn validate_facets(v: &Value, a: Aspect) -> Result<(), LexDataError> {
+    let holds = match (v, a) {
+        (Value::BigInt(i), Aspect::PositiveInteger) => i.cmp0() == Ordering::Greater,
+        (Value::BigInt(i), Aspect::NonNegativeInteger) => i.cmp0() != Ordering::Less,
+        (Value::Int32(_), _) => return check_int32_range(v.clone(), a).map(|_| ()),
+        (Value::Int64(i), Aspect::UnsignedInt) => u32::try_from(*i).is_ok(),
+        _ => true,
+    };
+    if holds {
+        Ok(())
+    } else {
+        Err(LexDataError::OutOfRange(format!(
+            "{v:?} violates the facets of {a:?}"
+        )))
+    }
+}
+
+// Decode and re-validate facets, to catch corrupt buffers that would
+// otherwise yield a schema violating value.
+pub fn storage_to_value_validated(bytes: Bytes) -> Result<(Value, Aspect), LexDataError> {
+    let (v, a) = storage_to_value(bytes)?;
+    validate_facets(&v, a)?;
+    Ok((v, a))
+}
+
+pub fn string_length(bytes: &[u8]) -> usize {
+    let mut count = 0_usize;
+    for b in bytes.iter() {
+        if *b == 0 {
+            break;
+        }
+        count += 1;
+    }
+    count
+}
+
+// The size of a string starting at `start`, including its terminating
+// 0 byte when it is a delimited field rather than the end of the buffer.
+fn delimited_size(bytes: &[u8], start: usize) -> usize {
+    let end = start + string_length(&bytes[start..]);
+    if end < bytes.len() {
+        end + 1
+    } else {
+        end
+    }
+}
+
+// The size of the value at the start of a buffer that is known to be
+// well formed. It panics on an empty buffer or an unknown aspect byte,
+// so untrusted input should go through storage_size_checked.
+pub fn storage_size(bytes: Bytes) -> usize {
+    if is_boolean_tag(bytes[0]) {
+        return 2;
+    }
+    if bytes[0] >= CUSTOM_TAG_MIN {
+        return storage_to_custom(&bytes).map_or(bytes.len(), |(_, size)| size);
+    }
+    let a = byte_aspect(&bytes[0]);
+    let storage_type = aspect_storage(a);
+    match storage_type {
+        StorageType::String if a == Aspect::QName => {
+            let prefix = string_length(&bytes[1..]);
+            if prefix + 1 == bytes.len() {
+                bytes.len()
+            } else {
+                delimited_size(&bytes, 2 + prefix)
+            }
+        }
+        StorageType::String => delimited_size(&bytes, 1),
+        StorageType::Int32 | StorageType::UInt32 => 5,
+        StorageType::Int64 => 9,
+        StorageType::Float32 => 5,
+        StorageType::Float64 => 9,
+        StorageType::Float16 => 3,
+        StorageType::BigInt => {
+            let (_, size, idx) = size_decode(&bytes[1..bytes.len()]);
+            (size as usize).saturating_add(idx + 1)
+        }
+        StorageType::BigNum => {
+            let (is_pos, size, idx) = size_decode(&bytes[1..bytes.len()]);
+            let offset = (size as usize).saturating_add(idx + 1);
+            // one past the buffer when the fraction never ends
+            offset + fraction_size(bytes.get(offset..).unwrap_or_default(), is_pos)
+        }
+        StorageType::DateTime => 9,
+        StorageType::DateTimeStamp => 17,
+        StorageType::Time => 9 + timezone_size(bytes.get(9)),
+        // one past the buffer when the value does not end in it
+        StorageType::Rational => rational_size(&bytes).unwrap_or(bytes.len() + 1),
+        StorageType::GMonth => 2 + timezone_size(bytes.get(2)),
+        StorageType::Version => version_size(&bytes).unwrap_or(bytes.len() + 1),
+        StorageType::Numeric => {
+            let (_, size, idx) = size_decode(&bytes[1..]);
+            let tail = (size as usize).saturating_add(idx + 1);
+            tail + numeric_tail_size(bytes.get(tail..).unwrap_or_default())
+        }
+        StorageType::DecimalExponent => {
+            if bytes[1] == EXPONENT_ZERO {
+                2
+            } else {
+                // the mantissa ends at 0x00, or 0xff when complemented
+                let terminator = if bytes[1] == EXPONENT_NEGATIVE {
+                    0xff
+                } else {
+                    0x00
+                };
+                match bytes.iter().skip(6).position(|b| *b == terminator) {
+                    Some(p) => 7 + p,
+                    None => bytes.len(),
+                }
+            }
+        }
+        StorageType::Bitmask | StorageType::SizedInt => 2 + bytes[1] as usize,
+        // one past the buffer when the size cannot be read
+        StorageType::PrefixedString => match decode_size(&bytes[1..]) {
+            Ok((size, idx)) => (1 + idx).saturating_add(size.try_into().unwrap_or(usize::MAX)),
+            Err(_) => bytes.len() + 1,
+        },
+        StorageType::OrderedURI => {
+            ordered_uri(&bytes[1..]).map_or(bytes.len() + 1, |(_, size)| 1 + size)
+        }
+        StorageType::HexBinary => binary_size(&bytes),
+        // True and False are a bare aspect byte, Boolean is inline
+        StorageType::Boolean if a == Aspect::Boolean => 2,
+        StorageType::Boolean => 1,
+        StorageType::Custom => 1,
+    }
+}
+
+// storage_size for untrusted input: an unknown aspect byte or a value
+// running past the end of the buffer is an error rather than a panic
+// or an out of range size. A string with no terminator runs to the end
+// of the buffer, as the last field of a key does.
+pub fn storage_size_checked(bytes: &[u8]) -> Result<usize, LexDataError> {
+    let truncated = |needed: usize| {
+        LexDataError::TruncatedInput(format!(
+            "The value needs {needed} bytes but the buffer has {}",
+            bytes.len()
+        ))
+    };
+    let first = match bytes.first() {
+        Some(first) => *first,
+        None => return Err(truncated(1)),
+    };
+    if first >= CUSTOM_TAG_MIN {
+        return storage_to_custom(bytes).map(|(_, size)| size);
+    }
+    if is_boolean_tag(first) {
+        return if bytes.len() < 2 {
+            Err(truncated(2))
+        } else {
+            Ok(2)
+        };
+    }
+    let storage_type = match Aspect::from_u8(first).and_then(try_aspect_storage) {
+        Some(storage_type) => storage_type,
+        None => {
+            return Err(LexDataError::UnexpectedAspect(format!(
+                "{first} is not the byte of an implemented aspect"
+            )))
+        }
+    };
+    let size = match storage_type {
+        StorageType::Bitmask | StorageType::DecimalExponent | StorageType::SizedInt
+            if bytes.len() < 2 =>
+        {
+            return Err(truncated(2))
+        }
+        StorageType::BigInt | StorageType::BigNum | StorageType::Numeric => {
+            sized_end(bytes, 1)?;
+            storage_size(Bytes::copy_from_slice(bytes))
+        }
+        StorageType::Rational => rational_size(bytes)?,
+        StorageType::Version => version_size(bytes)?,
+        _ => storage_size(Bytes::copy_from_slice(bytes)),
+    };
+    if size > bytes.len() {
+        Err(truncated(size))
+    } else {
+        Ok(size)
+    }
+}
+
+// The length of the record of aspect `a` at the start of an untrusted
+// buffer, as by storage_size_checked. A plain string has no end of its
+// own, so strings must be written by encode_string_length_prefixed and
+// are read as PrefixedString records.
+pub fn record_len(bytes: &[u8], a: Aspect) -> Result<usize, LexDataError> {
+    if try_aspect_storage(a) == Some(StorageType::String) {
+        return Err(LexDataError::UnexpectedAspect(format!(
+            "A {a:?} record has no length, write it as a PrefixedString"
+        )));
+    }
+    match bytes.first() {
+        Some(first) if *first == aspect_byte(a) => storage_size_checked(bytes),
+        Some(first) => Err(LexDataError::UnexpectedAspect(format!(
+            "Expected a {a:?} record but found the byte {first}"
+        ))),
+        None => Err(LexDataError::TruncatedInput(
+            "An empty buffer holds no record".to_string(),
+        )),
+    }
+}
+
+/// Encode and decode a value, panicking with a description of the
+/// mismatch if it does not come back unchanged. Intended as a
+/// conformance check for crates extending lexdata with their own
+/// aspects.
+///
+/// ```
+/// use lexdata::{assert_round_trip, Aspect, Value};
+///
+/// assert_round_trip(Value::Int32(-7), Aspect::Int);
+/// assert_round_trip(Value::String("0.25".to_string()), Aspect::Decimal);
+/// ```
+#[cfg(feature = "test-util")]
+pub fn assert_round_trip(v: Value, a: Aspect) {
+    let storage = match value_to_storage(v.clone(), a) {
+        Ok(storage) => storage,
+        Err(e) => panic!("could not encode {v:?} as {a:?}: {e:?}"),
+    };
+    match storage_to_value(Bytes::from(storage.clone())) {
+        Ok((v2, a2)) => assert!(
+            v == v2 && a == a2,
+            "{v:?} as {a:?} round tripped to {v2:?} as {a2:?} through {storage:?}"
+        ),
+        Err(e) => panic!("could not decode {v:?} as {a:?} from {storage:?}: {e:?}"),
+    }
+}
+
+/// Encode every value, sort the encodings and check that they decode
+/// in the natural order of the inputs. On failure the panic shows the
+/// first position where the orders differ, with the bytes of both the
+/// expected and the found value. The inputs must be totally ordered
+/// (no NaN).
+///
+/// ```
+/// use lexdata::{assert_ordered, Aspect};
+///
+/// assert_ordered(&[3, -1, 0, i32::MAX], Aspect::Int);
+/// assert_ordered(&["b", "a", "ab"], Aspect::String);
+/// ```
+#[cfg(feature = "test-util")]
+pub fn assert_ordered<T: Into<Value> + Clone + PartialOrd>(values: &[T], a: Aspect) {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|x, y| x.partial_cmp(y).expect("values must be totally ordered"));
+    let expected: Vec<Value> = sorted.into_iter().map(Into::into).collect();
+    let encode = |v: &Value| match value_to_storage(v.clone(), a) {
+        Ok(storage) => storage,
+        Err(e) => panic!("could not encode {v:?} as {a:?}: {e:?}"),
+    };
+    let mut encoded: Vec<Vec<u8>> = expected.iter().map(encode).collect();
+    encoded.sort();
+    for (i, (storage, want)) in encoded.iter().zip(expected.iter()).enumerate() {
+        let found = match storage_to_value(Bytes::from(storage.clone())) {
+            Ok((found, _)) => found,
+            Err(e) => panic!("could not decode {storage:?} as {a:?}: {e:?}"),
+        };
+        if found != *want {
+            panic!(
+                "ordering differs at position {i} of {}:\n  expected {want:?} stored as {:?}\n  found    {found:?} stored as {storage:?}",
+                expected.len(),
+                encode(want),
+            );
+        }
+    }
+}
+
+// The XSD canonical lexical form of a value, which depends on the
+// aspect as well as the value (an Int64 may be a long or a timestamp).
+pub fn canonical_lexical(v: &Value, a: Aspect) -> Result<String, LexDataError> {
+    match v {
+        Value::Boolean(b) => Ok(b.to_string()),
+        Value::Int32(i) => Ok(i.to_string()),
+        Value::Int64(i) => {
+            if aspect_storage(a) == StorageType::DateTime {
+                match DateTime::from_timestamp(*i, 0) {
+                    Some(dt) => Ok(dt.format("%Y-%m-%dT%H:%M:%SZ").to_string()),
+                    None => Err(LexDataError::BadDateFormat(format!(
+                        "The timestamp {i} is out of range"
+                    ))),
+                }
+            } else {
+                Ok(i.to_string())
+            }
+        }
+        Value::BigInt(i) => Ok(i.to_string()),
+        Value::Float32(f) => Ok(canonical_float(*f)),
+        Value::Float64(f) => Ok(canonical_float(*f)),
+        #[cfg(feature = "half")]
+        Value::Float16(f) => Ok(canonical_float(*f)),
+        Value::Bitmask(bits) => Ok(bits.to_string()),
+        Value::Rational(r) => Ok(r.to_string()),
+        Value::Custom(_, v) => canonical_lexical(v, a),
+        Value::DateTime(dt) => Ok(dt
+            .with_timezone(&Utc)
+            .to_rfc3339_opts(SecondsFormat::AutoSi, true)),
+        Value::String(s) => match aspect_storage(a) {
+            StorageType::BigNum => canonical_decimal(s),
+            StorageType::DecimalExponent => {
+                let (is_neg, mantissa, exponent) = parse_scientific(s)?;
+                Ok(scientific_string(is_neg, &mantissa, exponent))
+            }
+            StorageType::HexBinary => match hex_to_bytes(s) {
+                Some(_) => Ok(s.to_uppercase()),
+                None => Err(LexDataError::BadLexicalForm(format!(
+                    "{s} is not valid hexBinary"
+                ))),
+            },
+            StorageType::DateTime | StorageType::DateTimeStamp => Ok(parse_date_time(s)?
+                .with_timezone(&Utc)
+                .to_rfc3339_opts(SecondsFormat::AutoSi, true)),
+            // times with a timezone are given in UTC
+            StorageType::Time => {
+                let (nanos, tag, minutes) = parse_time(s)?;
+                if tag == TIME_ZONE_NONE {
+                    Ok(time_string(nanos))
+                } else {
+                    let utc = (nanos - offset_nanos(minutes)).rem_euclid(NANOS_PER_DAY);
+                    Ok(time_string(utc) + "Z")
+                }
+            }
+            StorageType::GMonth => {
+                let (month, tag, minutes) = parse_g_month(s)?;
+                Ok(format!("--{month:02}{}", timezone_string(tag, minutes)))
+            }
+            StorageType::Version => {
+                let key = version_to_storage(s.clone(), a)?;
+                match storage_to_version(&key[1..])? {
+                    Value::String(s) => Ok(s),
+                    _ => panic!("version storage must return a string"),
+                }
+            }
+            _ => Ok(s.clone()),
+        },
+    }
+}
+
+// No redundant zeros, and no decimal point for integral values (XSD 1.1).
+fn canonical_decimal(s: &str) -> Result<String, LexDataError> {
+    let (is_neg, unsigned) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+    let (int_part, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+    if (int_part.is_empty() && fraction.is_empty())
+        || !int_part.bytes().all(|b| b.is_ascii_digit())
+        || !fraction.bytes().all(|b| b.is_ascii_digit())
+    {
+        return Err(LexDataError::BadDecimalFormat(format!(
+            "{s} is not a valid decimal"
+        )));
+    }
+    let int_part = match int_part.trim_start_matches('0') {
+        "" => "0",
+        digits => digits,
+    };
+    let fraction = fraction.trim_end_matches('0');
+    let sign = if is_neg && (int_part != "0" || !fraction.is_empty()) {
+        "-"
+    } else {
+        ""
+    };
+    if fraction.is_empty() {
+        Ok(format!("{sign}{int_part}"))
+    } else {
+        Ok(format!("{sign}{int_part}.{fraction}"))
+    }
+}
+
+// Scientific notation with at least one fractional mantissa digit
+fn canonical_float<F: UpperExp + Into<f64> + Copy>(f: F) -> String {
+    let g: f64 = f.into();
+    if g.is_nan() {
+        "NaN".to_string()
+    } else if g.is_infinite() {
+        if g > 0.0 { "INF" } else { "-INF" }.to_string()
+    } else {
+        let scientific = format!("{f:E}");
+        match scientific.split_once('E') {
+            Some((mantissa, exponent)) if !mantissa.contains('.') => {
+                format!("{mantissa}.0E{exponent}")
+            }
+            _ => scientific,
+        }
+    }
+}
+
+// Compare values by number rather than by variant, for deduplication.
+// Integers of any width, rationals, finite floats and strings holding
+// a decimal are compared exactly, so 5, 5_i64 and "5.0" are equal, but
+// a float only equals a decimal it holds exactly (0.1 as a double is
+// not "0.1"). NaN equals nothing, and anything else falls back to ==.
+pub fn value_eq_numeric(a: &Value, b: &Value) -> bool {
+    match (numeric_value(a), numeric_value(b)) {
+        (Some(x), Some(y)) => x == y,
+        _ => a == b,
+    }
+}
+
+fn numeric_value(v: &Value) -> Option<Rational> {
+    match v {
+        Value::Int32(i) => Some(Rational::from(*i)),
+        Value::Int64(i) => Some(Rational::from(*i)),
+        Value::BigInt(i) => Some(Rational::from(i.clone())),
+        Value::Rational(r) => Some(r.clone()),
+        Value::Float32(f) => Rational::from_f64(f64::from(*f)),
+        Value::Float64(f) => Rational::from_f64(*f),
+        #[cfg(feature = "half")]
+        Value::Float16(f) => Rational::from_f64(f64::from(*f)),
+        Value::String(s) => {
+            let decimal = canonical_decimal(s).ok()?;
+            let (int_part, fraction) = decimal.split_once('.').unwrap_or((&decimal, ""));
+            let numer: Integer = format!("{int_part}{fraction}").parse().ok()?;
+            let denom: Integer = format!("1{}", "0".repeat(fraction.len())).parse().ok()?;
+            Some(Rational::from((numer, denom)))
+        }
+        Value::Boolean(_) | Value::Bitmask(_) | Value::Custom(..) | Value::DateTime(_) => None,
+    }
+}
+
+// Decode any integer, decimal or rational key as an exact rational,
+// so callers can treat the numeric aspects alike.
+pub fn decode_number(bytes: &[u8]) -> Result<(Rational, Aspect), LexDataError> {
+    let (v, a) = decode_exact(bytes)?;
+    let is_number = matches!(
+        aspect_storage(a),
+        StorageType::Int32
+            | StorageType::Int64
+            | StorageType::UInt32
+            | StorageType::SizedInt
+            | StorageType::BigInt
+            | StorageType::BigNum
+            | StorageType::Rational
+    );
+    match numeric_value(&v) {
+        Some(r) if is_number => Ok((r, a)),
+        _ => Err(LexDataError::UnexpectedAspect(format!(
+            "The aspect {a:?} is not an exact number"
+        ))),
+    }
+}
+
+// Decode a single value without allocating for strings and numbers
+pub fn decode_ref(bytes: &[u8]) -> Result<(ValueRef<'_>, Aspect), LexDataError> {
+    if bytes.is_empty() {
+        return Err(LexDataError::TruncatedInput(
+            "An empty buffer holds no value".to_string(),
+        ));
+    }
+    if bytes[0] >= CUSTOM_TAG_MIN {
+        return Ok((ValueRef::Owned(storage_to_custom(bytes)?.0), Aspect::Custom));
+    }
+    if is_boolean_tag(bytes[0]) {
+        return Ok((
+            ValueRef::Boolean(storage_to_placed_boolean(bytes)?),
+            Aspect::Boolean,
+        ));
+    }
+    let aspect = match Aspect::from_u8(bytes[0]) {
+        Some(Aspect::True) => return Ok((ValueRef::Boolean(true), Aspect::Boolean)),
+        Some(Aspect::False) => return Ok((ValueRef::Boolean(false), Aspect::Boolean)),
+        Some(aspect) => aspect,
+        None => {
+            return Err(LexDataError::UnexpectedAspect(format!(
+                "{} is not an aspect byte",
+                bytes[0]
+            )))
+        }
+    };
+    let storage_type = match try_aspect_storage(aspect) {
+        Some(storage_type) => storage_type,
+        None => {
+            return Err(LexDataError::UnexpectedAspect(format!(
+                "The aspect {aspect:?} is not implemented"
+            )))
+        }
+    };
+    let payload = &bytes[1..];
+    let value = match storage_type {
+        StorageType::String if aspect != Aspect::QName => {
+            let string = &payload[..string_length(payload)];
+            match from_utf8(string) {
+                Ok(s) => ValueRef::String(s),
+                Err(e) => return Err(LexDataError::BadLexicalForm(e.to_string())),
+            }
+        }
+        // the size prefix and magnitude, once checked to fit the buffer
+        StorageType::BigInt => ValueRef::BigInt(&payload[..storage_size_checked(bytes)? - 1]),
+        StorageType::Int32 => {
+            let i = read_int32(payload)?;
+            check_int32_range(Value::Int32(i), aspect)?;
+            ValueRef::Int32(i)
+        }
+        StorageType::Int64 => ValueRef::Int64(read_int64(payload)?),
+        StorageType::UInt32 => ValueRef::Int64(read_uint32(payload)?.into()),
+        StorageType::SizedInt => ValueRef::Int64(read_sized_int(payload)?),
+        StorageType::PrefixedString => ValueRef::String(prefixed_string(payload)?),
+        StorageType::OrderedURI => ValueRef::String(ordered_uri(payload)?.0),
+        StorageType::Float32 => ValueRef::Float32(read_float32(payload)?),
+        StorageType::Float64 => ValueRef::Float64(read_float64(payload)?),
+        // numeric keys report the aspect they were given as
+        _ => {
+            let (v, a) = storage_to_value(Bytes::copy_from_slice(bytes))?;
+            return Ok((ValueRef::Owned(v), a));
+        }
+    };
+    Ok((value, aspect))
+}
+
+// Decode the first value in a buffer of concatenated values, along
+// with the number of bytes it occupies, so that the remainder can be
+// handed on to the next call.
+pub fn decode_one(bytes: &[u8]) -> Result<(Value, Aspect, usize), LexDataError> {
+    let size = storage_size_checked(bytes)?;
+    let (value, aspect) = storage_to_value(Bytes::copy_from_slice(&bytes[..size]))?;
+    Ok((value, aspect, size))
+}
+
+// Decode each value of a buffer of concatenated fields in turn
+pub fn values_iter(
+    bytes: &[u8],
+) -> impl Iterator<Item = Result<(Value, Aspect), LexDataError>> + '_ {
+    let mut offset = 0;
+    std::iter::from_fn(move || {
+        if offset >= bytes.len() {
+            return None;
+        }
+        match decode_one(&bytes[offset..]) {
+            Ok((value, aspect, size)) => {
+                offset += size;
+                Some(Ok((value, aspect)))
+            }
+            Err(e) => {
+                offset = bytes.len();
+                Some(Err(e))
+            }
+        }
+    })
+}
+
+// Strings do not delimit themselves, so as a field of a compound key
+// they are followed by a 0 byte. A length prefix would break the
+// ordering, so a string field may not itself contain a 0 byte.
+pub fn field_to_storage(v: Value, a: Aspect) -> Result<Vec<u8>, LexDataError> {
+    let is_string = try_aspect_storage(a) == Some(StorageType::String);
+    let mut buf = value_to_storage(v, a)?;
+    if is_string {
+        buf.push(0);
+    }
+    Ok(buf)
+}
+
+// Decode a buffer that must hold exactly one value
+pub fn decode_exact(bytes: &[u8]) -> Result<(Value, Aspect), LexDataError> {
+    let size = storage_size_checked(bytes)?;
+    if size < bytes.len() {
+        return Err(LexDataError::TrailingBytes(format!(
+            "The value takes {size} bytes but the buffer has {}",
+            bytes.len()
+        )));
+    }
+    storage_to_value(Bytes::copy_from_slice(bytes))
+}
+
+// The encoding without its aspect byte, for columns whose aspect is
+// stored elsewhere. Keys of one aspect keep their order. Booleans,
+// which are only an aspect byte, take their inline form instead.
+pub fn encode_untagged(v: &Value, a: Aspect) -> Result<Vec<u8>, LexDataError> {
+    if a == Aspect::Custom {
+        return Err(LexDataError::UnexpectedAspect(
+            "A custom value needs its tag".to_string(),
+        ));
+    }
+    match (v, a) {
+        (Value::Boolean(b), Aspect::Boolean) => Ok(vec![u8::from(*b)]),
+        _ => Ok(value_to_storage(v.clone(), a)?.split_off(1)),
+    }
+}
+
+// Decode an untagged encoding given the aspect it was written with
+pub fn decode_untagged(bytes: &[u8], a: Aspect) -> Result<Value, LexDataError> {
+    if a == Aspect::Custom {
+        return Err(LexDataError::UnexpectedAspect(
+            "A custom value needs its tag".to_string(),
+        ));
+    }
+    let mut tagged = Vec::with_capacity(bytes.len() + 1);
+    tagged.push(aspect_byte(a));
+    tagged.extend(bytes);
+    decode_exact(&tagged).map(|(v, _)| v)
+}
+
+// Locate each field of a compound key built from self-terminating
+// encodings, without decoding any of them. As with decode_tuple, an
+// error reports the index of the field that could not be measured.
+pub fn field_ranges(bytes: &[u8]) -> Result<Vec<Range<usize>>, LexDataError> {
+    let mut ranges = Vec::new();
+    let mut offset = 0;
+    while offset < bytes.len() {
+        let size =
+            storage_size_checked(&bytes[offset..]).map_err(|source| LexDataError::FieldError {
+                index: ranges.len(),
+                source: Box::new(source),
+            })?;
+        ranges.push(offset..offset + size);
+        offset += size;
+    }
+    Ok(ranges)
+}
+
+const LIST_END: u8 = 0x00;
+const LIST_ELEMENT: u8 = 0x01;
+// A list of values of one aspect, each a field preceded by LIST_ELEMENT
+// and ended with LIST_END, so that lists compare element by element
+// and a list sorts before any longer list it is a prefix of. A count
+// up front would order lists by length first.
+pub fn encode_list(values: &[Value], a: Aspect) -> Result<Vec<u8>, LexDataError> {
+    let mut buf = Vec::new();
+    for v in values {
+        buf.push(LIST_ELEMENT);
+        buf.extend(field_to_storage(v.clone(), a)?);
+    }
+    buf.push(LIST_END);
+    Ok(buf)
+}
+
+pub fn decode_list(bytes: &[u8]) -> Result<Vec<Value>, LexDataError> {
+    let mut values = Vec::new();
+    let mut aspect = None;
+    let mut offset = 0;
+    loop {
+        match bytes.get(offset) {
+            Some(&LIST_ELEMENT) => offset += 1,
+            Some(&LIST_END) if offset + 1 == bytes.len() => return Ok(values),
+            Some(&LIST_END) => {
+                return Err(LexDataError::TrailingBytes(format!(
+                    "The list ends {} bytes before the buffer",
+                    bytes.len() - offset - 1
+                )))
+            }
+            Some(marker) => {
+                return Err(LexDataError::UnexpectedAspect(format!(
+                    "{marker} is not a list element or end marker"
+                )))
+            }
+            None => {
+                return Err(LexDataError::TruncatedInput(
+                    "The list is not terminated".to_string(),
+                ))
+            }
+        }
+        let (v, a, size) = decode_one(bytes.get(offset..).unwrap_or_default())?;
+        if *aspect.get_or_insert(a) != a {
+            return Err(LexDataError::UnexpectedAspect(format!(
+                "The list mixes {:?} and {a:?}",
+                aspect.unwrap()
+            )));
+        }
+        values.push(v);
+        offset += size;
+    }
+}
+
+// Decode every field of a compound key, reporting the index of the
+// field that failed.
+pub fn decode_tuple(bytes: &[u8]) -> Result<Vec<(Value, Aspect)>, LexDataError> {
+    let buf = Bytes::copy_from_slice(bytes);
+    let mut fields = Vec::new();
+    let mut offset = 0;
+    while offset < buf.len() {
+        let index = fields.len();
+        let field_error = |source| LexDataError::FieldError {
+            index,
+            source: Box::new(source),
+        };
+        let size = storage_size_checked(&buf[offset..]).map_err(field_error)?;
+        fields.push(storage_to_value(buf.slice(offset..offset + size)).map_err(field_error)?);
+        offset += size;
+    }
+    Ok(fields)
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum SortDir {
+    Ascending,
+    Descending,
+}
+
+// A compound key field that sorts in the given direction. A descending
+// field is the complement of its ascending bytes, string terminator
+// included, so longer strings still sort before their prefixes.
+pub fn field_to_storage_directed(
+    v: Value,
+    a: Aspect,
+    dir: SortDir,
+) -> Result<Vec<u8>, LexDataError> {
+    let mut buf = field_to_storage(v, a)?;
+    if dir == SortDir::Descending {
+        buf.iter_mut().for_each(|b| *b = !*b);
+    }
+    Ok(buf)
+}
+
+// Decode a compound key with one field per direction, complementing
+// the descending fields back before reading them.
+pub fn decode_tuple_directed(
+    bytes: &[u8],
+    dirs: &[SortDir],
+) -> Result<Vec<(Value, Aspect)>, LexDataError> {
+    let mut fields = Vec::with_capacity(dirs.len());
+    let mut offset = 0;
+    for (index, dir) in dirs.iter().enumerate() {
+        let field_error = |source| LexDataError::FieldError {
+            index,
+            source: Box::new(source),
+        };
+        let rest: Vec<u8> = match dir {
+            SortDir::Ascending => bytes[offset..].to_vec(),
+            SortDir::Descending => bytes[offset..].iter().map(|b| !b).collect(),
+        };
+        if rest.is_empty() {
+            return Err(field_error(LexDataError::TruncatedInput(
+                "The key has no bytes left for the field".to_string(),
+            )));
+        }
+        let size = storage_size_checked(&rest).map_err(field_error)?;
+        fields.push(storage_to_value(Bytes::copy_from_slice(&rest[..size])).map_err(field_error)?);
+        offset += size;
+    }
+    if offset < bytes.len() {
+        return Err(LexDataError::TrailingBytes(format!(
+            "The key has {} bytes after its {} fields",
+            bytes.len() - offset,
+            dirs.len()
+        )));
+    }
+    Ok(fields)
+}
+
+// A closed interval as a compound key of its two endpoints, so that
+// intervals sort by their low endpoint and then their high one.
+pub fn encode_interval(lo: &Value, hi: &Value, a: Aspect) -> Result<Vec<u8>, LexDataError> {
+    let mut buf = field_to_storage(lo.clone(), a)?;
+    let high = field_to_storage(hi.clone(), a)?;
+    if buf > high {
+        return Err(LexDataError::OutOfRange(format!(
+            "The interval [{lo:?}, {hi:?}] is empty"
+        )));
+    }
+    buf.extend(high);
+    Ok(buf)
+}
+
+pub fn decode_interval(bytes: &[u8]) -> Result<(Value, Value, Aspect), LexDataError> {
+    let mut fields = decode_tuple(bytes)?;
+    match (fields.pop(), fields.pop()) {
+        (Some((hi, a)), Some((lo, b))) if fields.is_empty() && a == b => Ok((lo, hi, a)),
+        _ => Err(LexDataError::UnexpectedAspect(
+            "An interval is two endpoints of one aspect".to_string(),
+        )),
+    }
+}
+
+// The storage family a value lands in under its natural aspect
+pub fn value_storage_type(v: &Value) -> StorageType {
+    match v {
+        Value::String(_) => StorageType::String,
+        Value::BigInt(_) => StorageType::BigInt,
+        Value::Int32(_) => StorageType::Int32,
+        Value::Int64(_) => StorageType::Int64,
+        Value::Float32(_) => StorageType::Float32,
+        Value::Float64(_) => StorageType::Float64,
+        Value::Boolean(_) => StorageType::Boolean,
+        #[cfg(feature = "half")]
+        Value::Float16(_) => StorageType::Float16,
+        Value::Bitmask(_) => StorageType::Bitmask,
+        Value::Rational(_) => StorageType::Rational,
+        Value::Custom(..) => StorageType::Custom,
+        Value::DateTime(_) => StorageType::DateTimeStamp,
+    }
+}
+
+// A zero or empty placeholder for each storage family, for fixtures
+// and schema driven rows. A family with no value to stand in fails.
+pub fn default_value(ty: StorageType) -> Result<Value, LexDataError> {
+    let v = match ty {
+        StorageType::String | StorageType::HexBinary | StorageType::PrefixedString => {
+            Value::String(String::new())
+        }
+        StorageType::BigNum | StorageType::DecimalExponent => Value::String("0".to_string()),
+        StorageType::DateTime | StorageType::DateTimeStamp => {
+            Value::String("1970-01-01T00:00:00Z".to_string())
+        }
+        StorageType::Time => Value::String("00:00:00".to_string()),
+        StorageType::GMonth => Value::String("--01".to_string()),
+        StorageType::Version => Value::String("0".to_string()),
+        StorageType::Int32 => Value::Int32(0),
+        StorageType::Int64 | StorageType::UInt32 | StorageType::SizedInt => Value::Int64(0),
+        StorageType::BigInt | StorageType::Numeric => Value::BigInt(Integer::new()),
+        StorageType::Float32 => Value::Float32(0.0),
+        StorageType::Float64 => Value::Float64(0.0),
+        #[cfg(feature = "half")]
+        StorageType::Float16 => Value::Float16(f16::ZERO),
+        // without half support there is no Float16 value
+        #[cfg(not(feature = "half"))]
+        StorageType::Float16 => {
+            return Err(LexDataError::BadFloat16Layout(
+                "Float16 values need the half feature".to_string(),
+            ))
+        }
+        StorageType::Bitmask => Value::Bitmask(0),
+        StorageType::Rational => Value::Rational(Rational::new()),
+        StorageType::Boolean => Value::Boolean(false),
+        // custom values carry their tag, so there is no default
+        StorageType::Custom => {
+            return Err(LexDataError::UnexpectedAspect(
+                "Custom values have no default, as each carries its own tag".to_string(),
+            ))
+        }
+        StorageType::OrderedURI => {
+            return Err(LexDataError::UnexpectedAspect(
+                "Ordered URIs have no default, as the empty string is not a URI".to_string(),
+            ))
+        }
+    };
+    Ok(v)
+}
+
+// An upper bound on the encoded length of a value, without encoding
+// it. Fixed widths are exact, variable widths are bounded by content.
+pub fn estimated_size(v: &Value, a: Aspect) -> usize {
+    match v {
+        Value::Boolean(_) => 1,
+        #[cfg(feature = "half")]
+        Value::Float16(_) => 3,
+        // a numeric key is a bigint of up to 9 bytes and the aspect
+        Value::Int32(_) | Value::Int64(_) if a == Aspect::Numeric => 12,
+        Value::Float32(f) if a == Aspect::Numeric => numeric_float_size(f64::from(*f)),
+        Value::Float64(f) if a == Aspect::Numeric => numeric_float_size(*f),
+        Value::Int64(_) if try_aspect_storage(a) == Some(StorageType::UInt32) => 5,
+        Value::Int64(_) if a == Aspect::SizedInt => 10,
+        Value::Int32(_) | Value::Float32(_) => 5,
+        Value::Int64(_) | Value::Float64(_) => 9,
+        Value::DateTime(_) if aspect_storage(a) == StorageType::DateTime => 9,
+        Value::DateTime(_) => 17,
+        Value::Bitmask(_) => 10,
+        Value::BigInt(i) => {
+            let bytes = (i.significant_bits() as usize + 1).div_ceil(8);
+            1 + size_encode(bytes as u64).len() + bytes + usize::from(a == Aspect::Numeric)
+        }
+        // The partial quotients after the floor multiply to at most the
+        // denominator, and there are at most two terms per bit of it.
+        Value::Rational(r) => {
+            let numer_bits = r.numer().significant_bits() as usize;
+            let denom_bits = r.denom().significant_bits() as usize;
+            let terms = 2 * denom_bits + 2;
+            2 + terms * 13 + (numer_bits + denom_bits) / 8 + 1
+        }
+        // a custom codec is opaque, so the value is encoded
+        Value::Custom(tag, v) => custom_codec(*tag)
+            .and_then(|codec| (codec.encode)(v))
+            .map_or(1, |buf| buf.len() + 1),
+        Value::String(s) => match try_aspect_storage(a) {
+            Some(StorageType::DateTime) => 9,
+            Some(StorageType::DateTimeStamp) => 17,
+            Some(StorageType::Time) => 12,
+            Some(StorageType::GMonth) => 5,
+            Some(StorageType::BigNum) => 12 + s.len(),
+            Some(StorageType::DecimalExponent) => 8 + s.len(),
+            Some(StorageType::HexBinary) => 3 + s.len(),
+            Some(StorageType::PrefixedString) => 1 + encode_size(s.len() as u64).len() + s.len(),
+            // percent encoding at most triples each byte of a component
+            Some(StorageType::OrderedURI) => 10 + 4 * s.len(),
+            // each digit adds under half a byte, and each component
+            // at most a marker, a size byte and a sign byte
+            Some(StorageType::Version) => 2 + 3 * s.len(),
+            // a QName adds a separator when it has no prefix
+            _ => 2 + s.len(),
+        },
+    }
+}
+
+// A numeric float is its floor, and a fraction of 7 bits a byte
+fn numeric_float_size(f: f64) -> usize {
+    match float_floor_fraction(f) {
+        Ok((floor, fraction)) => {
+            let bytes = (floor.significant_bits() as usize + 1).div_ceil(8);
+            let fraction = if fraction.is_empty() {
+                0
+            } else {
+                1 + fraction.len().div_ceil(7)
+            };
+            2 + size_encode(bytes as u64).len() + bytes + fraction
+        }
+        Err(_) => 1,
+    }
+}
+
+// The leading bytes that keys of an aspect can start with. This is
+// the aspect tag, except for Boolean which is stored as True or False
+// and may also be inline after its own tag, and Custom which is stored
+// under the registered tags.
+pub fn aspect_prefix(a: Aspect) -> Vec<u8> {
+    match a {
+        Aspect::Boolean => vec![
+            aspect_byte(Aspect::Boolean),
+            aspect_byte(Aspect::False),
+            aspect_byte(Aspect::True),
+        ],
+        Aspect::Custom => (CUSTOM_TAG_MIN..=CUSTOM_TAG_MAX).collect(),
+        _ => vec![aspect_byte(a)],
+    }
+}
+
+// A key above every value of the aspect. Fixed width values are
+// followed by more 0xff bytes than they can hold, and strings by 0xff
+// which never occurs in UTF-8. The other variable width encodings can
+// hold any run of bytes, so for them this is the next aspect's tag.
+// Custom keys are bounded by the byte above the last custom tag.
+pub fn aspect_high_sentinel(a: Aspect) -> Vec<u8> {
+    let payload = match aspect_storage(a) {
+        StorageType::Boolean => return vec![aspect_byte(Aspect::True), 0xff],
+        StorageType::Custom => return vec![CUSTOM_TAG_MAX + 1],
+        StorageType::String => 0,
+        StorageType::GMonth => 3,
+        StorageType::Float16 => 2,
+        StorageType::Int32 | StorageType::UInt32 | StorageType::Float32 => 4,
+        StorageType::Int64 | StorageType::Float64 | StorageType::DateTime => 8,
+        StorageType::Bitmask | StorageType::SizedInt => 9,
+        StorageType::Time => 11,
+        StorageType::DateTimeStamp => 16,
+        StorageType::BigInt
+        | StorageType::BigNum
+        | StorageType::DecimalExponent
+        | StorageType::HexBinary
+        | StorageType::Rational
+        | StorageType::Version
+        | StorageType::Numeric
+        | StorageType::PrefixedString
+        | StorageType::OrderedURI => return vec![aspect_byte(a) + 1],
+    };
+    let mut key = vec![aspect_byte(a)];
+    key.resize(payload + 2, 0xff);
+    key
+}
+
+// The lowest key of an aspect's region, so that with
+// aspect_high_sentinel the half open range covers every key written
+// for it. Booleans are written as the adjacent False and True tags, so
+// their region starts at False; the inline form under the Boolean tag
+// lies outside it. Custom keys start at the first custom tag.
+pub fn aspect_scan_start(a: Aspect) -> Vec<u8> {
+    match aspect_storage(a) {
+        StorageType::Boolean => vec![aspect_byte(Aspect::False)],
+        StorageType::Custom => vec![CUSTOM_TAG_MIN],
+        _ => vec![aspect_byte(a)],
+    }
+}
+
+// Bounds covering every key that starts with the byte `prefix`
+pub fn prefix_range(prefix: u8) -> KeyBounds {
+    let high = match prefix.checked_add(1) {
+        Some(next) => Bound::Excluded(vec![next]),
+        None => Bound::Unbounded,
+    };
+    (Bound::Included(vec![prefix]), high)
+}
+
+pub type KeyBounds = (Bound<Vec<u8>>, Bound<Vec<u8>>);
+
+// Byte bounds selecting every key of aspect `a` from `low` to `high`
+// inclusive, for use with any ordered map or tree of encoded keys.
+pub fn range_bounds(low: &Value, high: &Value, a: Aspect) -> Result<KeyBounds, LexDataError> {
+    let low = value_to_storage(low.clone(), a)?;
+    let high = value_to_storage(high.clone(), a)?;
+    Ok((Bound::Included(low), Bound::Included(high)))
+}
+
+// Keys compare as plain byte strings; this is the order every
+// encoding in this crate is designed to preserve.
+pub fn lex_cmp(a: &[u8], b: &[u8]) -> Ordering {
+    a.cmp(b)
+}
+
+// Whether two keys belong to one column, so that lex_cmp between them
+// compares values. This is the aspect the keys are tagged with, with
+// True and False as Boolean: numeric keys of any width share the
+// Numeric tag, and custom keys must share their tag.
+pub fn same_aspect(a: &[u8], b: &[u8]) -> Result<bool, LexDataError> {
+    Ok(key_tag(a)? == key_tag(b)?)
+}
+
+fn key_tag(bytes: &[u8]) -> Result<u8, LexDataError> {
+    match bytes.first() {
+        Some(tag) if *tag >= CUSTOM_TAG_MIN || is_boolean_tag(*tag) => Ok(*tag),
+        Some(tag) => match Aspect::from_u8(*tag) {
+            Some(Aspect::True | Aspect::False) => Ok(aspect_byte(Aspect::Boolean)),
+            Some(_) => Ok(*tag),
+            None => Err(LexDataError::UnexpectedAspect(format!(
+                "{tag} is not an aspect byte"
+            ))),
+        },
+        None => Err(LexDataError::TruncatedInput(
+            "An empty buffer holds no value".to_string(),
+        )),
+    }
+}
+
+// The shortest byte string s with smaller < s <= larger, for the
+// separators of B-tree splits. Anything shorter than one byte past the
+// common prefix is either a prefix of `smaller` or above `larger`, so
+// that much of `larger` is the answer.
+pub fn separator(smaller: &[u8], larger: &[u8]) -> Result<Vec<u8>, LexDataError> {
+    if smaller >= larger {
+        return Err(LexDataError::OutOfRange(
+            "A separator needs the first key to sort before the second".to_string(),
+        ));
+    }
+    let common = smaller
+        .iter()
+        .zip(larger)
+        .take_while(|(s, l)| s == l)
+        .count();
+    Ok(larger[..=common].to_vec())
+}
+
+// Sort values into the order of their encodings, failing on the first
+// value that cannot be encoded and leaving the slice untouched.
+pub fn sort_by_encoding(items: &mut [(Value, Aspect)]) -> Result<(), LexDataError> {
+    let mut keys = items
+        .iter()
+        .enumerate()
+        .map(|(i, (v, a))| Ok((value_to_storage(v.clone(), *a)?, i)))
+        .collect::<Result<Vec<_>, LexDataError>>()?;
+    keys.sort_by(|x, y| lex_cmp(&x.0, &y.0));
+    let sorted: Vec<(Value, Aspect)> = keys.iter().map(|(_, i)| items[*i].clone()).collect();
+    items.clone_from_slice(&sorted);
+    Ok(())
+}
+
+// Walk a block of concatenated keys and report the position of the
+// first adjacent pair that is out of order, i.e. Some(i) when key i
+// sorts after key i + 1.
+pub fn check_sorted(block: &[u8]) -> Result<Option<usize>, LexDataError> {
+    let ranges = field_ranges(block)?;
+    Ok(ranges.windows(2).position(|pair| {
+        lex_cmp(&block[pair[0].clone()], &block[pair[1].clone()]) == Ordering::Greater
+    }))
+}
+
+// Merge two sorted runs of keys into one sorted run, taking from a
+// first on ties so the merge is stable.
+pub fn merge_sorted(a: &[Vec<u8>], b: &[Vec<u8>]) -> Vec<Vec<u8>> {
+    let mut merged = Vec::with_capacity(a.len() + b.len());
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if lex_cmp(&a[i], &b[j]) == Ordering::Greater {
+            merged.push(b[j].clone());
+            j += 1;
+        } else {
+            merged.push(a[i].clone());
+            i += 1;
+        }
+    }
+    merged.extend_from_slice(&a[i..]);
+    merged.extend_from_slice(&b[j..]);
+    merged
+}
+
+// A query value encoded once, for comparing against many stored keys
+// in an index probe loop.
+pub struct Probe {
+    encoded: Vec<u8>,
+}
+
+impl Probe {
+    pub fn new(v: &Value, a: Aspect) -> Result<Probe, LexDataError> {
+        let encoded = value_to_storage(v.clone(), a)?;
+        Ok(Probe { encoded })
+    }
+
+    // How the stored key orders relative to the probe. This compares
+    // against raw keys, not another Probe, so it is not Ord::cmp.
+    #[allow(clippy::should_implement_trait)]
+    pub fn cmp(&self, key: &[u8]) -> Ordering {
+        lex_cmp(key, &self.encoded)
+    }
+}
+
+// CRC-32 (IEEE), computed bitwise as keys are short.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffff_u32;
+    for b in bytes.iter() {
+        crc ^= *b as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+// The checksum trails the value written as a field, so that strings
+// are terminated before it and ordering is unaffected. It is always
+// the last four bytes, and the value must fill the rest.
+pub fn encode_checked(v: &Value, a: Aspect) -> Result<Vec<u8>, LexDataError> {
+    let mut buf = field_to_storage(v.clone(), a)?;
+    let crc = crc32(&buf);
+    buf.write_u32::<BigEndian>(crc).unwrap();
+    Ok(buf)
+}
+
+pub fn decode_checked(bytes: &[u8]) -> Result<(Value, Aspect), LexDataError> {
+    if bytes.len() < 5 {
+        return Err(LexDataError::ChecksumMismatch(
+            "Buffer is too short to hold a value and checksum".to_string(),
+        ));
+    }
+    let (payload, checksum) = bytes.split_at(bytes.len() - 4);
+    let expected = u32::from_be_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]);
+    let actual = crc32(payload);
+    if expected != actual {
+        return Err(LexDataError::ChecksumMismatch(format!(
+            "Stored checksum {expected:08x} does not match computed {actual:08x}"
+        )));
+    }
+    decode_exact(payload)
+}
+
+// Make keys of a non-unique index unique by appending the row id as a
+// fixed width big endian suffix, so equal keys sort by row id. The
+// key must be self delimiting for that, so write strings with
+// field_to_storage.
+pub fn append_rowid(key: &mut Vec<u8>, rowid: u64) {
+    key.extend(rowid.to_be_bytes());
+}
+
+pub fn split_rowid(key: &[u8]) -> Result<(&[u8], u64), LexDataError> {
+    if key.len() < 8 {
+        return Err(LexDataError::TruncatedInput(format!(
+            "A row id needs 8 bytes but the key has {}",
+            key.len()
+        )));
+    }
+    let (key, rowid) = key.split_at(key.len() - 8);
+    Ok((key, u64::from_be_bytes(rowid.try_into().unwrap())))
+}
+
+// Keep the lexical form a value was given in after its key, as a u32
+// length and the text. Keys still order by value first. Equal values
+// then order by the length of their original text, and only then by
+// the text itself, as the length comes first.
+pub fn encode_with_original(v: &Value, a: Aspect, original: &str) -> Result<Vec<u8>, LexDataError> {
+    let mut buf = value_to_storage(v.clone(), a)?;
+    push_original(&mut buf, original)?;
+    Ok(buf)
+}
+
+pub fn decode_with_original(bytes: &[u8]) -> Result<(Value, Aspect, String), LexDataError> {
+    let (v, a, size) = decode_one(bytes)?;
+    let original = original_after(bytes, size)?;
+    Ok((v, a, original.to_string()))
+}
+
+// The u32 length and text that follow a key kept with its original
+// lexical form.
+fn push_original(buf: &mut Vec<u8>, original: &str) -> Result<(), LexDataError> {
+    let len = u32::try_from(original.len()).map_err(|_| {
+        LexDataError::OutOfRange(format!(
+            "The original text is {} bytes long",
+            original.len()
+        ))
+    })?;
+    buf.write_u32::<BigEndian>(len).unwrap();
+    buf.extend(original.as_bytes());
+    Ok(())
+}
+
+// The original text after the first `size` bytes of a key, which must
+// run to the end of the buffer.
+fn original_after(bytes: &[u8], size: usize) -> Result<&str, LexDataError> {
+    let (original, end) = original_at(bytes, size)?;
+    if end < bytes.len() {
+        return Err(LexDataError::TrailingBytes(format!(
+            "The original text takes {} bytes but {} remain",
+            original.len(),
+            bytes.len() - size - 4
+        )));
+    }
+    Ok(original)
+}
+
+// The original text after the first `size` bytes of a key, and where
+// it ends, so that further fields may follow.
+fn original_at(bytes: &[u8], size: usize) -> Result<(&str, usize), LexDataError> {
+    let len = match bytes.get(size..size + 4) {
+        Some(len) => u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize,
+        None => {
+            return Err(LexDataError::TruncatedInput(
+                "The original text has no length".to_string(),
+            ))
+        }
+    };
+    let end = size + 4 + len;
+    let original = match bytes.get(size + 4..end) {
+        Some(text) => text,
+        None => {
+            return Err(LexDataError::TruncatedInput(format!(
+                "The original text needs {len} bytes"
+            )))
+        }
+    };
+    from_utf8(original)
+        .map(|original| (original, end))
+        .map_err(|e| LexDataError::BadLexicalForm(e.to_string()))
+}
+
+const VARINT_CONTINUATION: u8 = 0b1000_0000;
+// Little-endian base 128, as the deltas are never compared lexically.
+fn varint_encode(mut n: u64, buf: &mut Vec<u8>) {
+    while n >= VARINT_CONTINUATION as u64 {
+        buf.push(n as u8 | VARINT_CONTINUATION);
+        n >>= 7;
+    }
+    buf.push(n as u8);
+}
+
+fn varint_decode(bytes: &[u8]) -> Option<(u64, usize)> {
+    let mut n: u64 = 0;
+    for (i, b) in bytes.iter().enumerate().take(10) {
+        n |= ((b & BASE_MASK) as u64) << (7 * i);
+        if b & VARINT_CONTINUATION == 0 {
+            return Some((n, i + 1));
+        }
+    }
+    None
+}
+
+// A sorted sequence is stored as its first value, in the Int64 layout,
+// followed by the varint differences between neighbours. These stay
+// small for dense columns such as those in front-coded blocks.
+pub fn encode_deltas(sorted: &[i64]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(sorted.len() + 8);
+    if let Some((first, rest)) = sorted.split_first() {
+        buf.write_i64::<BigEndian>(*first).unwrap();
+        buf[0] ^= BYTE_SIGN_MASK;
+        let mut previous = *first;
+        for i in rest {
+            varint_encode(i.wrapping_sub(previous) as u64, &mut buf);
+            previous = *i;
+        }
+    }
+    buf
+}
+
+pub fn decode_deltas(bytes: &[u8]) -> Result<Vec<i64>, LexDataError> {
+    if bytes.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut previous = match storage_to_int64(bytes)? {
+        Value::Int64(i) => i,
+        _ => panic!("Imposible return value from storage_to_int64"),
+    };
+    let mut result = vec![previous];
+    let mut offset = 8;
+    while offset < bytes.len() {
+        match varint_decode(&bytes[offset..]) {
+            Some((delta, len)) => {
+                previous = previous.wrapping_add(delta as i64);
+                result.push(previous);
+                offset += len;
+            }
+            None => {
+                return Err(LexDataError::BadDeltaLayout(
+                    "Delta sequence ends in the middle of a value".to_string(),
+                ))
+            }
+        }
+    }
+    Ok(result)
+}
+
+// The sign of an encoded number read straight from its layout,
+// without materializing the value. Both zeros of a float are Equal.
+pub fn encoded_sign(bytes: &[u8]) -> Result<Ordering, LexDataError> {
+    if bytes.len() < 2 {
+        return Err(LexDataError::TruncatedInput(
+            "An encoded number needs an aspect byte and a payload".to_string(),
+        ));
+    }
+    let a = byte_aspect(&bytes[0]);
+    if a == Aspect::True || a == Aspect::False {
+        return Err(LexDataError::UnexpectedAspect(format!(
+            "The aspect {a:?} is not numeric"
+        )));
+    }
+    let payload = &bytes[1..];
+    let storage_type = aspect_storage(a);
+    match storage_type {
+        StorageType::Int32 | StorageType::Int64 | StorageType::DateTime => {
+            let width = if storage_type == StorageType::Int32 {
+                4
+            } else {
+                8
+            };
+            let payload = fixed_width_payload(payload, width)?;
+            if payload[0] & BYTE_SIGN_MASK == 0 {
+                Ok(Ordering::Less)
+            } else if payload[0] == BYTE_SIGN_MASK && payload[1..].iter().all(|b| *b == 0) {
+                Ok(Ordering::Equal)
+            } else {
+                Ok(Ordering::Greater)
+            }
+        }
+        StorageType::Float16 | StorageType::Float32 | StorageType::Float64 => {
+            let width = match storage_type {
+                StorageType::Float16 => 2,
+                StorageType::Float32 => 4,
+                _ => 8,
+            };
+            let payload = fixed_width_payload(payload, width)?;
+            if payload[0] & BYTE_SIGN_MASK == 0 {
+                // negatives are complemented, so -0.0 is 0x7f followed by 0xff
+                if payload[0] == !BYTE_SIGN_MASK && payload[1..].iter().all(|b| *b == 0xff) {
+                    Ok(Ordering::Equal)
+                } else {
+                    Ok(Ordering::Less)
+                }
+            } else if payload[0] == BYTE_SIGN_MASK && payload[1..].iter().all(|b| *b == 0) {
+                Ok(Ordering::Equal)
+            } else {
+                Ok(Ordering::Greater)
+            }
+        }
+        StorageType::BigInt | StorageType::BigNum | StorageType::Numeric => {
+            let (is_pos, size, idx) = size_decode(payload);
+            let end = idx + size as usize;
+            if payload.len() < end {
+                return Err(LexDataError::TruncatedInput(format!(
+                    "The integer needs {end} bytes but only {} remain",
+                    payload.len()
+                )));
+            }
+            let zero_byte = if is_pos { 0x00 } else { 0xff };
+            let sign = if is_pos {
+                Ordering::Greater
+            } else {
+                Ordering::Less
+            };
+            if !payload[idx..end].iter().all(|b| *b == zero_byte) {
+                return Ok(sign);
+            }
+            if storage_type == StorageType::Numeric {
+                // a zero floor followed by a fraction is positive
+                return match payload.get(end) {
+                    Some(&NUMERIC_FRACTION) => Ok(Ordering::Greater),
+                    _ => Ok(Ordering::Equal),
+                };
+            }
+            if storage_type != StorageType::BigNum {
+                return Ok(Ordering::Equal);
+            }
+            // A zero integer part, so the fraction decides. The codes
+            // for "0" and "00" are 1 and 2 (0 is the empty fraction).
+            for byte in payload[end..].iter() {
+                let byte = if is_pos { *byte } else { !*byte };
+                if byte >> 1 > 2 {
+                    return Ok(sign);
+                }
+                if byte & 1 == 0 {
+                    break;
+                }
+            }
+            Ok(Ordering::Equal)
+        }
+        StorageType::SizedInt => match read_sized_int(payload)? {
+            0 => Ok(Ordering::Equal),
+            i if i < 0 => Ok(Ordering::Less),
+            _ => Ok(Ordering::Greater),
+        },
+        StorageType::UInt32 => {
+            if fixed_width_payload(payload, 4)?.iter().all(|b| *b == 0) {
+                Ok(Ordering::Equal)
+            } else {
+                Ok(Ordering::Greater)
+            }
+        }
+        StorageType::Rational => {
+            // the first term is the floor, so a zero floor followed by
+            // further terms is a positive fraction
+            let (floor, size) = rational_term(payload)?;
+            match floor.cmp0() {
+                Ordering::Equal if payload.get(size) == Some(&RATIONAL_TERM) => {
+                    Ok(Ordering::Greater)
+                }
+                sign => Ok(sign),
+            }
+        }
+        StorageType::DecimalExponent => match payload[0] {
+            EXPONENT_NEGATIVE => Ok(Ordering::Less),
+            EXPONENT_ZERO => Ok(Ordering::Equal),
+            EXPONENT_POSITIVE => Ok(Ordering::Greater),
+            tag => Err(LexDataError::BadDecimalFormat(format!(
+                "{tag} is not a decimal sign tag"
+            ))),
+        },
+        StorageType::String
+        | StorageType::Bitmask
+        | StorageType::HexBinary
+        | StorageType::Boolean
+        | StorageType::Time
+        | StorageType::GMonth
+        | StorageType::Custom
+        | StorageType::Version
+        | StorageType::DateTimeStamp
+        | StorageType::PrefixedString
+        | StorageType::OrderedURI => Err(LexDataError::UnexpectedAspect(format!(
+            "The aspect {a:?} is not numeric"
+        ))),
+    }
+}
+
+fn fixed_width_payload(payload: &[u8], width: usize) -> Result<&[u8], LexDataError> {
+    if payload.len() < width {
+        Err(LexDataError::TruncatedInput(format!(
+            "Expected {width} bytes but only {} were given",
+            payload.len()
+        )))
+    } else {
+        Ok(&payload[..width])
+    }
+}
+
+// Low cardinality strings are stored as their index in a sorted
+// dictionary, a fixed-width u32, so ordinals sort like the strings.
+// The aspect must be stored as UInt32, such as UnsignedInt, so the key
+// also decodes as the ordinal.
+pub fn encode_enum(index: u32, dictionary: &[&str], a: Aspect) -> Result<Vec<u8>, LexDataError> {
+    check_dictionary(dictionary)?;
+    if index as usize >= dictionary.len() {
+        return Err(LexDataError::OutOfRange(format!(
+            "{index} is out of range for a dictionary of {} entries",
+            dictionary.len()
+        )));
+    }
+    if try_aspect_storage(a) != Some(StorageType::UInt32) {
+        return Err(LexDataError::UnexpectedAspect(format!(
+            "Enumerations are stored as UInt32, which {a:?} is not"
+        )));
+    }
+    uint32_to_storage(index.into(), a)
+}
+
+pub fn decode_enum(bytes: &[u8], dictionary: &[&str]) -> Result<Value, LexDataError> {
+    check_dictionary(dictionary)?;
+    if bytes.len() < 5 {
+        return Err(LexDataError::TruncatedInput(format!(
+            "Enumerations need 5 bytes but only {} were given",
+            bytes.len()
+        )));
+    }
+    if Aspect::from_u8(bytes[0]).and_then(try_aspect_storage) != Some(StorageType::UInt32) {
+        return Err(LexDataError::UnexpectedAspect(format!(
+            "Enumerations are stored as UInt32, not under the byte {}",
+            bytes[0]
+        )));
+    }
+    let index = read_uint32(&bytes[1..])?;
+    match dictionary.get(index as usize) {
+        Some(s) => Ok(Value::String(s.to_string())),
+        None => Err(LexDataError::OutOfRange(format!(
+            "{index} is out of range for a dictionary of {} entries",
+            dictionary.len()
+        ))),
+    }
+}
+
+fn check_dictionary(dictionary: &[&str]) -> Result<(), LexDataError> {
+    match dictionary.windows(2).position(|pair| pair[0] >= pair[1]) {
+        Some(i) => Err(LexDataError::UnsortedDictionary(format!(
+            "{:?} does not sort strictly before {:?}",
+            dictionary[i],
+            dictionary[i + 1]
+        ))),
+        None => Ok(()),
+    }
+}
+
+// Money is kept as integer minor units (e.g. cents) in the Int64
+// layout, so amounts of the same scale sort by value and never pass
+// through the BCD fraction path. The scale is not stored: it belongs
+// to the column, and the reader supplies it to decode. The aspect must
+// be stored as Int64, so the key also decodes as the plain count of
+// minor units.
+pub fn money_to_storage(minor_units: i64, a: Aspect) -> Result<Vec<u8>, LexDataError> {
+    fixed_point_to_storage(minor_units, a)
+}
+
+pub fn money_from_storage(bytes: &[u8], scale: u32) -> Result<String, LexDataError> {
+    let minor_units = storage_to_fixed_point(bytes)?;
+    Ok(fixed_point_string(minor_units, scale))
+}
+
+// Ratios use the same layout as money, but decode to the canonical
+// decimal rather than keeping every digit of the scale. The ratio must
+// lie between 0 and 1, so the numerator between 0 and 10^scale.
+pub fn fixed_ratio_to_storage(
+    numerator: i64,
+    scale: u32,
+    a: Aspect,
+) -> Result<Vec<u8>, LexDataError> {
+    // past 10^18 every non-negative i64 is below the denominator
+    let in_range = match 10_i64.checked_pow(scale) {
+        Some(denominator) => (0..=denominator).contains(&numerator),
+        None => numerator >= 0,
+    };
+    if !in_range {
+        return Err(LexDataError::OutOfRange(format!(
+            "{numerator} at scale {scale} is not a ratio between 0 and 1"
+        )));
+    }
+    fixed_point_to_storage(numerator, a)
+}
+
+pub fn fixed_ratio_from_storage(bytes: &[u8], scale: u32) -> Result<String, LexDataError> {
+    let numerator = storage_to_fixed_point(bytes)?;
+    canonical_decimal(&fixed_point_string(numerator, scale))
+}
+
+fn fixed_point_to_storage(units: i64, a: Aspect) -> Result<Vec<u8>, LexDataError> {
+    if try_aspect_storage(a) != Some(StorageType::Int64) {
+        return Err(LexDataError::UnexpectedAspect(format!(
+            "Fixed point values are stored as Int64, which {a:?} is not"
+        )));
+    }
+    int64_to_storage(units, a)
+}
+
+fn storage_to_fixed_point(bytes: &[u8]) -> Result<i64, LexDataError> {
+    if bytes.len() < 9 {
+        return Err(LexDataError::TruncatedInput(format!(
+            "Fixed point values need 9 bytes but only {} were given",
+            bytes.len()
+        )));
+    }
+    let is_int64 = Aspect::from_u8(bytes[0]).and_then(try_aspect_storage);
+    if is_int64 != Some(StorageType::Int64) {
+        return Err(LexDataError::UnexpectedAspect(format!(
+            "Fixed point values are stored as Int64, not under the byte {}",
+            bytes[0]
+        )));
+    }
+    read_int64(&bytes[1..9])
+}
+
+// Render integer units as a decimal with exactly `scale` fraction digits
+fn fixed_point_string(units: i64, scale: u32) -> String {
+    let scale = scale as usize;
+    let digits = format!("{:0>width$}", units.unsigned_abs(), width = scale + 1);
+    let sign = if units < 0 { "-" } else { "" };
+    if scale == 0 {
+        format!("{sign}{digits}")
+    } else {
+        let (whole, fraction) = digits.split_at(digits.len() - scale);
+        format!("{sign}{whole}.{fraction}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(v: Value, a: Aspect) -> (Value, Aspect) {
+        let storage = value_to_storage(v, a);
+        let bytes = Bytes::from(storage.unwrap());
+        storage_to_value(bytes).unwrap()
+    }
+
+    #[test]
+    fn string_round_trip() {
+        let res = round_trip(Value::String("test".to_string()), Aspect::String);
+        let (v, a) = res;
+        assert_eq!(v, Value::String("test".to_string()));
+        assert_eq!(a, Aspect::String);
+        let res = round_trip(Value::String("test".to_string()), Aspect::Token);
+        let (v, a) = res;
+        assert_eq!(v, Value::String("test".to_string()));
+        assert_eq!(a, Aspect::Token);
+        let res = round_trip(Value::String("test".to_string()), Aspect::ID);
+        let (v, a) = res;
+        assert_eq!(v, Value::String("test".to_string()));
+        assert_eq!(a, Aspect::ID);
+        assert!(value_to_storage(Value::from("a\0b"), Aspect::String).is_err());
+    }
+
+    #[test]
+    fn string_ordering() {
+        let strings = [
+            "entertain",
+            "zig",
+            "pangolin",
+            "penguin",
+            "peaches",
+            "plums",
+            "pears",
+            "apple",
+            "candy",
+        ];
+        let mut byte_vec = Vec::with_capacity(strings.len());
         for s in strings.iter() {
             let string: &str = s;
             let storage =
-                value_to_storage(Value::String(string.to_string()), Aspect::String).unwrap();
-            byte_vec.push(storage)
+                value_to_storage(Value::String(string.to_string()), Aspect::String).unwrap();
+            byte_vec.push(storage)
+        }
+        byte_vec.sort();
+        let mut result_vec = Vec::with_capacity(strings.len());
+        for b in byte_vec.iter() {
+            let bytes = Bytes::from(b.clone());
+            let (value, _) = storage_to_value(bytes).unwrap();
+            if let Value::String(v) = value {
+                result_vec.push(v)
+            } else {
+                panic!("This can't happen")
+            }
+        }
+        assert_eq!(
+            vec![
+                "apple",
+                "candy",
+                "entertain",
+                "pangolin",
+                "peaches",
+                "pears",
+                "penguin",
+                "plums",
+                "zig"
+            ],
+            result_vec
+        )
+    }
+
+    #[test]
+    fn boolean_round_trip() {
+        let res = round_trip(Value::Boolean(false), Aspect::Boolean);
+        assert_eq!((Value::Boolean(false), Aspect::Boolean), res);
+        let res = round_trip(Value::Boolean(true), Aspect::Boolean);
+        assert_eq!((Value::Boolean(true), Aspect::Boolean), res);
+    }
+
+    #[test]
+    fn float32_round_trip() {
+        let res = round_trip(Value::Float32(-10.87_f32), Aspect::Float);
+        assert_eq!((Value::Float32(-10.87_f32), Aspect::Float), res);
+    }
+
+    #[test]
+    fn float32_ordering() {
+        let floats = [
+            32.5_f32,
+            33432.53_f32,
+            f32::INFINITY,
+            -132.98701_f32,
+            f32::MIN,
+            -100.3_f32,
+            f32::NEG_INFINITY,
+            22.5_f32,
+            0_f32,
+            f32::MAX,
+            0.1_f32,
+            -0.1_f32,
+        ];
+        let mut byte_vec = Vec::with_capacity(floats.len());
+        for f in floats.iter() {
+            let storage = value_to_storage(Value::Float32(*f), Aspect::Float).unwrap();
+            byte_vec.push(storage)
+        }
+        byte_vec.sort();
+        let mut result_vec = Vec::with_capacity(floats.len());
+        for b in byte_vec.iter() {
+            let bytes = Bytes::from(b.clone());
+            let (value, _) = storage_to_value(bytes).unwrap();
+            if let Value::Float32(v) = value {
+                result_vec.push(v)
+            } else {
+                panic!("This can't happen")
+            }
+        }
+        assert_eq!(
+            vec![
+                f32::NEG_INFINITY,
+                f32::MIN,
+                -132.98701,
+                -100.3,
+                -0.1,
+                0.0,
+                0.1,
+                22.5,
+                32.5,
+                33432.53,
+                f32::MAX,
+                f32::INFINITY,
+            ],
+            result_vec
+        )
+    }
+
+    #[test]
+    fn float64_ordering() {
+        let floats = [
+            64.5_f64,
+            33464.53_f64,
+            f64::INFINITY,
+            -164.98701_f64,
+            f64::MIN,
+            -100.3_f64,
+            33464.533432_f64,
+            f64::NEG_INFINITY,
+            22.5_f64,
+            f64::MAX,
+            -1e308_f64,
+            0_f64,
+            0.1_f64,
+            -0.1_f64,
+        ];
+        let mut byte_vec = Vec::with_capacity(floats.len());
+        for f in floats.iter() {
+            let storage = value_to_storage(Value::Float64(*f), Aspect::Double).unwrap();
+            byte_vec.push(storage)
+        }
+        byte_vec.sort();
+        let mut result_vec = Vec::with_capacity(floats.len());
+        for b in byte_vec.iter() {
+            let bytes = Bytes::from(b.clone());
+            let (value, _) = storage_to_value(bytes).unwrap();
+            if let Value::Float64(v) = value {
+                result_vec.push(v)
+            } else {
+                panic!("This can't happen")
+            }
+        }
+        assert_eq!(
+            vec![
+                f64::NEG_INFINITY,
+                f64::MIN,
+                -1e308,
+                -164.98701,
+                -100.3,
+                -0.1,
+                0.0,
+                0.1,
+                22.5,
+                64.5,
+                33464.53,
+                33464.533432,
+                f64::MAX,
+                f64::INFINITY
+            ],
+            result_vec
+        )
+    }
+
+    #[test]
+    fn int32_ordering() {
+        let int = [64_i32, 33464, 164, -100, 22, 0, 1, -1];
+        let mut byte_vec = Vec::with_capacity(int.len());
+        for f in int.iter() {
+            let storage = value_to_storage(Value::Int32(*f), Aspect::Int).unwrap();
+            byte_vec.push(storage)
+        }
+        byte_vec.sort();
+        let mut result_vec = Vec::with_capacity(int.len());
+        for b in byte_vec.iter() {
+            let bytes = Bytes::from(b.clone());
+            let (value, _) = storage_to_value(bytes).unwrap();
+            if let Value::Int32(v) = value {
+                result_vec.push(v)
+            } else {
+                panic!("This can't happen")
+            }
+        }
+        assert_eq!(vec![-100, -1, 0, 1, 22, 64, 164, 33464], result_vec)
+    }
+
+    #[test]
+    fn int64_ordering() {
+        let int = [64_i64, 33464, 164, -100, -234234322, 22, 0, 1, -1];
+        let mut byte_vec = Vec::with_capacity(int.len());
+        for f in int.iter() {
+            let storage = value_to_storage(Value::Int64(*f), Aspect::Long).unwrap();
+            byte_vec.push(storage)
+        }
+        byte_vec.sort();
+        let mut result_vec = Vec::with_capacity(int.len());
+        for b in byte_vec.iter() {
+            let bytes = Bytes::from(b.clone());
+            let (value, _) = storage_to_value(bytes).unwrap();
+            if let Value::Int64(v) = value {
+                result_vec.push(v)
+            } else {
+                panic!("This can't happen")
+            }
+        }
+        assert_eq!(
+            vec![-234234322, -100, -1, 0, 1, 22, 64, 164, 33464],
+            result_vec
+        )
+    }
+
+    #[test]
+    fn size_encode_correct() {
+        let bytes = size_encode(1);
+        assert_eq!(bytes, vec![129]);
+
+        let (is_pos, size, idx) = size_decode(&bytes);
+        assert!(is_pos);
+        assert_eq!(size, 1);
+        assert_eq!(idx, 1);
+
+        let bytes = size_encode(12);
+        assert_eq!(bytes, vec![140]);
+
+        let bytes = size_encode(0);
+        assert_eq!(bytes, vec![128]);
+
+        let bytes = size_encode(0);
+        let (is_pos, size, idx) = size_decode(&bytes);
+        assert!(is_pos);
+        assert_eq!(size, 0);
+        assert_eq!(idx, 1);
+    }
+
+    #[test]
+    fn bigint_roundtrip_negative() {
+        let minus_one = "-1".parse::<Integer>().unwrap();
+        let res = round_trip(Value::BigInt(minus_one.clone()), Aspect::Integer);
+        assert_eq!((Value::BigInt(minus_one), Aspect::Integer), res);
+
+        let minus_two_hundred = "-200".parse::<Integer>().unwrap();
+        let res = round_trip(Value::BigInt(minus_two_hundred.clone()), Aspect::Integer);
+        assert_eq!((Value::BigInt(minus_two_hundred), Aspect::Integer), res);
+    }
+
+    #[test]
+    fn bigint_ordering() {
+        let int_strings = [
+            "64",
+            "33464",
+            "164",
+            "-100",
+            "256",
+            "-923423234234322",
+            "22",
+            "0",
+            "1",
+            "-1",
+            "234987394839323",
+        ];
+        let int = int_strings.map(|s| s.parse::<Integer>().unwrap());
+        let mut byte_vec = Vec::with_capacity(int.len());
+        for i in int.iter() {
+            let storage = value_to_storage(Value::BigInt(i.clone()), Aspect::Integer).unwrap();
+            byte_vec.push(storage)
+        }
+        byte_vec.sort();
+        let mut result_vec = Vec::with_capacity(int.len());
+        for b in byte_vec.iter() {
+            let bytes = Bytes::from(b.clone());
+            let (value, _) = storage_to_value(bytes).unwrap();
+            if let Value::BigInt(v) = value {
+                let res = format!("{v:?}");
+                result_vec.push(res)
+            } else {
+                panic!("This can't happen")
+            }
+        }
+        assert_eq!(
+            vec![
+                "-923423234234322",
+                "-100",
+                "-1",
+                "0",
+                "1",
+                "22",
+                "64",
+                "164",
+                "256",
+                "33464",
+                "234987394839323"
+            ],
+            result_vec
+        )
+    }
+
+    #[test]
+    fn float64_round_trip() {
+        let res = round_trip(Value::Float64(-10.87_f64), Aspect::Double);
+        assert_eq!((Value::Float64(-10.87_f64), Aspect::Double), res);
+    }
+
+    #[test]
+    fn fraction_round_trip() {
+        let s = "12325";
+        let f = encode_fraction(Some(s));
+        let original = decode_fraction(&f);
+        assert_eq!(s, original);
+    }
+
+    #[test]
+    fn fraction_round_trip_empty() {
+        let s = "";
+        let f = encode_fraction(Some(s));
+        let original = decode_fraction(&f);
+        assert_eq!(s, original);
+    }
+
+    #[test]
+    fn fraction_trailing_zero() {
+        let s = "10";
+        let f = encode_fraction(Some(s));
+        let original = decode_fraction(&f);
+        assert_eq!(s, original);
+    }
+
+    #[test]
+    fn fraction_mid_one() {
+        let s = "101";
+        let f = encode_fraction(Some(s));
+        let original = decode_fraction(&f);
+        assert_eq!(s, original);
+    }
+
+    #[test]
+    fn fraction_leading_zeros() {
+        let s = "001";
+        let f = encode_fraction(Some(s));
+        let original = decode_fraction(&f);
+        assert_eq!(s, original);
+    }
+
+    #[test]
+    fn centary_encoding() {
+        let s = "0";
+        let r = centary_decimal_encode(s);
+        let d = centary_decimal_decode(r);
+        assert_eq!(s, d);
+
+        let s = "00";
+        let r = centary_decimal_encode(s);
+        let d = centary_decimal_decode(r);
+        assert_eq!(s, d);
+
+        let s = "01";
+        let r = centary_decimal_encode(s);
+        let d = centary_decimal_decode(r);
+        assert_eq!(s, d);
+
+        let s = "11";
+        let r = centary_decimal_encode(s);
+        let d = centary_decimal_decode(r);
+        assert_eq!(s, d);
+
+        let s = "22";
+        let r = centary_decimal_encode(s);
+        let d = centary_decimal_decode(r);
+        assert_eq!(s, d);
+
+        let s = "9";
+        let r = centary_decimal_encode(s);
+        let d = centary_decimal_decode(r);
+        assert_eq!(s, d);
+
+        let s = "99";
+        let r = centary_decimal_encode(s);
+        let d = centary_decimal_decode(r);
+        assert_eq!(s, d);
+    }
+
+    #[test]
+    fn order_fractions() {
+        let fractions = vec!["1234", "123", "100", "10000", "32"];
+        let mut encodes: Vec<_> = fractions.iter().map(|x| encode_fraction(Some(x))).collect();
+        encodes.sort();
+        let results: Vec<_> = encodes.iter().map(|x| decode_fraction(x)).collect();
+        assert_eq!(vec!["100", "10000", "123", "1234", "32"], results);
+    }
+
+    #[test]
+    fn negative_zero() {
+        let negative_zero = vec![NEGATIVE_ZERO];
+        let (is_pos, size, idx) = size_decode(&negative_zero);
+        assert!(!is_pos);
+        assert_eq!(size, 0);
+        assert_eq!(idx, 1);
+    }
+
+    #[test]
+    fn bignum_round_trip() {
+        let fractions = vec![
+            "1234.2343",
+            "987.23",
+            "-0.001",
+            "-10.3",
+            "-3233.23423",
+            "-0.0",
+            "0",
+            "0.0",
+            "0.100",
+            "10000.33",
+            "0.333",
+            "-9871234.1928374",
+        ];
+        let mut encodes: Vec<_> = fractions
+            .iter()
+            .map(|x| bignum_to_storage(x.to_string(), Aspect::Decimal).unwrap())
+            .collect();
+        encodes.sort();
+        let results: Vec<_> = encodes
+            .iter()
+            .map(|x| {
+                let res = storage_to_bignum(&x[1..x.len()]).unwrap();
+                match res {
+                    Value::String(s) => s,
+                    _ => panic!("Can't be here"),
+                }
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                "-9871234.1928374",
+                "-3233.23423",
+                "-10.3",
+                "-0.001",
+                "-0.0",
+                "0",
+                "0.0",
+                "0.100",
+                "0.333",
+                "987.23",
+                "1234.2343",
+                "10000.33"
+            ],
+            results
+        );
+    }
+
+    #[test]
+    fn date_time_round_trip() {
+        let res = round_trip(
+            Value::String("2007-03-01T13:00:00Z".to_string()),
+            Aspect::DateTime,
+        );
+        assert_eq!(
+            (
+                date_time_value("2007-03-01T13:00:00Z").unwrap(),
+                Aspect::DateTime
+            ),
+            res
+        );
+    }
+
+    #[test]
+    fn date_time_ordering() {
+        let dates = vec![
+            "2525-03-01T12:00:00Z",
+            "2007-03-01T13:00:00Z",
+            "1977-05-07T11:30:20Z",
+        ];
+        let mut date_bytes: Vec<_> = dates
+            .iter()
+            .map(|x| value_to_storage(Value::String(x.to_string()), Aspect::DateTime).unwrap())
+            .collect();
+        date_bytes.sort();
+        let dates_sorted: Vec<_> = date_bytes
+            .iter()
+            .map(|x| {
+                let res = &*x;
+                let bytes: Bytes = Bytes::from(res.clone());
+                let (res, _aspect) = storage_to_value(bytes).unwrap();
+                match res {
+                    Value::DateTime(date) => date.to_rfc3339_opts(SecondsFormat::Secs, true),
+                    _ => panic!("Didn't work"),
+                }
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                "1977-05-07T11:30:20Z",
+                "2007-03-01T13:00:00Z",
+                "2525-03-01T12:00:00Z"
+            ],
+            dates_sorted
+        );
+    }
+
+    #[test]
+    fn self_termination_tests() {
+        // we need to know we are not relying on the length of an array anywhere.
+        let mut num = value_to_storage(
+            Value::BigInt("-3233".parse::<Integer>().unwrap()),
+            Aspect::Integer,
+        )
+        .unwrap();
+        let garbage = vec![23, 35, 128];
+        num.extend(garbage);
+        let (res, _) = storage_to_value(Bytes::from(num)).unwrap();
+        match res {
+            Value::BigInt(i) => {
+                let rendered = format!("{i:?}");
+                assert_eq!(rendered, "-3233");
+            }
+            _ => panic!("This is not good"),
+        }
+
+        let mut num =
+            value_to_storage(Value::String("-3233.23423".to_string()), Aspect::Decimal).unwrap();
+        let garbage = vec![183, 35, 128];
+        num.extend(garbage);
+        let (res, _) = storage_to_value(Bytes::from(num)).unwrap();
+        match res {
+            Value::String(n) => {
+                assert_eq!("-3233.23423".to_string(), n);
+            }
+            _ => panic!("This is not good"),
+        }
+    }
+
+    #[test]
+    fn delta_round_trip() {
+        let sorted = vec![-300_i64, -12, 0, 5, 6, 130, 1000, 1001, 1_000_000];
+        let encoded = encode_deltas(&sorted);
+        assert!(encoded.len() < sorted.len() * 8);
+        assert_eq!(sorted, decode_deltas(&encoded).unwrap());
+
+        assert!(encode_deltas(&[]).is_empty());
+        assert!(decode_deltas(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn boolean_followed_by_value() {
+        let mut buf = value_to_storage(Value::Boolean(true), Aspect::Boolean).unwrap();
+        buf.extend(value_to_storage(Value::Int32(-5), Aspect::Int).unwrap());
+        assert_eq!(storage_size(Bytes::from(buf.clone())), 1);
+
+        let (value, aspect, size) = decode_one(&buf).unwrap();
+        assert_eq!(
+            (Value::Boolean(true), Aspect::Boolean, 1),
+            (value, aspect, size)
+        );
+        let (value, aspect, size) = decode_one(&buf[size..]).unwrap();
+        assert_eq!((Value::Int32(-5), Aspect::Int, 5), (value, aspect, size));
+        // untrusted input is an error rather than a panic
+        assert!(matches!(
+            decode_one(&[]),
+            Err(LexDataError::TruncatedInput(_))
+        ));
+        assert!(matches!(
+            decode_one(&[0x7f, 1]),
+            Err(LexDataError::UnexpectedAspect(_))
+        ));
+        assert!(decode_exact(&[0x7f]).is_err());
+        assert!(field_ranges(&[0x7f]).is_err());
+        assert!(decode_tuple(&[0x7f]).is_err());
+    }
+
+    #[test]
+    fn checksum_detects_corruption() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+
+        let v = Value::String("-3233.23423".to_string());
+        let mut checked = encode_checked(&v, Aspect::Decimal).unwrap();
+        let plain = value_to_storage(v.clone(), Aspect::Decimal).unwrap();
+        assert!(checked.starts_with(&plain));
+        assert_eq!((v, Aspect::Decimal), decode_checked(&checked).unwrap());
+
+        checked[3] ^= 0b0001_0000;
+        assert!(matches!(
+            decode_checked(&checked),
+            Err(LexDataError::ChecksumMismatch(_))
+        ));
+
+        // strings end before their checksum, so prefixes still sort first
+        let strings = ["ab", "abc", "abd", "b"];
+        let keys: Vec<Vec<u8>> = strings
+            .iter()
+            .map(|s| encode_checked(&Value::from(*s), Aspect::String).unwrap())
+            .collect();
+        for pair in keys.windows(2) {
+            assert!(pair[0] < pair[1], "{pair:?}");
+        }
+        for (s, key) in strings.iter().zip(&keys) {
+            assert_eq!(
+                decode_checked(key).unwrap(),
+                (Value::from(*s), Aspect::String)
+            );
+        }
+    }
+
+    #[test]
+    fn compound_key_field_ranges() {
+        let int = value_to_storage(Value::Int32(42), Aspect::Int).unwrap();
+        let bigint = value_to_storage(
+            Value::BigInt("-923423234234322".parse::<Integer>().unwrap()),
+            Aspect::Integer,
+        )
+        .unwrap();
+        let decimal =
+            value_to_storage(Value::String("-3233.23423".to_string()), Aspect::Decimal).unwrap();
+        let key = [int.clone(), bigint.clone(), decimal.clone()].concat();
+
+        let ranges = field_ranges(&key).unwrap();
+        assert_eq!(ranges.len(), 3);
+        assert_eq!(&key[ranges[0].clone()], &int[..]);
+        assert_eq!(&key[ranges[1].clone()], &bigint[..]);
+        assert_eq!(&key[ranges[2].clone()], &decimal[..]);
+
+        let truncated = [&int[..], &bigint[..bigint.len() - 2]].concat();
+        match field_ranges(&truncated) {
+            Err(LexDataError::FieldError { index, source }) => {
+                assert_eq!(index, 1);
+                assert!(matches!(*source, LexDataError::TruncatedInput(_)));
+            }
+            other => panic!("expected a field error, got {other:?}"),
+        }
+        let unknown = [&int[..], &[0x7f]].concat();
+        assert!(matches!(
+            field_ranges(&unknown),
+            Err(LexDataError::FieldError { index: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn date_time_verbatim_round_trip() {
+        let dates = vec![
+            "2007-03-01T13:00:01Z",
+            "2007-03-01T14:00:00+01:00",
+            "2007-03-01T13:00:00.000+00:00",
+            "2007-03-01T12:59:59Z",
+            "2007-03-01T13:00:00Z",
+        ];
+        let mut encoded: Vec<_> = dates
+            .iter()
+            .map(|d| date_time_to_storage_verbatim(d.to_string(), Aspect::DateTime).unwrap())
+            .collect();
+        encoded.sort();
+        let decoded: Vec<_> = encoded
+            .iter()
+            .map(|e| match storage_to_date_time_verbatim(e).unwrap() {
+                Value::String(s) => s,
+                _ => panic!("Didn't work"),
+            })
+            .collect();
+        assert_eq!(decoded[0], "2007-03-01T12:59:59Z");
+        assert_eq!(decoded[4], "2007-03-01T13:00:01Z");
+        let mut same_instant = decoded[1..4].to_vec();
+        same_instant.sort();
+        assert_eq!(
+            vec![
+                "2007-03-01T13:00:00.000+00:00",
+                "2007-03-01T13:00:00Z",
+                "2007-03-01T14:00:00+01:00"
+            ],
+            same_instant
+        );
+        // the text follows the instant, which still decodes on its own
+        let key = &encoded[2];
+        assert_eq!(storage_size_checked(key).unwrap(), 9);
+        assert_eq!(
+            decode_one(key).unwrap().0,
+            date_time_value("2007-03-01T13:00:00Z").unwrap()
+        );
+        assert!(storage_to_date_time_verbatim(&key[..key.len() - 1]).is_err());
+        let int = value_to_storage(Value::Int64(1), Aspect::Long).unwrap();
+        assert!(storage_to_date_time_verbatim(&int).is_err());
+    }
+
+    #[test]
+    fn canonical_lexical_forms() {
+        let cases = vec![
+            (Value::Int32(7), Aspect::Int, "7"),
+            (Value::Int64(0), Aspect::Long, "0"),
+            (Value::Int64(0), Aspect::DateTime, "1970-01-01T00:00:00Z"),
+            (
+                Value::BigInt("-0042".parse::<Integer>().unwrap()),
+                Aspect::Integer,
+                "-42",
+            ),
+            (Value::String("007.500".to_string()), Aspect::Decimal, "7.5"),
+            (Value::String("-0.0".to_string()), Aspect::Decimal, "0"),
+            (Value::String("+3.0".to_string()), Aspect::Decimal, "3"),
+            (Value::Boolean(true), Aspect::Boolean, "true"),
+            (Value::Float64(100.0), Aspect::Double, "1.0E2"),
+            (Value::Float32(0.1), Aspect::Float, "1.0E-1"),
+            (Value::Float64(f64::NEG_INFINITY), Aspect::Double, "-INF"),
+            (
+                Value::String("2007-03-01T14:00:00+01:00".to_string()),
+                Aspect::DateTime,
+                "2007-03-01T13:00:00Z",
+            ),
+            (Value::String(" a b ".to_string()), Aspect::String, " a b "),
+        ];
+        for (v, a, expected) in cases {
+            assert_eq!(expected, canonical_lexical(&v, a).unwrap());
+        }
+        assert!(matches!(
+            canonical_lexical(&Value::String("1.2.3".to_string()), Aspect::Decimal),
+            Err(LexDataError::BadDecimalFormat(_))
+        ));
+    }
+
+    #[test]
+    fn short_and_byte_ranges_checked_on_decode() {
+        let res = round_trip(Value::Int32(-32768), Aspect::Short);
+        assert_eq!(res, (Value::Int32(-32768), Aspect::Short));
+        let res = round_trip(Value::Int32(127), Aspect::Byte);
+        assert_eq!(res, (Value::Int32(127), Aspect::Byte));
+
+        let storage = value_to_storage(Value::Int32(40000), Aspect::Short).unwrap();
+        assert!(matches!(
+            storage_to_value(Bytes::from(storage)),
+            Err(LexDataError::OutOfRange(_))
+        ));
+        let storage = value_to_storage(Value::Int32(-129), Aspect::Byte).unwrap();
+        assert!(matches!(
+            storage_to_value(Bytes::from(storage)),
+            Err(LexDataError::OutOfRange(_))
+        ));
+        let storage = value_to_storage(Value::Int32(40000), Aspect::Int).unwrap();
+        assert!(storage_to_value(Bytes::from(storage)).is_ok());
+    }
+
+    #[test]
+    fn money_sorts_by_amount() {
+        let amounts = [250, -1, 0, i64::MIN, -100000, 5, i64::MAX, -250, 99];
+        let mut encoded: Vec<_> = amounts
+            .iter()
+            .map(|m| money_to_storage(*m, Aspect::Long).unwrap())
+            .collect();
+        encoded.sort();
+        let decoded: Vec<_> = encoded
+            .iter()
+            .map(|e| money_from_storage(e, 2).unwrap())
+            .collect();
+        assert_eq!(
+            decoded,
+            vec![
+                "-92233720368547758.08",
+                "-1000.00",
+                "-2.50",
+                "-0.01",
+                "0.00",
+                "0.05",
+                "0.99",
+                "2.50",
+                "92233720368547758.07",
+            ]
+        );
+        let encoded = money_to_storage(1234, Aspect::Long).unwrap();
+        assert_eq!(money_from_storage(&encoded, 0).unwrap(), "1234");
+        assert!(money_from_storage(&encoded[..5], 0).is_err());
+        // the key is an ordinary long
+        assert_eq!(
+            decode_exact(&encoded).unwrap(),
+            (Value::Int64(1234), Aspect::Long)
+        );
+        assert!(matches!(
+            money_to_storage(1234, Aspect::Decimal),
+            Err(LexDataError::UnexpectedAspect(_))
+        ));
+        let int = value_to_storage(Value::Int32(1234), Aspect::Int).unwrap();
+        assert!(money_from_storage(&[int, vec![0; 4]].concat(), 0).is_err());
+    }
+
+    #[test]
+    fn encoded_sign_without_decoding() {
+        let cases = vec![
+            (Value::Int32(-3), Aspect::Int, Ordering::Less),
+            (Value::Int32(0), Aspect::Int, Ordering::Equal),
+            (Value::Int32(3), Aspect::Int, Ordering::Greater),
+            (Value::Int64(-3), Aspect::Long, Ordering::Less),
+            (Value::Int64(0), Aspect::Long, Ordering::Equal),
+            (Value::Int64(3), Aspect::Long, Ordering::Greater),
+            (Value::Float32(-0.5), Aspect::Float, Ordering::Less),
+            (Value::Float32(-0.0), Aspect::Float, Ordering::Equal),
+            (Value::Float32(0.0), Aspect::Float, Ordering::Equal),
+            (Value::Float32(0.5), Aspect::Float, Ordering::Greater),
+            (
+                Value::Float64(f64::NEG_INFINITY),
+                Aspect::Double,
+                Ordering::Less,
+            ),
+            (Value::Float64(-0.0), Aspect::Double, Ordering::Equal),
+            (Value::Float64(0.0), Aspect::Double, Ordering::Equal),
+            (Value::Float64(1e-300), Aspect::Double, Ordering::Greater),
+            (
+                Value::BigInt("-123456789012345678901234567890".parse().unwrap()),
+                Aspect::Integer,
+                Ordering::Less,
+            ),
+            (
+                Value::BigInt(Integer::new()),
+                Aspect::Integer,
+                Ordering::Equal,
+            ),
+            (
+                Value::BigInt(Integer::from(256)),
+                Aspect::Integer,
+                Ordering::Greater,
+            ),
+            (
+                Value::String("-0.01".to_string()),
+                Aspect::Decimal,
+                Ordering::Less,
+            ),
+            (
+                Value::String("-12.5".to_string()),
+                Aspect::Decimal,
+                Ordering::Less,
+            ),
+            (
+                Value::String("-0.00".to_string()),
+                Aspect::Decimal,
+                Ordering::Equal,
+            ),
+            (
+                Value::String("0".to_string()),
+                Aspect::Decimal,
+                Ordering::Equal,
+            ),
+            (
+                Value::String("0.000".to_string()),
+                Aspect::Decimal,
+                Ordering::Equal,
+            ),
+            (
+                Value::String("0.001".to_string()),
+                Aspect::Decimal,
+                Ordering::Greater,
+            ),
+            (
+                Value::String("7".to_string()),
+                Aspect::Decimal,
+                Ordering::Greater,
+            ),
+        ];
+        for (v, a, expected) in cases {
+            let storage = value_to_storage(v.clone(), a).unwrap();
+            assert_eq!(encoded_sign(&storage).unwrap(), expected, "{v:?}");
+        }
+        let storage = value_to_storage(Value::Boolean(true), Aspect::Boolean).unwrap();
+        assert!(encoded_sign(&storage).is_err());
+        let storage = value_to_storage(Value::Int64(5), Aspect::Long).unwrap();
+        assert!(encoded_sign(&storage[..4]).is_err());
+    }
+
+    #[cfg(feature = "half")]
+    #[test]
+    fn float16_sorts() {
+        let floats = [
+            f16::INFINITY,
+            f16::from_f32(-2.5),
+            f16::ZERO,
+            f16::MIN_POSITIVE_SUBNORMAL,
+            f16::NEG_INFINITY,
+            f16::MAX,
+            f16::NEG_ZERO,
+            -f16::MIN_POSITIVE_SUBNORMAL,
+            f16::from_f32(0.1),
+            f16::MIN,
+            f16::MIN_POSITIVE,
+        ];
+        let mut byte_vec: Vec<_> = floats
+            .iter()
+            .map(|f| value_to_storage(Value::Float16(*f), Aspect::Float16).unwrap())
+            .collect();
+        assert!(byte_vec.iter().all(|b| b.len() == 3));
+        byte_vec.sort();
+        let result_vec: Vec<_> = byte_vec
+            .iter()
+            .map(
+                |b| match storage_to_value(Bytes::from(b.clone())).unwrap() {
+                    (Value::Float16(f), Aspect::Float16) => f.to_bits(),
+                    _ => panic!("This can't happen"),
+                },
+            )
+            .collect();
+        let expected: Vec<_> = [
+            f16::NEG_INFINITY,
+            f16::MIN,
+            f16::from_f32(-2.5),
+            -f16::MIN_POSITIVE_SUBNORMAL,
+            f16::NEG_ZERO,
+            f16::ZERO,
+            f16::MIN_POSITIVE_SUBNORMAL,
+            f16::MIN_POSITIVE,
+            f16::from_f32(0.1),
+            f16::MAX,
+            f16::INFINITY,
+        ]
+        .iter()
+        .map(|f| f.to_bits())
+        .collect();
+        assert_eq!(expected, result_vec);
+    }
+
+    #[test]
+    fn golden_byte_vectors() {
+        let two_to_64: Integer = "18446744073709551616".parse().unwrap();
+        let cases = vec![
+            (
+                Value::BigInt(Integer::new()),
+                Aspect::Integer,
+                vec![4, 0x81, 0x00],
+            ),
+            (
+                Value::BigInt(Integer::from(255)),
+                Aspect::Integer,
+                vec![4, 0x82, 0x00, 0xff],
+            ),
+            (
+                Value::BigInt(Integer::from(-255)),
+                Aspect::Integer,
+                vec![4, 0x7d, 0xff, 0x00],
+            ),
+            (
+                Value::BigInt(two_to_64),
+                Aspect::Integer,
+                vec![4, 0x89, 0x01, 0, 0, 0, 0, 0, 0, 0, 0],
+            ),
+            (Value::Int32(1), Aspect::Int, vec![21, 0x80, 0, 0, 1]),
+            (
+                Value::Int32(-2),
+                Aspect::Int,
+                vec![21, 0x7f, 0xff, 0xff, 0xfe],
+            ),
+            (
+                Value::Int64(258),
+                Aspect::Long,
+                vec![22, 0x80, 0, 0, 0, 0, 0, 0x01, 0x02],
+            ),
+            (
+                Value::Float32(1.0),
+                Aspect::Float,
+                vec![6, 0xbf, 0x80, 0, 0],
+            ),
+            (
+                Value::Float32(-1.0),
+                Aspect::Float,
+                vec![6, 0x40, 0x7f, 0xff, 0xff],
+            ),
+            (
+                Value::Float64(1.0),
+                Aspect::Double,
+                vec![5, 0xbf, 0xf0, 0, 0, 0, 0, 0, 0],
+            ),
+            (
+                Value::String("1.5".to_string()),
+                Aspect::Decimal,
+                vec![3, 0x81, 0x01, 0x70],
+            ),
+            (
+                Value::String("ab".to_string()),
+                Aspect::String,
+                vec![1, b'a', b'b'],
+            ),
+            (Value::Boolean(true), Aspect::Boolean, vec![48]),
+        ];
+        for (v, a, expected) in cases {
+            assert_eq!(value_to_storage(v.clone(), a).unwrap(), expected, "{v:?}");
+            assert_eq!(round_trip(v.clone(), a).0, v);
+        }
+    }
+
+    #[test]
+    fn timestamp_from_storage() {
+        let dates = [
+            ("1970-01-01T00:00:00Z", 0),
+            ("2007-03-01T14:00:00+01:00", 1172754000),
+            ("1969-12-31T23:59:59Z", -1),
+        ];
+        for (date, timestamp) in dates {
+            let storage =
+                value_to_storage(Value::String(date.to_string()), Aspect::DateTime).unwrap();
+            assert_eq!(storage_to_timestamp(&storage[1..]).unwrap(), timestamp);
+        }
+        assert!(storage_to_timestamp(&[0x80, 0]).is_err());
+    }
+
+    #[test]
+    fn decimal_exponent_sorts() {
+        let decimals = [
+            "1E300",
+            "-1",
+            "0",
+            "1.23E+400",
+            "1E-300",
+            "-0.000",
+            "1",
+            "-1E300",
+            "120",
+            "1.5",
+            "0.0015e3",
+            "-1E-300",
+            "12.3",
+        ];
+        let mut encoded: Vec<_> = decimals
+            .iter()
+            .map(|d| {
+                value_to_storage(Value::String(d.to_string()), Aspect::DecimalExponent).unwrap()
+            })
+            .collect();
+        encoded.sort();
+        let decoded: Vec<_> = encoded
+            .iter()
+            .map(
+                |e| match storage_to_value(Bytes::from(e.clone())).unwrap() {
+                    (Value::String(s), Aspect::DecimalExponent) => s,
+                    _ => panic!("This can't happen"),
+                },
+            )
+            .collect();
+        assert_eq!(
+            decoded,
+            vec![
+                "-1.0E300",
+                "-1.0E0",
+                "-1.0E-300",
+                "0.0E0",
+                "0.0E0",
+                "1.0E-300",
+                "1.0E0",
+                "1.5E0",
+                "1.5E0",
+                "1.23E1",
+                "1.2E2",
+                "1.0E300",
+                "1.23E400",
+            ]
+        );
+        let mut buf = value_to_storage(
+            Value::String("-4.56E-7".to_string()),
+            Aspect::DecimalExponent,
+        )
+        .unwrap();
+        let len = buf.len();
+        buf.extend(value_to_storage(Value::Int32(1), Aspect::Int).unwrap());
+        assert_eq!(field_ranges(&buf).unwrap(), vec![0..len, len..len + 5]);
+        assert!(
+            value_to_storage(Value::String("1E".to_string()), Aspect::DecimalExponent).is_err()
+        );
+    }
+
+    #[test]
+    fn value_aspect_mismatch() {
+        let mismatches = vec![
+            (Value::Float32(1.0), Aspect::Integer, "Float32"),
+            (Value::Int32(1), Aspect::Long, "Int32"),
+            (Value::Int64(1), Aspect::Double, "Int64"),
+            (Value::String("1".to_string()), Aspect::Int, "String"),
+            (Value::BigInt(Integer::from(1)), Aspect::Decimal, "BigInt"),
+            (Value::Boolean(true), Aspect::String, "Boolean"),
+            (Value::Float64(1.0), Aspect::Boolean, "Float64"),
+        ];
+        for (v, a, kind) in mismatches {
+            match value_to_storage(v, a) {
+                Err(LexDataError::ValueAspectMismatch { value_kind, aspect }) => {
+                    assert_eq!(value_kind, kind);
+                    assert_eq!(aspect, a);
+                }
+                other => panic!("expected a mismatch for {kind} and {a:?}, got {other:?}"),
+            }
+        }
+        assert!(timestamp_to_storage(1, Aspect::DateTime).is_ok());
+    }
+
+    #[test]
+    fn bitmasks_sort_numerically() {
+        let masks = [0b1010_0000_0001, 0, 0xffff, 0b1, 0b1000_0000_0000, 0b11];
+        let mut encoded: Vec<_> = masks
+            .iter()
+            .map(|m| bitmask_to_storage(*m, 2, Aspect::Bitmask).unwrap())
+            .collect();
+        assert!(encoded.iter().all(|e| e.len() == 4));
+        encoded.sort();
+        let decoded: Vec<_> = encoded
+            .iter()
+            .map(
+                |e| match storage_to_value(Bytes::from(e.clone())).unwrap() {
+                    (Value::Bitmask(bits), Aspect::Bitmask) => bits,
+                    _ => panic!("This can't happen"),
+                },
+            )
+            .collect();
+        assert_eq!(
+            decoded,
+            vec![0, 0b1, 0b11, 0b1000_0000_0000, 0b1010_0000_0001, 0xffff]
+        );
+        let res = round_trip(Value::Bitmask(u64::MAX), Aspect::Bitmask);
+        assert_eq!(res, (Value::Bitmask(u64::MAX), Aspect::Bitmask));
+        assert!(storage_to_bitmask(&[9, 0]).is_err());
+        assert!(storage_to_bitmask(&[2, 0]).is_err());
+        assert!(matches!(
+            bitmask_to_storage(0x1ff, 1, Aspect::Bitmask),
+            Err(LexDataError::OutOfRange(_))
+        ));
+        assert!(bitmask_to_storage(1, 9, Aspect::Bitmask).is_err());
+        assert!(matches!(
+            bitmask_to_storage(5, 2, Aspect::String),
+            Err(LexDataError::UnexpectedAspect(_))
+        ));
+    }
+
+    #[test]
+    fn check_sorted_finds_first_inversion() {
+        let block = |values: &[i64]| -> Vec<u8> {
+            values
+                .iter()
+                .flat_map(|i| value_to_storage(Value::Int64(*i), Aspect::Long).unwrap())
+                .collect()
+        };
+        assert_eq!(check_sorted(&block(&[-5, 0, 0, 7, 1000])).unwrap(), None);
+        assert_eq!(
+            check_sorted(&block(&[-5, 0, 7, 3, 1000, 2])).unwrap(),
+            Some(2)
+        );
+        assert_eq!(check_sorted(&[]).unwrap(), None);
+        let truncated = block(&[1, 2]);
+        assert!(check_sorted(&truncated[..12]).is_err());
+        assert_eq!(lex_cmp(&[1, 2], &[1, 2, 0]), Ordering::Less);
+    }
+
+    #[test]
+    fn date_time_custom_format() {
+        let storage = value_to_storage(
+            Value::String("2007-03-01T14:00:00+01:00".to_string()),
+            Aspect::DateTime,
+        )
+        .unwrap();
+        assert_eq!(
+            storage_to_date_time_fmt(&storage[1..], "%Y-%m-%d %H:%M:%S").unwrap(),
+            Value::String("2007-03-01 13:00:00".to_string())
+        );
+        assert_eq!(
+            storage_to_date_time_fmt(&storage[1..], "%d/%m/%Y %z").unwrap(),
+            Value::String("01/03/2007 +0000".to_string())
+        );
+        assert!(matches!(
+            storage_to_date_time_fmt(&storage[1..], "%Y-%Q"),
+            Err(LexDataError::BadDateFormat(_))
+        ));
+    }
+
+    #[test]
+    fn fixed_ratios_sort() {
+        let ratios = [10000, 2500, 0, 1, 9999, 5000, 333];
+        let mut encoded: Vec<_> = ratios
+            .iter()
+            .map(|r| fixed_ratio_to_storage(*r, 4, Aspect::Long).unwrap())
+            .collect();
+        encoded.sort();
+        let decoded: Vec<_> = encoded
+            .iter()
+            .map(|e| fixed_ratio_from_storage(e, 4).unwrap())
+            .collect();
+        assert_eq!(
+            decoded,
+            vec!["0", "0.0001", "0.0333", "0.25", "0.5", "0.9999", "1"]
+        );
+        for bad in [-1, 10001] {
+            assert!(matches!(
+                fixed_ratio_to_storage(bad, 4, Aspect::Long),
+                Err(LexDataError::OutOfRange(_))
+            ));
+        }
+        assert!(fixed_ratio_to_storage(i64::MAX, 19, Aspect::Long).is_ok());
+        assert!(fixed_ratio_to_storage(1, 4, Aspect::Decimal).is_err());
+    }
+
+    #[test]
+    fn decode_exact_rejects_trailing_bytes() {
+        let mut storage = value_to_storage(Value::Int32(42), Aspect::Int).unwrap();
+        assert_eq!(
+            decode_exact(&storage).unwrap(),
+            (Value::Int32(42), Aspect::Int)
+        );
+        storage.push(0);
+        assert!(matches!(
+            decode_exact(&storage),
+            Err(LexDataError::TrailingBytes(_))
+        ));
+        assert!(storage_to_value(Bytes::from(storage.clone())).is_ok());
+        assert!(matches!(
+            decode_exact(&storage[..3]),
+            Err(LexDataError::TruncatedInput(_))
+        ));
+    }
+
+    #[test]
+    fn qnames_sort_by_prefix() {
+        let names = ["b:a", "local", "a:z", "a:b", "ab:a"];
+        let mut encoded: Vec<_> = names
+            .iter()
+            .map(|n| value_to_storage(Value::String(n.to_string()), Aspect::QName).unwrap())
+            .collect();
+        encoded.sort();
+        let decoded: Vec<_> = encoded
+            .iter()
+            .map(|e| match decode_exact(e).unwrap() {
+                (Value::String(s), Aspect::QName) => s,
+                _ => panic!("This can't happen"),
+            })
+            .collect();
+        assert_eq!(decoded, vec!["local", "a:b", "a:z", "ab:a", "b:a"]);
+        for bad in ["", ":a", "a:", "a:b:c", "1a", "a b"] {
+            assert!(matches!(
+                value_to_storage(Value::String(bad.to_string()), Aspect::QName),
+                Err(LexDataError::BadName(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn ncname_aspects_validated() {
+        for a in [Aspect::NCName, Aspect::ID, Aspect::IdRef, Aspect::Entity] {
+            let res = round_trip(Value::String("_valid-Name.1".to_string()), a);
+            assert_eq!(res, (Value::String("_valid-Name.1".to_string()), a));
+            for bad in ["has space", "pre:fix", "", "-dash"] {
+                assert!(matches!(
+                    value_to_storage(Value::String(bad.to_string()), a),
+                    Err(LexDataError::BadName(_))
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn values_start_with_aspect_prefix() {
+        let values = vec![
+            (Value::String("x".to_string()), Aspect::Token),
+            (Value::String("1.5".to_string()), Aspect::Decimal),
+            (
+                Value::String("2007-03-01T13:00:00Z".to_string()),
+                Aspect::DateTime,
+            ),
+            (Value::Int32(-3), Aspect::Short),
+            (Value::Int64(3), Aspect::Long),
+            (Value::BigInt(Integer::from(9)), Aspect::NonNegativeInteger),
+            (Value::Float32(1.0), Aspect::Float),
+            (Value::Float64(-1.0), Aspect::Double),
+            (Value::Bitmask(5), Aspect::Bitmask),
+            (Value::Boolean(true), Aspect::Boolean),
+            (Value::Boolean(false), Aspect::Boolean),
+        ];
+        for (v, a) in values {
+            let storage = value_to_storage(v, a).unwrap();
+            assert!(aspect_prefix(a).contains(&storage[0]), "{a:?}");
+        }
+
+        let mut index = std::collections::BTreeMap::new();
+        for i in [1, 2, 3] {
+            index.insert(value_to_storage(Value::Int32(i), Aspect::Int).unwrap(), i);
+            index.insert(
+                value_to_storage(Value::Int64(i.into()), Aspect::Long).unwrap(),
+                -i,
+            );
+        }
+        let ints: Vec<_> = index
+            .range(prefix_range(aspect_prefix(Aspect::Int)[0]))
+            .map(|(_, i)| *i)
+            .collect();
+        assert_eq!(ints, vec![1, 2, 3]);
+        assert_eq!(prefix_range(0xff).1, Bound::Unbounded);
+    }
+
+    #[test]
+    fn unified_floats_interleave() {
+        let mut encoded = vec![
+            float_to_storage_unified(f64::from(2.5_f32), Aspect::Float).unwrap(),
+            float_to_storage_unified(-1.25, Aspect::Double).unwrap(),
+            float_to_storage_unified(f64::from(-7.0_f32), Aspect::Float).unwrap(),
+            float_to_storage_unified(0.1, Aspect::Double).unwrap(),
+            float_to_storage_unified(f64::from(0.1_f32), Aspect::Float).unwrap(),
+            float_to_storage_unified(1e300, Aspect::Double).unwrap(),
+        ];
+        encoded.sort();
+        let decoded: Vec<_> = encoded
+            .iter()
+            .map(
+                |e| match storage_to_value(Bytes::from(e.clone())).unwrap() {
+                    (Value::Float64(f), Aspect::Double) => f,
+                    _ => panic!("This can't happen"),
+                },
+            )
+            .collect();
+        assert_eq!(
+            decoded,
+            vec![-7.0, -1.25, 0.1, f64::from(0.1_f32), 2.5, 1e300]
+        );
+        assert!(float_to_storage_unified(1.0, Aspect::Int).is_err());
+    }
+
+    #[test]
+    fn padded_bigint_rejected() {
+        let storage = value_to_storage(Value::BigInt(Integer::from(5)), Aspect::Integer).unwrap();
+        assert_eq!(storage, vec![4, 0x81, 0x05]);
+        // the same integer with a redundant leading zero byte
+        let padded = [0x82, 0x00, 0x05];
+        assert!(matches!(
+            storage_to_bigint(&padded),
+            Err(LexDataError::BadBigIntLayout(_))
+        ));
+        let padded_negative = [!0x82, 0xff, !0x05];
+        assert!(storage_to_bigint(&padded_negative).is_err());
+        // 128 needs two bytes to leave room for the spare top bit
+        assert!(storage_to_bigint(&[0x81, 0x80]).is_err());
+        assert!(storage_to_bigint(&[0x82, 0x00, 0x80]).is_ok());
+        // negative zero is only valid as the empty integer
+        assert!(storage_to_bigint(&[!0x81, 0xff]).is_err());
+        assert!(storage_to_bigint(&[NEGATIVE_ZERO]).is_ok());
+        assert!(matches!(
+            storage_to_bigint(&[0x83, 0x01]),
+            Err(LexDataError::TruncatedInput(_))
+        ));
+    }
+
+    #[test]
+    fn f64_order_key_monotonic() {
+        let sorted = [
+            f64::NEG_INFINITY,
+            f64::MIN,
+            -1.5,
+            -f64::MIN_POSITIVE,
+            -0.0,
+            0.0,
+            5e-324,
+            f64::MIN_POSITIVE,
+            1.0,
+            f64::MAX,
+            f64::INFINITY,
+        ];
+        let keys: Vec<u64> = sorted.iter().map(|f| f64_order_key(*f)).collect();
+        assert!(keys.windows(2).all(|pair| pair[0] < pair[1]));
+        for f in sorted {
+            assert_eq!(f64_from_order_key(f64_order_key(f)).to_bits(), f.to_bits());
+        }
+        let nan_key = f64_order_key(f64::NAN);
+        assert!(nan_key > f64_order_key(f64::INFINITY));
+        assert_eq!(f64_order_key(-f64::NAN), nan_key);
+        assert!(f64_from_order_key(nan_key).is_nan());
+    }
+
+    #[test]
+    fn enum_ordinals_sort_like_strings() {
+        let dictionary = ["closed", "new", "open", "pending"];
+        let rows = ["open", "closed", "pending", "new", "open", "closed"];
+        let mut encoded: Vec<_> = rows
+            .iter()
+            .map(|r| {
+                let index = dictionary.iter().position(|d| d == r).unwrap() as u32;
+                encode_enum(index, &dictionary, Aspect::UnsignedInt).unwrap()
+            })
+            .collect();
+        encoded.sort();
+        let decoded: Vec<_> = encoded
+            .iter()
+            .map(|e| match decode_enum(e, &dictionary).unwrap() {
+                Value::String(s) => s,
+                _ => panic!("This can't happen"),
+            })
+            .collect();
+        let mut sorted_rows = rows.to_vec();
+        sorted_rows.sort();
+        assert_eq!(decoded, sorted_rows);
+        assert!(matches!(
+            encode_enum(0, &["new", "closed"], Aspect::UnsignedInt),
+            Err(LexDataError::UnsortedDictionary(_))
+        ));
+        assert!(matches!(
+            encode_enum(4, &dictionary, Aspect::UnsignedInt),
+            Err(LexDataError::OutOfRange(_))
+        ));
+        assert!(matches!(
+            encode_enum(1, &dictionary, Aspect::String),
+            Err(LexDataError::UnexpectedAspect(_))
+        ));
+        // the key is the ordinal as an unsignedInt
+        let key = encode_enum(2, &dictionary, Aspect::UnsignedInt).unwrap();
+        assert_eq!(
+            decode_exact(&key).unwrap(),
+            (Value::Int64(2), Aspect::UnsignedInt)
+        );
+        let int = value_to_storage(Value::Int32(2), Aspect::Int).unwrap();
+        assert!(decode_enum(&int, &dictionary).is_err());
+    }
+
+    #[test]
+    fn unsigned_ints_round_trip() {
+        let values = [u32::MAX, 0, 1, 255, 256, 70000];
+        let mut keys: Vec<Vec<u8>> = values
+            .iter()
+            .map(|i| value_to_storage(Value::Int64((*i).into()), Aspect::UnsignedInt).unwrap())
+            .collect();
+        assert!(keys.iter().all(|k| k.len() == 5));
+        keys.sort();
+        let decoded: Vec<Value> = keys.iter().map(|k| decode_exact(k).unwrap().0).collect();
+        let mut sorted = values;
+        sorted.sort();
+        assert_eq!(
+            decoded,
+            sorted
+                .iter()
+                .map(|i| Value::Int64((*i).into()))
+                .collect::<Vec<_>>()
+        );
+        for bad in [-1, i64::from(u32::MAX) + 1] {
+            assert!(matches!(
+                value_to_storage(Value::Int64(bad), Aspect::UnsignedInt),
+                Err(LexDataError::OutOfRange(_))
+            ));
+        }
+        assert_eq!(
+            parse_value("4294967295", Aspect::UnsignedInt).unwrap(),
+            Value::Int64(4_294_967_295)
+        );
+        assert!(parse_value("-1", Aspect::UnsignedInt).is_err());
+        assert!(typed_value(Aspect::UnsignedInt, TypedInput::Int(-1)).is_err());
+        assert_eq!(encoded_sign(&keys[0]).unwrap(), Ordering::Equal);
+        assert_eq!(encoded_sign(&keys[1]).unwrap(), Ordering::Greater);
+        assert!(keys
+            .iter()
+            .all(|k| *k < aspect_high_sentinel(Aspect::UnsignedInt)));
+    }
+
+    #[test]
+    fn widened_int32_keys_keep_order() {
+        let ints = [i32::MAX, -1, 0, i32::MIN, 256, -256, 1, 70000];
+        let mut originals: Vec<_> = ints
+            .iter()
+            .map(|i| value_to_storage(Value::Int32(*i), Aspect::Int).unwrap())
+            .collect();
+        originals.sort();
+        let widened: Vec<_> = originals
+            .iter()
+            .map(|o| widen_int32_to_int64(o).unwrap())
+            .collect();
+        let mut resorted = widened.clone();
+        resorted.sort();
+        assert_eq!(widened, resorted);
+        for (original, wide) in originals.iter().zip(widened.iter()) {
+            let i = match storage_to_value(Bytes::from(original.clone())).unwrap() {
+                (Value::Int32(i), _) => i,
+                _ => panic!("This can't happen"),
+            };
+            assert_eq!(
+                *wide,
+                value_to_storage(Value::Int64(i.into()), Aspect::Long).unwrap()
+            );
+        }
+        let long = value_to_storage(Value::Int64(1), Aspect::Long).unwrap();
+        assert!(widen_int32_to_int64(&long).is_err());
+    }
+
+    #[test]
+    fn subnormal_floats_sort() {
+        let doubles = [
+            f64::MIN_POSITIVE,
+            -f64::from_bits(1),
+            0.0,
+            -f64::MIN_POSITIVE,
+            f64::from_bits(1),
+            -0.0,
+            f64::from_bits(0x000f_ffff_ffff_ffff),
+            -f64::from_bits(0x000f_ffff_ffff_ffff),
+        ];
+        let mut encoded: Vec<_> = doubles
+            .iter()
+            .map(|f| value_to_storage(Value::Float64(*f), Aspect::Double).unwrap())
+            .collect();
+        encoded.sort();
+        let decoded: Vec<u64> = encoded
+            .iter()
+            .map(
+                |e| match storage_to_value(Bytes::from(e.clone())).unwrap() {
+                    (Value::Float64(f), _) => f.to_bits(),
+                    _ => panic!("This can't happen"),
+                },
+            )
+            .collect();
+        let expected: Vec<u64> = [
+            -f64::MIN_POSITIVE,
+            -f64::from_bits(0x000f_ffff_ffff_ffff),
+            -f64::from_bits(1),
+            -0.0,
+            0.0,
+            f64::from_bits(1),
+            f64::from_bits(0x000f_ffff_ffff_ffff),
+            f64::MIN_POSITIVE,
+        ]
+        .iter()
+        .map(|f| f.to_bits())
+        .collect();
+        assert_eq!(decoded, expected);
+
+        let floats = [
+            f32::from_bits(1),
+            -f32::MIN_POSITIVE,
+            -f32::from_bits(1),
+            f32::MIN_POSITIVE,
+        ];
+        let mut encoded: Vec<_> = floats
+            .iter()
+            .map(|f| value_to_storage(Value::Float32(*f), Aspect::Float).unwrap())
+            .collect();
+        encoded.sort();
+        let decoded: Vec<u32> = encoded
+            .iter()
+            .map(
+                |e| match storage_to_value(Bytes::from(e.clone())).unwrap() {
+                    (Value::Float32(f), _) => f.to_bits(),
+                    _ => panic!("This can't happen"),
+                },
+            )
+            .collect();
+        let expected: Vec<u32> = [
+            -f32::MIN_POSITIVE,
+            -f32::from_bits(1),
+            f32::from_bits(1),
+            f32::MIN_POSITIVE,
+        ]
+        .iter()
+        .map(|f| f.to_bits())
+        .collect();
+        assert_eq!(decoded, expected);
+    }
+
+    #[test]
+    fn encode_rdf_literals() {
+        let xsd = |local: &str| format!("http://www.w3.org/2001/XMLSchema#{local}");
+        assert_eq!(
+            encode_literal("-42", &xsd("integer"), false).unwrap(),
+            value_to_storage(Value::BigInt(Integer::from(-42)), Aspect::Integer).unwrap()
+        );
+        assert_eq!(
+            encode_literal("3.25", &xsd("decimal"), false).unwrap(),
+            value_to_storage(Value::String("3.25".to_string()), Aspect::Decimal).unwrap()
+        );
+        assert_eq!(
+            encode_literal("true", &xsd("boolean"), false).unwrap(),
+            vec![aspect_byte(Aspect::True)]
+        );
+        assert_eq!(
+            encode_literal("INF", &xsd("double"), false).unwrap(),
+            value_to_storage(Value::Float64(f64::INFINITY), Aspect::Double).unwrap()
+        );
+        let unknown = "http://example.com/datatypes#colour";
+        assert_eq!(
+            encode_literal("red", unknown, true).unwrap(),
+            value_to_storage(Value::String("red".to_string()), Aspect::String).unwrap()
+        );
+        assert!(matches!(
+            encode_literal("red", unknown, false),
+            Err(LexDataError::UnknownDatatype(_))
+        ));
+        assert!(matches!(
+            encode_literal("forty", &xsd("int"), false),
+            Err(LexDataError::BadLexicalForm(_))
+        ));
+        assert!(matches!(
+            encode_literal("2020-01-01", &xsd("date"), false),
+            Err(LexDataError::UnexpectedAspect(_))
+        ));
+        assert!(matches!(
+            encode_literal("0", &xsd("positiveInteger"), false),
+            Err(LexDataError::OutOfRange(_))
+        ));
+        assert!(matches!(
+            encode_literal("-1", &xsd("nonNegativeInteger"), false),
+            Err(LexDataError::OutOfRange(_))
+        ));
+        for special in ["+INF", "-INF", "NaN"] {
+            assert!(encode_literal(special, &xsd("float"), false).is_ok());
+        }
+        for special in ["inf", "-infinity", "Infinity", "nan", "NAN", "+nan"] {
+            assert!(
+                matches!(
+                    encode_literal(special, &xsd("double"), false),
+                    Err(LexDataError::BadLexicalForm(_))
+                ),
+                "{special}"
+            );
+        }
+    }
+
+    #[test]
+    fn delimited_string_then_integer() {
+        let mut buf = field_to_storage(Value::String("abc".to_string()), Aspect::String).unwrap();
+        buf.extend(field_to_storage(Value::Int32(7), Aspect::Int).unwrap());
+        buf.extend(field_to_storage(Value::String("a:b".to_string()), Aspect::QName).unwrap());
+        buf.extend(field_to_storage(Value::String("last".to_string()), Aspect::Token).unwrap());
+
+        let (value, aspect, size) = decode_one(&buf).unwrap();
+        assert_eq!(
+            (value, aspect, size),
+            (Value::String("abc".to_string()), Aspect::String, 5)
+        );
+        let values: Vec<_> = values_iter(&buf).map(|r| r.unwrap()).collect();
+        assert_eq!(
+            values,
+            vec![
+                (Value::String("abc".to_string()), Aspect::String),
+                (Value::Int32(7), Aspect::Int),
+                (Value::String("a:b".to_string()), Aspect::QName),
+                (Value::String("last".to_string()), Aspect::Token),
+            ]
+        );
+        assert_eq!(
+            field_ranges(&buf).unwrap(),
+            vec![0..5, 5..10, 10..15, 15..21]
+        );
+
+        let mut keys: Vec<_> = ["ab", "a", "b", ""]
+            .iter()
+            .map(|s| {
+                let mut key =
+                    field_to_storage(Value::String(s.to_string()), Aspect::String).unwrap();
+                key.extend(field_to_storage(Value::Int32(1), Aspect::Int).unwrap());
+                key
+            })
+            .collect();
+        keys.sort();
+        let firsts: Vec<_> = keys.iter().map(|k| decode_one(k).unwrap().0).collect();
+        assert_eq!(
+            firsts,
+            ["", "a", "ab", "b"]
+                .iter()
+                .map(|s| Value::String(s.to_string()))
+                .collect::<Vec<_>>()
+        );
+        assert!(field_to_storage(Value::String("a\0b".to_string()), Aspect::String).is_err());
+    }
+
+    #[test]
+    fn date_time_custom_epoch() {
+        let epoch = DateTime::parse_from_rfc3339("1800-01-01T00:00:00Z").unwrap();
+        let dates = [
+            "1800-06-01T12:00:00Z",
+            "1799-12-31T23:59:59Z",
+            "1800-01-01T00:00:00Z",
+            "1800-01-01T01:00:00+02:00",
+            "1850-03-01T00:00:00Z",
+        ];
+        let mut encoded: Vec<_> = dates
+            .iter()
+            .map(|d| datetime_to_storage_epoch(d, epoch, Aspect::Integer).unwrap())
+            .collect();
+        encoded.sort();
+        assert!(encoded.iter().all(|e| e.len() <= 6));
+        let decoded: Vec<_> = encoded
+            .iter()
+            .map(|e| match storage_to_datetime_epoch(e, epoch).unwrap() {
+                Value::String(s) => s,
+                _ => panic!("This can't happen"),
+            })
+            .collect();
+        assert_eq!(
+            decoded,
+            vec![
+                "1799-12-31T23:00:00Z",
+                "1799-12-31T23:59:59Z",
+                "1800-01-01T00:00:00Z",
+                "1800-06-01T12:00:00Z",
+                "1850-03-01T00:00:00Z",
+            ]
+        );
+        // the key is the offset in seconds as an integer
+        let key =
+            datetime_to_storage_epoch("1800-01-01T00:01:00Z", epoch, Aspect::Integer).unwrap();
+        assert_eq!(
+            decode_exact(&key).unwrap(),
+            (Value::BigInt(Integer::from(60)), Aspect::Integer)
+        );
+        assert!(datetime_to_storage_epoch(dates[0], epoch, Aspect::DateTime).is_err());
+        let date_time = value_to_storage(Value::from(dates[0]), Aspect::DateTime).unwrap();
+        assert!(storage_to_datetime_epoch(&date_time, epoch).is_err());
+    }
+
+    #[cfg(feature = "test-util")]
+    #[test]
+    fn assert_ordered_floats() {
+        assert_ordered(
+            &[
+                f32::NEG_INFINITY,
+                -100.3_f32,
+                f32::MIN,
+                22.5_f32,
+                0_f32,
+                f32::MAX,
+                0.1_f32,
+                -0.1_f32,
+                f32::INFINITY,
+            ],
+            Aspect::Float,
+        );
+        assert_ordered(&[1e-300, -2.5, f64::MAX, -0.5, 3.0], Aspect::Double);
+    }
+
+    #[test]
+    fn hex_binary_order() {
+        let hexes = ["FF", "01", "0000", "", "00", "00ff", "0001", "ff00"];
+        let mut encoded: Vec<_> = hexes
+            .iter()
+            .map(|h| value_to_storage(Value::String(h.to_string()), Aspect::HexBinary).unwrap())
+            .collect();
+        encoded.sort();
+        let decoded: Vec<_> = encoded
+            .iter()
+            .map(|e| match decode_exact(e).unwrap() {
+                (Value::String(s), Aspect::HexBinary) => s,
+                _ => panic!("This can't happen"),
+            })
+            .collect();
+        assert_eq!(
+            decoded,
+            vec!["", "00", "0000", "0001", "00FF", "01", "FF", "FF00"]
+        );
+        for bad in ["0", "0G", "é0"] {
+            assert!(matches!(
+                value_to_storage(Value::String(bad.to_string()), Aspect::HexBinary),
+                Err(LexDataError::BadLexicalForm(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn storage_type_of_values() {
+        let values = [
+            (
+                Value::String("s".to_string()),
+                StorageType::String,
+                Aspect::String,
+            ),
+            (
+                Value::BigInt(Integer::from(1)),
+                StorageType::BigInt,
+                Aspect::Integer,
+            ),
+            (Value::Int32(1), StorageType::Int32, Aspect::Int),
+            (Value::Int64(1), StorageType::Int64, Aspect::Long),
+            (Value::Float32(1.0), StorageType::Float32, Aspect::Float),
+            (Value::Float64(1.0), StorageType::Float64, Aspect::Double),
+            (Value::Boolean(false), StorageType::Boolean, Aspect::Boolean),
+            (Value::Bitmask(1), StorageType::Bitmask, Aspect::Bitmask),
+        ];
+        for (v, ty, a) in values {
+            assert_eq!(value_storage_type(&v), ty);
+            assert_eq!(aspect_storage(a), ty);
+            assert!(value_to_storage(v, a).is_ok());
+        }
+        #[cfg(feature = "half")]
+        assert_eq!(
+            value_storage_type(&Value::Float16(f16::ONE)),
+            StorageType::Float16
+        );
+    }
+
+    #[test]
+    fn short_int_payloads() {
+        assert!(storage_to_int32(&[]).is_err());
+        assert!(storage_to_int32(&[0x80, 0, 0]).is_err());
+        assert!(storage_to_int64(&[0x80, 0, 0, 0, 0, 0, 0]).is_err());
+        assert_eq!(storage_to_int32(&[0x80, 0, 0, 1]).unwrap(), Value::Int32(1));
+        assert_eq!(
+            storage_to_int64(&[0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]).unwrap(),
+            Value::Int64(-1)
+        );
+    }
+
+    #[test]
+    fn boolean_fields_in_compound_keys() {
+        let key = |b: bool, i: i32| {
+            let mut k = field_to_storage(Value::Boolean(b), Aspect::Boolean).unwrap();
+            k.extend(field_to_storage(Value::Int32(i), Aspect::Int).unwrap());
+            k
+        };
+        let k = key(false, 7);
+        let (v, a, size) = decode_one(&k).unwrap();
+        assert_eq!((v, a, size), (Value::Boolean(false), Aspect::Boolean, 1));
+        assert_eq!(decode_exact(&k[size..]).unwrap().0, Value::Int32(7));
+        assert!(key(false, 5) < key(true, -1));
+        assert!(key(true, -1) < key(true, 1));
+    }
+
+    #[test]
+    fn fractions_with_leading_zeros_sort() {
+        let decimals = [
+            "-0.1",
+            "-0.001",
+            "-0.0000001234",
+            "0.0000001234",
+            "0.0001",
+            "0.00011",
+            "0.001",
+            "0.0012",
+            "0.1",
+            "0.12",
+        ];
+        let keys: Vec<Vec<u8>> = decimals
+            .iter()
+            .map(|d| value_to_storage(Value::String(d.to_string()), Aspect::Decimal).unwrap())
+            .collect();
+        for (k, d) in keys.iter().zip(decimals) {
+            assert_eq!(
+                storage_to_bignum(&k[1..]).unwrap(),
+                Value::String(d.to_string())
+            );
+        }
+        for pair in keys.windows(2) {
+            assert!(pair[0] < pair[1]);
+        }
+    }
+
+    #[test]
+    fn default_values_encode() {
+        let families = [
+            (StorageType::String, Aspect::String),
+            (StorageType::Int32, Aspect::Int),
+            (StorageType::Int64, Aspect::Long),
+            (StorageType::Float32, Aspect::Float),
+            (StorageType::Float64, Aspect::Double),
+            (StorageType::BigInt, Aspect::Integer),
+            (StorageType::BigNum, Aspect::Decimal),
+            (StorageType::DateTime, Aspect::DateTime),
+            (StorageType::DecimalExponent, Aspect::DecimalExponent),
+            (StorageType::Bitmask, Aspect::Bitmask),
+            (StorageType::HexBinary, Aspect::HexBinary),
+            (StorageType::Boolean, Aspect::Boolean),
+            (StorageType::Time, Aspect::Time),
+            (StorageType::Rational, Aspect::Rational),
+            (StorageType::GMonth, Aspect::GMonth),
+            (StorageType::Version, Aspect::Version),
+            (StorageType::Numeric, Aspect::Numeric),
+            (StorageType::DateTimeStamp, Aspect::DateTimeStamp),
+            (StorageType::UInt32, Aspect::UnsignedInt),
+            (StorageType::SizedInt, Aspect::SizedInt),
+            (StorageType::PrefixedString, Aspect::PrefixedString),
+            #[cfg(feature = "half")]
+            (StorageType::Float16, Aspect::Float16),
+        ];
+        for (ty, a) in families {
+            assert_eq!(aspect_storage(a), ty);
+            assert!(
+                value_to_storage(default_value(ty).unwrap(), a).is_ok(),
+                "{ty:?}"
+            );
+        }
+        assert!(default_value(StorageType::Custom).is_err());
+        assert!(default_value(StorageType::OrderedURI).is_err());
+        assert_eq!(
+            default_value(StorageType::Float16).is_ok(),
+            cfg!(feature = "half")
+        );
+    }
+
+    #[test]
+    fn probe_orders_against_keys() {
+        let keys: Vec<Vec<u8>> = [-40_i64, -1, 0, 12, 12, 300]
+            .iter()
+            .map(|i| value_to_storage(Value::Int64(*i), Aspect::Long).unwrap())
+            .collect();
+        let probe = Probe::new(&Value::Int64(12), Aspect::Long).unwrap();
+        let orders: Vec<Ordering> = keys.iter().map(|k| probe.cmp(k)).collect();
+        assert_eq!(
+            orders,
+            [
+                Ordering::Less,
+                Ordering::Less,
+                Ordering::Less,
+                Ordering::Equal,
+                Ordering::Equal,
+                Ordering::Greater
+            ]
+        );
+        assert_eq!(keys.partition_point(|k| probe.cmp(k) == Ordering::Less), 3);
+        assert!(Probe::new(&Value::Int64(12), Aspect::Int).is_err());
+    }
+
+    #[test]
+    fn empty_string_round_trips_and_sorts_first() {
+        let empty = value_to_storage(Value::String(String::new()), Aspect::String).unwrap();
+        assert_eq!(empty, [aspect_byte(Aspect::String)]);
+        assert_eq!(storage_size(Bytes::from(empty.clone())), 1);
+        assert_eq!(
+            decode_exact(&empty).unwrap(),
+            (Value::String(String::new()), Aspect::String)
+        );
+        let field = field_to_storage(Value::String(String::new()), Aspect::String).unwrap();
+        assert_eq!(decode_one(&field).unwrap().2, 2);
+        for s in ["\u{1}", "a", "zzz"] {
+            let other = value_to_storage(Value::String(s.to_string()), Aspect::String).unwrap();
+            assert!(empty < other);
+            let other = field_to_storage(Value::String(s.to_string()), Aspect::String).unwrap();
+            assert!(field < other);
+        }
+    }
+
+    #[test]
+    fn facets_rejected_on_validated_decode() {
+        let mut buf = value_to_storage(Value::BigInt(Integer::from(-3)), Aspect::Integer).unwrap();
+        buf[0] = aspect_byte(Aspect::PositiveInteger);
+        let bytes = Bytes::from(buf.clone());
+        assert!(storage_to_value(bytes.clone()).is_ok());
+        assert!(matches!(
+            storage_to_value_validated(bytes),
+            Err(LexDataError::OutOfRange(_))
+        ));
+        buf[0] = aspect_byte(Aspect::NonNegativeInteger);
+        assert!(storage_to_value_validated(Bytes::from(buf)).is_err());
+
+        let zero =
+            value_to_storage(Value::BigInt(Integer::new()), Aspect::NonNegativeInteger).unwrap();
+        assert!(storage_to_value_validated(Bytes::from(zero.clone())).is_ok());
+        let mut zero = zero;
+        zero[0] = aspect_byte(Aspect::PositiveInteger);
+        assert!(storage_to_value_validated(Bytes::from(zero)).is_err());
+    }
+
+    #[test]
+    fn merge_interleaved_runs() {
+        let keys = |values: &[i32]| -> Vec<Vec<u8>> {
+            values
+                .iter()
+                .map(|i| value_to_storage(Value::Int32(*i), Aspect::Int).unwrap())
+                .collect()
+        };
+        let merged = merge_sorted(&keys(&[-9, -1, 4, 4, 20]), &keys(&[-5, 0, 4, 30, 31]));
+        assert_eq!(merged, keys(&[-9, -5, -1, 0, 4, 4, 4, 20, 30, 31]));
+        assert_eq!(merge_sorted(&[], &keys(&[1])), keys(&[1]));
+    }
+
+    #[test]
+    fn time_with_timezone() {
+        let times = [
+            "13:45:30+02:00",
+            "08:00:00Z",
+            "13:45:30",
+            "23:59:59.25-05:30",
+            "09:00:00-00:00",
+        ];
+        for t in times {
+            let stored = value_to_storage(Value::String(t.to_string()), Aspect::Time).unwrap();
+            assert_eq!(
+                decode_exact(&stored).unwrap(),
+                (Value::String(t.to_string()), Aspect::Time)
+            );
+        }
+        // 13:45:30+02:00 is 11:45:30Z, and 01:00:00+03:00 the evening before
+        let ordered = [
+            "01:00:00+03:00",
+            "08:00:00Z",
+            "09:00:00+00:00",
+            "09:00:00-00:00",
+            "13:45:30+02:00",
+            "13:00:00",
+            "13:45:30",
+            "10:00:00-05:00",
+        ];
+        let keys: Vec<Vec<u8>> = ordered
+            .iter()
+            .map(|t| value_to_storage(Value::String(t.to_string()), Aspect::Time).unwrap())
+            .collect();
+        for pair in keys.windows(2) {
+            assert!(pair[0] < pair[1]);
+        }
+        for bad in [
+            "13:45:30+2:00",
+            "13:45:30+15:00",
+            "13:45:30+02:60",
+            "13:45",
+            "24:00:00x",
+        ] {
+            assert!(matches!(
+                value_to_storage(Value::String(bad.to_string()), Aspect::Time),
+                Err(LexDataError::BadDateFormat(_))
+            ));
+        }
+        assert_eq!(
+            canonical_lexical(&Value::String("01:00:00+03:00".to_string()), Aspect::Time).unwrap(),
+            "22:00:00Z"
+        );
+    }
+
+    #[test]
+    fn decode_tuple_reports_failing_field() {
+        let first = value_to_storage(Value::Int32(1), Aspect::Int).unwrap();
+        let last = value_to_storage(Value::Int32(3), Aspect::Int).unwrap();
+        // a Short holding 65536
+        let corrupt = vec![aspect_byte(Aspect::Short), 0x80, 0x01, 0x00, 0x00];
+        let good = [first.clone(), first.clone(), last.clone()].concat();
+        assert_eq!(
+            decode_tuple(&good).unwrap(),
+            vec![
+                (Value::Int32(1), Aspect::Int),
+                (Value::Int32(1), Aspect::Int),
+                (Value::Int32(3), Aspect::Int)
+            ]
+        );
+        let key = [first, corrupt, last].concat();
+        match decode_tuple(&key) {
+            Err(LexDataError::FieldError { index, source }) => {
+                assert_eq!(index, 1);
+                assert!(matches!(*source, LexDataError::OutOfRange(_)));
+            }
+            other => panic!("expected a field error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn rationals_sort_by_value() {
+        let fractions = [
+            (-5, 1),
+            (-2, 3),
+            (-1, 2),
+            (-3, 8),
+            (-1, 3),
+            (0, 1),
+            (1, 3),
+            (3, 8),
+            (1, 2),
+            (2, 3),
+            (1, 1),
+            (3, 2),
+            (22, 7),
+        ];
+        let keys: Vec<Vec<u8>> = fractions
+            .iter()
+            .map(|nd| {
+                let r = Rational::from(*nd);
+                let key = value_to_storage(Value::Rational(r.clone()), Aspect::Rational).unwrap();
+                assert_eq!(
+                    decode_exact(&key).unwrap(),
+                    (Value::Rational(r), Aspect::Rational)
+                );
+                key
+            })
+            .collect();
+        for pair in keys.windows(2) {
+            assert!(pair[0] < pair[1]);
+        }
+        let signs: Vec<Ordering> = keys.iter().map(|k| encoded_sign(k).unwrap()).collect();
+        assert_eq!(
+            signs[4..7],
+            [Ordering::Less, Ordering::Equal, Ordering::Greater]
+        );
+    }
+
+    #[test]
+    fn g_month_with_timezone() {
+        let months = ["--03", "--03Z", "--03-05:00", "--03+05:00", "--11"];
+        let keys: Vec<Vec<u8>> = months
+            .iter()
+            .map(|m| {
+                let key = value_to_storage(Value::String(m.to_string()), Aspect::GMonth).unwrap();
+                assert_eq!(
+                    decode_exact(&key).unwrap(),
+                    (Value::String(m.to_string()), Aspect::GMonth)
+                );
+                key
+            })
+            .collect();
+        for pair in keys.windows(2) {
+            assert!(pair[0] < pair[1]);
+        }
+        for bad in ["--03+5:00", "--03-15:00", "--13", "--3", "03"] {
+            assert!(matches!(
+                value_to_storage(Value::String(bad.to_string()), Aspect::GMonth),
+                Err(LexDataError::BadDateFormat(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn size_round_trip() {
+        let mut sizes: Vec<u64> = (0..=200).collect();
+        sizes.extend([4095, 8191, 8192, 1 << 20, u32::MAX.into(), u64::MAX]);
+        for n in sizes {
+            let bytes = encode_size(n);
+            assert_eq!(decode_size(&bytes).unwrap(), (n, bytes.len()));
+        }
+        let encoded: Vec<Vec<u8>> = (0..8192).map(encode_size).collect();
+        for pair in encoded.windows(2) {
+            assert!(pair[0] < pair[1]);
+        }
+        // 63 fits in the 6 bits of the first byte, 64 does not
+        assert_eq!(encode_size(63), [0xbf]);
+        assert_eq!(encode_size(64), [0xc0, 0x40]);
+        assert!(matches!(
+            decode_size(&[0xc0]),
+            Err(LexDataError::TruncatedInput(_))
+        ));
+        assert!(decode_size(&[]).is_err());
+    }
+
+    #[test]
+    fn values_sort_below_high_sentinel() {
+        let samples = vec![
+            (Value::Int32(i32::MAX), Aspect::Int),
+            (Value::Int64(i64::MAX), Aspect::Long),
+            (Value::Float32(f32::NAN), Aspect::Float),
+            (Value::Float64(f64::INFINITY), Aspect::Double),
+            (Value::Float64(f64::NAN), Aspect::Double),
+            (Value::Boolean(true), Aspect::Boolean),
+            (Value::Bitmask(u64::MAX), Aspect::Bitmask),
+            (
+                Value::String("\u{10FFFF}\u{10FFFF}".to_string()),
+                Aspect::String,
+            ),
+            (
+                Value::String("23:59:59.999999999-14:00".to_string()),
+                Aspect::Time,
+            ),
+            (Value::String("--12+14:00".to_string()), Aspect::GMonth),
+            (Value::String("FFFFFFFF".to_string()), Aspect::HexBinary),
+            (Value::String("99999999.99".to_string()), Aspect::Decimal),
+            (
+                Value::BigInt(Integer::from(u64::MAX) * Integer::from(u64::MAX)),
+                Aspect::Integer,
+            ),
+            (
+                Value::Rational(Rational::from((1_000_001, 1))),
+                Aspect::Rational,
+            ),
+        ];
+        for (v, a) in samples {
+            let key = value_to_storage(v, a).unwrap();
+            assert!(key < aspect_high_sentinel(a), "{a:?}");
         }
-        byte_vec.sort();
-        let mut result_vec = Vec::with_capacity(strings.len());
-        for b in byte_vec.iter() {
-            let bytes = Bytes::from(b.clone());
-            let (value, _) = storage_to_value(bytes).unwrap();
-            if let Value::String(v) = value {
-                result_vec.push(v)
-            } else {
-                panic!("This can't happen")
+        assert!(aspect_high_sentinel(Aspect::Int).starts_with(&aspect_prefix(Aspect::Int)));
+    }
+
+    #[test]
+    fn compact_and_inline_booleans() {
+        for b in [false, true] {
+            let compact = value_to_storage(Value::Boolean(b), Aspect::Boolean).unwrap();
+            let inline = [aspect_byte(Aspect::Boolean), u8::from(b)];
+            assert_eq!(
+                decode_one(&compact).unwrap(),
+                (Value::Boolean(b), Aspect::Boolean, 1)
+            );
+            assert_eq!(
+                decode_one(&inline).unwrap(),
+                (Value::Boolean(b), Aspect::Boolean, 2)
+            );
+        }
+        assert!(decode_exact(&[aspect_byte(Aspect::Boolean), 2]).is_err());
+    }
+
+    #[test]
+    fn estimated_size_bounds_encoding() {
+        let samples = vec![
+            (Value::Boolean(true), Aspect::Boolean),
+            (Value::Int32(-7), Aspect::Int),
+            (Value::Int64(7), Aspect::Long),
+            (Value::Float32(0.5), Aspect::Float),
+            (Value::Float64(-0.5), Aspect::Double),
+            (Value::Bitmask(3), Aspect::Bitmask),
+            (Value::BigInt(Integer::from(-1)), Aspect::Integer),
+            (
+                Value::BigInt(Integer::from(u64::MAX) * Integer::from(u64::MAX)),
+                Aspect::Integer,
+            ),
+            (
+                Value::Rational(Rational::from((-355, 113))),
+                Aspect::Rational,
+            ),
+            (Value::String("lexdata".to_string()), Aspect::String),
+            (Value::String("local".to_string()), Aspect::QName),
+            (Value::String("-0.000123".to_string()), Aspect::Decimal),
+            (
+                Value::String("98765432109876543210".to_string()),
+                Aspect::Decimal,
+            ),
+            (
+                Value::String("-1.5E-300".to_string()),
+                Aspect::DecimalExponent,
+            ),
+            (Value::String("00FF00".to_string()), Aspect::HexBinary),
+            (
+                Value::String("2020-01-01T00:00:00Z".to_string()),
+                Aspect::DateTime,
+            ),
+            (Value::String("13:45:30.5+02:00".to_string()), Aspect::Time),
+            (Value::String("--03-05:00".to_string()), Aspect::GMonth),
+        ];
+        for (v, a) in samples {
+            let estimate = estimated_size(&v, a);
+            let fixed = !matches!(v, Value::String(_) | Value::BigInt(_) | Value::Rational(_));
+            let stored = value_to_storage(v, a).unwrap();
+            let size = storage_size(Bytes::from(stored));
+            assert!(estimate >= size, "{a:?}");
+            if fixed {
+                assert_eq!(estimate, size, "{a:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn decode_ref_borrows() {
+        let key = field_to_storage(Value::String("borrowed".to_string()), Aspect::String).unwrap();
+        match decode_ref(&key).unwrap() {
+            (ValueRef::String(s), Aspect::String) => {
+                assert_eq!(s, "borrowed");
+                assert_eq!(s.as_ptr(), key[1..].as_ptr());
             }
+            other => panic!("expected a borrowed string, got {other:?}"),
         }
+        let int = Integer::from(u64::MAX) * Integer::from(-3);
+        let key = value_to_storage(Value::BigInt(int.clone()), Aspect::Integer).unwrap();
+        let (v, _) = decode_ref(&key).unwrap();
+        assert_eq!(v, ValueRef::BigInt(&key[1..]));
+        assert_eq!(v.to_value().unwrap(), Value::BigInt(int));
+        let key = value_to_storage(Value::Float64(-2.5), Aspect::Double).unwrap();
+        assert_eq!(decode_ref(&key).unwrap().0, ValueRef::Float64(-2.5));
+        let key = value_to_storage(Value::Boolean(true), Aspect::Boolean).unwrap();
         assert_eq!(
-            vec![
-                "apple",
-                "candy",
-                "entertain",
-                "pangolin",
-                "peaches",
-                "pears",
-                "penguin",
-                "plums",
-                "zig"
-            ],
-            result_vec
-        )
+            decode_ref(&key).unwrap(),
+            (ValueRef::Boolean(true), Aspect::Boolean)
+        );
+        assert!(matches!(
+            decode_ref(&[0x7f]),
+            Err(LexDataError::UnexpectedAspect(_))
+        ));
+        assert!(decode_ref(&[aspect_byte(Aspect::Duration)]).is_err());
+        assert!(matches!(
+            decode_ref(&[aspect_byte(Aspect::Integer)]),
+            Err(LexDataError::TruncatedInput(_))
+        ));
     }
 
     #[test]
-    fn boolean_round_trip() {
-        let res = round_trip(Value::Boolean(false), Aspect::Boolean);
-        assert_eq!((Value::Boolean(false), Aspect::Boolean), res);
-        let res = round_trip(Value::Boolean(true), Aspect::Boolean);
-        assert_eq!((Value::Boolean(true), Aspect::Boolean), res);
+    fn float64_signed_zero_and_nan() {
+        let round = |f: f64| match round_trip(Value::Float64(f), Aspect::Double).0 {
+            Value::Float64(g) => g,
+            _ => panic!("float64 storage must return float64"),
+        };
+        assert_eq!(round(-0.0).to_bits(), (-0.0_f64).to_bits());
+        assert_eq!(round(0.0).to_bits(), 0.0_f64.to_bits());
+        assert_eq!(round(f64::NAN).to_bits(), f64::NAN.to_bits());
+        assert_eq!(round(-f64::NAN).to_bits(), (-f64::NAN).to_bits());
+        let key = |f: f64| value_to_storage(Value::Float64(f), Aspect::Double).unwrap();
+        assert!(key(-f64::NAN) < key(f64::NEG_INFINITY));
+        assert!(key(f64::INFINITY) < key(f64::NAN));
+        assert!(key(-0.0) < key(0.0));
     }
 
+    #[cfg(feature = "serde_json")]
     #[test]
-    fn float32_round_trip() {
-        let res = round_trip(Value::Float32(-10.87_f32), Aspect::Float);
-        assert_eq!((Value::Float32(-10.87_f32), Aspect::Float), res);
+    fn json_round_trip() {
+        let big = "123456789012345678901234567890";
+        let cases = vec![
+            (serde_json::json!(big), Aspect::Integer),
+            (serde_json::json!(42), Aspect::Int),
+            (serde_json::json!(-42), Aspect::Long),
+            (serde_json::json!("-3.25"), Aspect::Decimal),
+            (serde_json::json!(true), Aspect::Boolean),
+            (serde_json::json!(0.5), Aspect::Double),
+        ];
+        for (j, a) in cases {
+            let v = value_from_json(&j, a).unwrap();
+            assert!(value_to_storage(v.clone(), a).is_ok());
+            assert_eq!(value_to_json(&v), j);
+        }
+        assert_eq!(
+            value_from_json(&serde_json::json!(big), Aspect::Integer).unwrap(),
+            Value::BigInt(big.parse::<Integer>().unwrap())
+        );
+        assert!(value_from_json(&serde_json::json!(1.5), Aspect::Int).is_err());
+        assert!(value_from_json(&serde_json::json!(null), Aspect::String).is_err());
     }
 
     #[test]
-    fn float32_ordering() {
-        let floats = [
-            32.5_f32,
-            33432.53_f32,
-            f32::INFINITY,
-            -132.98701_f32,
-            f32::MIN,
-            -100.3_f32,
-            f32::NEG_INFINITY,
-            22.5_f32,
-            0_f32,
-            f32::MAX,
-            0.1_f32,
-            -0.1_f32,
+    fn naive_time_round_trip() {
+        let times = [
+            NaiveTime::from_hms_nano_opt(0, 0, 0, 0).unwrap(),
+            NaiveTime::from_hms_nano_opt(13, 45, 30, 123_456_789).unwrap(),
+            NaiveTime::from_hms_nano_opt(23, 59, 59, 999_999_999).unwrap(),
         ];
-        let mut byte_vec = Vec::with_capacity(floats.len());
-        for f in floats.iter() {
-            let storage = value_to_storage(Value::Float32(*f), Aspect::Float).unwrap();
-            byte_vec.push(storage)
+        let keys: Vec<Vec<u8>> = times
+            .iter()
+            .map(|t| time_to_storage(*t, Aspect::Time).unwrap())
+            .collect();
+        for (t, key) in times.iter().zip(&keys) {
+            assert_eq!(storage_to_naive_time(&key[1..]).unwrap(), *t);
         }
-        byte_vec.sort();
-        let mut result_vec = Vec::with_capacity(floats.len());
-        for b in byte_vec.iter() {
-            let bytes = Bytes::from(b.clone());
-            let (value, _) = storage_to_value(bytes).unwrap();
-            if let Value::Float32(v) = value {
-                result_vec.push(v)
-            } else {
-                panic!("This can't happen")
-            }
+        for pair in keys.windows(2) {
+            assert!(pair[0] < pair[1]);
+        }
+        assert_eq!(
+            decode_exact(&keys[1]).unwrap().0,
+            Value::String("13:45:30.123456789".to_string())
+        );
+        let leap = NaiveTime::from_hms_nano_opt(23, 59, 59, 1_500_000_000).unwrap();
+        assert!(time_to_storage(leap, Aspect::Time).is_err());
+    }
+
+    // each component as a 1 byte followed by a big endian u32, ended by 0
+    fn semver_encode(v: &Value) -> Result<Vec<u8>, LexDataError> {
+        let Value::String(s) = v else {
+            return Err(LexDataError::BadLexicalForm(format!("{v:?}")));
+        };
+        let mut buf = Vec::new();
+        for part in s.split('.') {
+            let n: u32 = part
+                .parse()
+                .map_err(|_| LexDataError::BadLexicalForm(s.clone()))?;
+            buf.push(1);
+            buf.extend(n.to_be_bytes());
+        }
+        buf.push(0);
+        Ok(buf)
+    }
+
+    fn semver_decode(bytes: &[u8]) -> Result<(Value, usize), LexDataError> {
+        let mut parts = Vec::new();
+        let mut pos = 0;
+        while bytes.get(pos) == Some(&1) {
+            let n = bytes
+                .get(pos + 1..pos + 5)
+                .ok_or_else(|| LexDataError::TruncatedInput("semver".to_string()))?;
+            parts.push(u32::from_be_bytes(n.try_into().unwrap()).to_string());
+            pos += 5;
+        }
+        Ok((Value::String(parts.join(".")), pos + 1))
+    }
+
+    #[test]
+    fn custom_codec_round_trip() {
+        let tag = CUSTOM_TAG_MIN + 1;
+        register_custom(tag, semver_encode, semver_decode).unwrap();
+        assert!(
+            register_custom(aspect_byte(Aspect::Rational), semver_encode, semver_decode).is_err()
+        );
+        let custom = |s: &str| Value::Custom(tag, Box::new(Value::String(s.to_string())));
+        let keys: Vec<Vec<u8>> = ["1.2", "1.2.2", "1.2.10", "2.0"]
+            .iter()
+            .map(|s| value_to_storage(custom(s), Aspect::Custom).unwrap())
+            .collect();
+        for pair in keys.windows(2) {
+            assert!(pair[0] < pair[1]);
         }
         assert_eq!(
+            storage_to_value(Bytes::from(keys[2].clone())).unwrap(),
+            (custom("1.2.10"), Aspect::Custom)
+        );
+        let mut key = keys[1].clone();
+        key.extend(value_to_storage(Value::Int32(7), Aspect::Int).unwrap());
+        assert_eq!(
+            decode_tuple(&key).unwrap(),
             vec![
-                f32::NEG_INFINITY,
-                f32::MIN,
-                -132.98701,
-                -100.3,
-                -0.1,
-                0.0,
-                0.1,
-                22.5,
-                32.5,
-                33432.53,
-                f32::MAX,
-                f32::INFINITY,
-            ],
-            result_vec
-        )
+                (custom("1.2.2"), Aspect::Custom),
+                (Value::Int32(7), Aspect::Int)
+            ]
+        );
+        let unregistered = Value::Custom(0xfe, Box::new(Value::Int32(1)));
+        assert!(value_to_storage(unregistered, Aspect::Custom).is_err());
+        assert!(value_to_storage(custom("1.2"), Aspect::String).is_err());
+        assert!(register_custom(0xff, semver_encode, semver_decode).is_err());
+        // every custom key lies in the Custom region
+        let (start, end) = (
+            aspect_scan_start(Aspect::Custom),
+            aspect_high_sentinel(Aspect::Custom),
+        );
+        for key in &keys {
+            assert!(aspect_prefix(Aspect::Custom).contains(&key[0]));
+            assert!(start <= *key && *key < end);
+        }
+        // the codec reads past the end of a truncated key
+        let short = &keys[1][..keys[1].len() - 1];
+        assert!(matches!(
+            storage_size_checked(short),
+            Err(LexDataError::TruncatedInput(_))
+        ));
+        assert!(decode_one(short).is_err());
     }
 
     #[test]
-    fn float64_ordering() {
-        let floats = [
-            64.5_f64,
-            33464.53_f64,
-            f64::INFINITY,
-            -164.98701_f64,
-            -100.3_f64,
-            33464.533432_f64,
-            f64::NEG_INFINITY,
-            22.5_f64,
-            0_f64,
-            0.1_f64,
-            -0.1_f64,
+    fn truncated_strings_keep_order() {
+        let s = "naïve café";
+        for max in 0..=s.len() + 1 {
+            let key = encode_string_truncated(s, max, Aspect::String).unwrap();
+            assert!(key.len() <= max + 1);
+            let truncated = from_utf8(&key[1..]).unwrap();
+            assert!(s.starts_with(truncated));
+            assert!(key <= value_to_storage(Value::from(s), Aspect::String).unwrap());
+        }
+        // "ï" is two bytes, so a cut inside it falls back before it
+        assert_eq!(
+            &encode_string_truncated(s, 3, Aspect::String).unwrap()[1..],
+            b"na"
+        );
+        let low = value_to_storage(Value::from("naive"), Aspect::String).unwrap();
+        let high = value_to_storage(Value::from("naïvf"), Aspect::String).unwrap();
+        let key = encode_string_truncated(s, 6, Aspect::String).unwrap();
+        assert!(low < key && key < high);
+        assert!(encode_string_truncated("1.5", 1, Aspect::Double).is_err());
+        // cutting an NCName down to nothing leaves no valid name
+        assert!(encode_string_truncated("name", 0, Aspect::NCName).is_err());
+        assert!(encode_string_truncated("name", 2, Aspect::NCName).is_ok());
+    }
+
+    #[test]
+    fn versions_sort_by_component() {
+        let versions = [
+            "0",
+            "0.9",
+            "1",
+            "1.0",
+            "1.0.0",
+            "1.2",
+            "1.2.2",
+            "1.2.10",
+            "1.10",
+            "2",
+            "255.0",
+            "256",
+            "100000000000000000000",
         ];
-        let mut byte_vec = Vec::with_capacity(floats.len());
-        for f in floats.iter() {
-            let storage = value_to_storage(Value::Float64(*f), Aspect::Double).unwrap();
-            byte_vec.push(storage)
+        let keys: Vec<Vec<u8>> = versions
+            .iter()
+            .map(|s| value_to_storage(Value::from(*s), Aspect::Version).unwrap())
+            .collect();
+        for pair in keys.windows(2) {
+            assert!(pair[0] < pair[1], "{pair:?}");
         }
-        byte_vec.sort();
-        let mut result_vec = Vec::with_capacity(floats.len());
-        for b in byte_vec.iter() {
-            let bytes = Bytes::from(b.clone());
-            let (value, _) = storage_to_value(bytes).unwrap();
-            if let Value::Float64(v) = value {
-                result_vec.push(v)
-            } else {
-                panic!("This can't happen")
+        for (s, key) in versions.iter().zip(&keys) {
+            assert_eq!(
+                decode_exact(key).unwrap(),
+                (Value::from(*s), Aspect::Version)
+            );
+        }
+        assert_eq!(
+            canonical_lexical(&Value::from("1.02"), Aspect::Version).unwrap(),
+            "1.2"
+        );
+        for bad in ["", "1.", ".1", "1..2", "1.-2", "v1", "1.2a"] {
+            assert!(
+                value_to_storage(Value::from(bad), Aspect::Version).is_err(),
+                "{bad}"
+            );
+        }
+    }
+
+    #[test]
+    fn original_lexical_forms_are_kept() {
+        let encode = |original: &str| {
+            let v = parse_value(original, Aspect::Integer).unwrap();
+            encode_with_original(&v, Aspect::Integer, original).unwrap()
+        };
+        let (padded, plain, plus, eight) = (encode("007"), encode("7"), encode("+7"), encode("8"));
+        let key_size = value_to_storage(Value::from(Integer::from(7)), Aspect::Integer)
+            .unwrap()
+            .len();
+        assert_eq!(padded[..key_size], plain[..key_size]);
+        assert!(padded < eight && plain < eight && plus < eight);
+        assert_eq!(
+            decode_with_original(&padded).unwrap(),
+            (
+                Value::BigInt(Integer::from(7)),
+                Aspect::Integer,
+                "007".to_string()
+            )
+        );
+        assert_eq!(decode_with_original(&plus).unwrap().2, "+7");
+        let decimal =
+            encode_with_original(&Value::from("1.500"), Aspect::Decimal, "1.500").unwrap();
+        assert_eq!(decode_with_original(&decimal).unwrap().2, "1.500");
+        assert!(decode_with_original(&padded[..padded.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn size_encode_boundaries() {
+        let cases: [(u64, &[u8]); 11] = [
+            (0, &[0x80]),
+            (63, &[0xbf]),
+            (64, &[0xc0, 0x40]),
+            (127, &[0xc0, 0x7f]),
+            (128, &[0xc1, 0x00]),
+            (8191, &[0xff, 0x7f]),
+            (8192, &[0xff, 0x82, 0x20, 0x00]),
+            (16383, &[0xff, 0x82, 0x3f, 0xff]),
+            (16384, &[0xff, 0x82, 0x40, 0x00]),
+            (1 << 16, &[0xff, 0x83, 0x01, 0x00, 0x00]),
+            (
+                u64::MAX,
+                &[0xff, 0x88, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff],
+            ),
+        ];
+        for (n, expected) in cases {
+            let mut bytes = size_encode(n);
+            bytes.reverse();
+            assert_eq!(bytes, expected, "{n}");
+            assert_eq!(size_decode(&bytes), (true, n, bytes.len()), "{n}");
+            assert_eq!(decode_size(&bytes).unwrap(), (n, bytes.len()), "{n}");
+            let complemented: Vec<u8> = bytes.iter().map(|b| !b).collect();
+            assert_eq!(size_decode(&complemented), (false, n, bytes.len()), "{n}");
+            for end in 1..bytes.len() {
+                assert!(
+                    matches!(
+                        decode_size(&bytes[..end]),
+                        Err(LexDataError::TruncatedInput(_))
+                    ),
+                    "{n} {end}"
+                );
             }
         }
+        let sizes = (0..1 << 16).chain([1 << 24, (1 << 24) + 1, 1 << 40, u64::MAX]);
+        let mut last = encode_size(0);
+        for n in sizes.skip(1) {
+            let bytes = encode_size(n);
+            assert_eq!(size_decode(&bytes), (true, n, bytes.len()), "{n}");
+            assert!(last < bytes, "{n}");
+            last = bytes;
+        }
+        // larger sizes sort after smaller ones however long either is
+        assert!(encode_size(128) < encode_size(8191));
+        assert!(encode_size(8191) < encode_size(8192));
+        assert!(encode_size(8192) < encode_size(16384));
+        let big = |bytes: u32| {
+            // the sign bit takes the top bit of the magnitude
+            let mut n = Integer::from(1);
+            n <<= 8 * bytes - 2;
+            value_to_storage(Value::BigInt(n), Aspect::Integer).unwrap()
+        };
+        assert!(big(8191) < big(8192) && big(8192) < big(16384));
+        let key = big(8192);
+        assert_eq!(key.len(), 1 + 4 + 8192);
+        assert_eq!(decode_exact(&key).unwrap().1, Aspect::Integer);
+    }
+
+    #[test]
+    fn untagged_round_trip() {
+        let ints = [i32::MIN, -5, 0, 7, i32::MAX];
+        let keys: Vec<Vec<u8>> = ints
+            .iter()
+            .map(|i| encode_untagged(&Value::Int32(*i), Aspect::Int).unwrap())
+            .collect();
+        for (i, key) in ints.iter().zip(&keys) {
+            assert_eq!(key.len(), 4);
+            assert_eq!(decode_untagged(key, Aspect::Int).unwrap(), Value::Int32(*i));
+        }
+        for pair in keys.windows(2) {
+            assert!(pair[0] < pair[1]);
+        }
+        let strings = ["", "a", "ab", "b"];
+        let keys: Vec<Vec<u8>> = strings
+            .iter()
+            .map(|s| encode_untagged(&Value::from(*s), Aspect::String).unwrap())
+            .collect();
+        for (s, key) in strings.iter().zip(&keys) {
+            let tagged = value_to_storage(Value::from(*s), Aspect::String).unwrap();
+            assert_eq!(key.len() + 1, tagged.len());
+            assert_eq!(
+                decode_untagged(key, Aspect::String).unwrap(),
+                Value::from(*s)
+            );
+        }
+        for pair in keys.windows(2) {
+            assert!(pair[0] < pair[1]);
+        }
+        let t = encode_untagged(&Value::Boolean(true), Aspect::Boolean).unwrap();
         assert_eq!(
-            vec![
-                f64::NEG_INFINITY,
-                -164.98701,
-                -100.3,
-                -0.1,
-                0.0,
-                0.1,
-                22.5,
-                64.5,
-                33464.53,
-                33464.533432,
-                f64::INFINITY
-            ],
-            result_vec
-        )
+            decode_untagged(&t, Aspect::Boolean).unwrap(),
+            Value::Boolean(true)
+        );
+    }
+
+    #[test]
+    fn numeric_family_interleaves_widths() {
+        let tagged = |v: Value, a| value_to_storage(v, a).unwrap();
+        assert!(tagged(Value::Int32(100), Aspect::Int) < tagged(Value::Int64(-100), Aspect::Long));
+        let values = [
+            (
+                Value::BigInt("-100000000000000000000".parse().unwrap()),
+                Aspect::Integer,
+            ),
+            (Value::Int64(i64::MIN), Aspect::Long),
+            (Value::Int32(-300), Aspect::Int),
+            (Value::Int32(-2), Aspect::Short),
+            (Value::Int64(0), Aspect::Long),
+            (Value::Int32(1), Aspect::Byte),
+            (Value::BigInt(Integer::from(5)), Aspect::PositiveInteger),
+            (Value::Int32(7), Aspect::Int),
+            (Value::Int64(7), Aspect::Long),
+            (Value::Int64(1 << 40), Aspect::Long),
+        ];
+        let keys: Vec<Vec<u8>> = values
+            .iter()
+            .map(|(v, a)| encode_numeric(v, *a).unwrap())
+            .collect();
+        for pair in keys.windows(2) {
+            assert!(pair[0] < pair[1], "{pair:?}");
+        }
+        for ((v, a), key) in values.iter().zip(&keys) {
+            let (decoded, aspect) = decode_exact(key).unwrap();
+            assert_eq!((&decoded, aspect), (v, *a));
+            assert_eq!(encode_numeric(&decoded, aspect).unwrap(), *key);
+        }
+        // a trailing byte that is no aspect
+        let mut bad = keys[0].clone();
+        *bad.last_mut().unwrap() = 0x7f;
+        assert!(matches!(
+            decode_exact(&bad),
+            Err(LexDataError::UnexpectedAspect(_))
+        ));
+        assert_eq!(
+            decode_exact(&tagged(Value::Int32(3), Aspect::Numeric)).unwrap(),
+            (Value::Int32(3), Aspect::Int)
+        );
+        assert!(encode_numeric(&Value::Int32(300), Aspect::Byte).is_err());
+        assert!(encode_numeric(&Value::Int64(3), Aspect::Int).is_err());
+    }
+
+    #[test]
+    fn peek_aspect_reports_logical_aspects() {
+        let keys = [
+            value_to_storage(Value::Boolean(true), Aspect::Boolean).unwrap(),
+            value_to_storage(Value::Boolean(false), Aspect::Boolean).unwrap(),
+            vec![aspect_byte(Aspect::Boolean), 1],
+        ];
+        for key in &keys {
+            assert_eq!(peek_aspect(key).unwrap(), Aspect::Boolean);
+            assert_eq!(peek_aspect(key).unwrap(), decode_exact(key).unwrap().1);
+        }
+        let numeric = encode_numeric(&Value::Int32(-4), Aspect::Short).unwrap();
+        assert_eq!(peek_aspect(&numeric).unwrap(), Aspect::Short);
+        let long = value_to_storage(Value::Int64(9), Aspect::Long).unwrap();
+        assert_eq!(peek_aspect(&long).unwrap(), Aspect::Long);
+        assert!(peek_aspect(&[]).is_err());
+        assert!(peek_aspect(&[0x7f]).is_err());
+    }
+
+    #[test]
+    fn intervals_sort_by_low_then_high() {
+        let intervals = [(-5, 3), (-5, 10), (0, 0), (0, 7), (2, 4), (2, 9), (8, 9)];
+        let keys: Vec<Vec<u8>> = intervals
+            .iter()
+            .map(|(lo, hi)| {
+                encode_interval(&Value::Int32(*lo), &Value::Int32(*hi), Aspect::Int).unwrap()
+            })
+            .collect();
+        for pair in keys.windows(2) {
+            assert!(pair[0] < pair[1]);
+        }
+        for ((lo, hi), key) in intervals.iter().zip(&keys) {
+            assert_eq!(
+                decode_interval(key).unwrap(),
+                (Value::Int32(*lo), Value::Int32(*hi), Aspect::Int)
+            );
+        }
+        let words = encode_interval(&Value::from("a"), &Value::from("ab"), Aspect::String).unwrap();
+        assert_eq!(
+            decode_interval(&words).unwrap(),
+            (Value::from("a"), Value::from("ab"), Aspect::String)
+        );
+        assert!(encode_interval(&Value::Int32(2), &Value::Int32(1), Aspect::Int).is_err());
+        assert!(decode_interval(&keys[0][..5]).is_err());
+    }
+
+    #[test]
+    fn decimal_parts() {
+        let key = value_to_storage(Value::from("-123.4500"), Aspect::Decimal).unwrap();
+        let (is_neg, int, fraction) = storage_to_decimal_parts(&key[1..]).unwrap();
+        assert!(is_neg);
+        assert_eq!(int, 123);
+        assert_eq!(fraction, "4500");
+        let key = value_to_storage(Value::from("-0.25"), Aspect::Decimal).unwrap();
+        assert_eq!(
+            storage_to_decimal_parts(&key[1..]).unwrap(),
+            (true, Integer::new(), "25".to_string())
+        );
+        let key = value_to_storage(Value::from("42"), Aspect::Decimal).unwrap();
+        assert_eq!(
+            storage_to_decimal_parts(&key[1..]).unwrap(),
+            (false, Integer::from(42), String::new())
+        );
+    }
+
+    #[test]
+    fn date_times_reject_bare_integers() {
+        match value_to_storage(Value::Int64(0), Aspect::DateTime) {
+            Err(LexDataError::ValueAspectMismatch { value_kind, aspect }) => {
+                assert_eq!((value_kind, aspect), ("Int64", Aspect::DateTime));
+            }
+            other => panic!("expected a mismatch, got {other:?}"),
+        }
+        let parsed =
+            value_to_storage(Value::from("1970-01-01T00:00:00Z"), Aspect::DateTime).unwrap();
+        assert_eq!(timestamp_to_storage(0, Aspect::DateTime).unwrap(), parsed);
+        assert!(timestamp_to_storage(0, Aspect::Long).is_err());
+        assert!(timestamp_to_storage(i64::MAX, Aspect::DateTime).is_err());
+    }
+
+    #[test]
+    fn separators_split_sorted_keys() {
+        let check = |keys: &[Vec<u8>]| {
+            for pair in keys.windows(2) {
+                let s = separator(&pair[0], &pair[1]).unwrap();
+                assert!(pair[0] < s && s <= pair[1], "{pair:?}");
+                assert!(s.len() <= pair[1].len());
+                // no shorter prefix of the larger key would do
+                assert!(pair[1][..s.len() - 1] <= pair[0][..]);
+            }
+        };
+        let strings: Vec<Vec<u8>> = ["", "apple", "applesauce", "apricot", "b", "banana"]
+            .iter()
+            .map(|s| value_to_storage(Value::from(*s), Aspect::String).unwrap())
+            .collect();
+        check(&strings);
+        assert_eq!(
+            separator(&strings[2], &strings[3]).unwrap(),
+            [&strings[3][..3], b"r"].concat()
+        );
+        let ints: Vec<Vec<u8>> = [-70000, -1, 0, 1, 255, 256, 70000]
+            .iter()
+            .map(|i| value_to_storage(Value::Int32(*i), Aspect::Int).unwrap())
+            .collect();
+        check(&ints);
+        assert_eq!(separator(&ints[4], &ints[5]).unwrap().len(), 4);
+        assert!(separator(&ints[5], &ints[4]).is_err());
+        assert!(separator(&ints[4], &ints[4]).is_err());
     }
 
     #[test]
-    fn int32_ordering() {
-        let int = [64_i32, 33464, 164, -100, 22, 0, 1, -1];
-        let mut byte_vec = Vec::with_capacity(int.len());
-        for f in int.iter() {
-            let storage = value_to_storage(Value::Int32(*f), Aspect::Int).unwrap();
-            byte_vec.push(storage)
+    fn date_time_end_of_day_and_leap_seconds() {
+        let encode = |s: &str| value_to_storage(Value::from(s), Aspect::DateTime);
+        let midnight = encode("2007-03-02T00:00:00Z").unwrap();
+        assert_eq!(encode("2007-03-01T24:00:00Z").unwrap(), midnight);
+        assert_eq!(encode("2007-03-01T24:00:00.000Z").unwrap(), midnight);
+        assert_eq!(
+            encode("2007-02-28T24:00:00Z").unwrap(),
+            encode("2007-03-01T00:00:00Z").unwrap()
+        );
+        assert_eq!(
+            encode("2007-03-01T24:00:00+01:00").unwrap(),
+            encode("2007-03-01T23:00:00Z").unwrap()
+        );
+        assert_eq!(
+            canonical_lexical(&Value::from("2007-03-01T24:00:00Z"), Aspect::DateTime).unwrap(),
+            "2007-03-02T00:00:00Z"
+        );
+        assert!(encode("2007-03-01T24:00:01Z").is_err());
+        assert!(encode("2007-03-01T24:00:00.5Z").is_err());
+        match encode("2016-12-31T23:59:60Z") {
+            Err(LexDataError::BadDateFormat(msg)) => assert!(msg.contains("leap second")),
+            other => panic!("expected the leap second to be rejected, got {other:?}"),
         }
-        byte_vec.sort();
-        let mut result_vec = Vec::with_capacity(int.len());
-        for b in byte_vec.iter() {
-            let bytes = Bytes::from(b.clone());
-            let (value, _) = storage_to_value(bytes).unwrap();
-            if let Value::Int32(v) = value {
-                result_vec.push(v)
-            } else {
-                panic!("This can't happen")
+    }
+
+    #[test]
+    fn numeric_equality_across_widths() {
+        let fives = [
+            Value::Int32(5),
+            Value::Int64(5),
+            Value::BigInt(Integer::from(5)),
+            Value::from("5.0"),
+            Value::from("+005"),
+            Value::Rational(Rational::from(5)),
+            Value::Float64(5.0),
+        ];
+        for a in &fives {
+            for b in &fives {
+                assert!(value_eq_numeric(a, b), "{a:?} {b:?}");
             }
         }
-        assert_eq!(vec![-100, -1, 0, 1, 22, 64, 164, 33464], result_vec)
+        assert_ne!(Value::Int32(5), Value::Int64(5));
+        assert!(!value_eq_numeric(&Value::Int32(5), &Value::Int64(6)));
+        assert!(value_eq_numeric(
+            &Value::from("-0.50"),
+            &Value::Rational(Rational::from((-1, 2)))
+        ));
+        assert!(!value_eq_numeric(&Value::Float64(0.1), &Value::from("0.1")));
+        assert!(value_eq_numeric(&Value::Float32(0.5), &Value::from("0.5")));
+        assert!(!value_eq_numeric(
+            &Value::Float64(f64::NAN),
+            &Value::Float64(f64::NAN)
+        ));
+        assert!(value_eq_numeric(&Value::from("abc"), &Value::from("abc")));
+        assert!(!value_eq_numeric(&Value::from("abc"), &Value::Int32(5)));
     }
 
     #[test]
-    fn int64_ordering() {
-        let int = [64_i64, 33464, 164, -100, -234234322, 22, 0, 1, -1];
-        let mut byte_vec = Vec::with_capacity(int.len());
-        for f in int.iter() {
-            let storage = value_to_storage(Value::Int64(*f), Aspect::Long).unwrap();
-            byte_vec.push(storage)
-        }
-        byte_vec.sort();
-        let mut result_vec = Vec::with_capacity(int.len());
-        for b in byte_vec.iter() {
-            let bytes = Bytes::from(b.clone());
-            let (value, _) = storage_to_value(bytes).unwrap();
-            if let Value::Int64(v) = value {
-                result_vec.push(v)
-            } else {
-                panic!("This can't happen")
+    fn aspect_regions_hold_their_values() {
+        let cases = [
+            (
+                Aspect::String,
+                vec![Value::from(""), Value::from("\u{10ffff}\u{10ffff}")],
+            ),
+            (
+                Aspect::Int,
+                vec![Value::Int32(i32::MIN), Value::Int32(i32::MAX)],
+            ),
+            (
+                Aspect::Long,
+                vec![Value::Int64(i64::MIN), Value::Int64(i64::MAX)],
+            ),
+            (
+                Aspect::Double,
+                vec![Value::Float64(f64::NEG_INFINITY), Value::Float64(f64::NAN)],
+            ),
+            (
+                Aspect::Integer,
+                vec![
+                    Value::BigInt(format!("-1{}", "0".repeat(60)).parse().unwrap()),
+                    Value::BigInt(format!("1{}", "0".repeat(60)).parse().unwrap()),
+                ],
+            ),
+            (
+                Aspect::Decimal,
+                vec![Value::from("-99999.99"), Value::from("99999.99")],
+            ),
+            (
+                Aspect::Boolean,
+                vec![Value::Boolean(false), Value::Boolean(true)],
+            ),
+            (
+                Aspect::Time,
+                vec![
+                    Value::from("00:00:00"),
+                    Value::from("23:59:59.999999999-14:00"),
+                ],
+            ),
+            (
+                Aspect::Version,
+                vec![Value::from("0"), Value::from("999.999.999")],
+            ),
+        ];
+        for (a, values) in cases {
+            let (start, end) = (aspect_scan_start(a), aspect_high_sentinel(a));
+            for v in values {
+                let key = value_to_storage(v, a).unwrap();
+                assert!(start <= key && key < end, "{a:?} {key:?}");
             }
         }
-        assert_eq!(
-            vec![-234234322, -100, -1, 0, 1, 22, 64, 164, 33464],
-            result_vec
-        )
     }
 
     #[test]
-    fn size_encode_correct() {
-        let bytes = size_encode(1);
-        assert_eq!(bytes, vec![129]);
-
-        let (is_pos, size, idx) = size_decode(&bytes);
-        assert!(is_pos);
-        assert_eq!(size, 1);
-        assert_eq!(idx, 1);
-
-        let bytes = size_encode(12);
-        assert_eq!(bytes, vec![140]);
+    fn rowids_break_ties() {
+        let key = field_to_storage(Value::from("same"), Aspect::String).unwrap();
+        let mut rows: Vec<Vec<u8>> = [300_u64, 2, u64::MAX, 0]
+            .iter()
+            .map(|rowid| {
+                let mut row = key.clone();
+                append_rowid(&mut row, *rowid);
+                row
+            })
+            .collect();
+        rows.sort();
+        let rowids: Vec<u64> = rows
+            .iter()
+            .map(|row| {
+                let (primary, rowid) = split_rowid(row).unwrap();
+                assert_eq!(primary, key);
+                rowid
+            })
+            .collect();
+        assert_eq!(rowids, [0, 2, 300, u64::MAX]);
+        let mut higher = field_to_storage(Value::from("samea"), Aspect::String).unwrap();
+        append_rowid(&mut higher, 0);
+        assert!(rows.iter().all(|row| *row < higher));
+        assert!(split_rowid(&[1, 2, 3]).is_err());
+    }
 
-        let bytes = size_encode(0);
-        assert_eq!(bytes, vec![191]);
+    #[test]
+    fn bigints_narrow_to_int64() {
+        let i = Integer::from(i64::MIN);
+        assert_eq!(
+            bigint_to_int64_storage(&i, Aspect::Long).unwrap(),
+            value_to_storage(Value::Int64(i64::MIN), Aspect::Long).unwrap()
+        );
+        let too_big: Integer = "9223372036854775808".parse().unwrap();
+        assert!(matches!(
+            bigint_to_int64_storage(&too_big, Aspect::Long),
+            Err(LexDataError::OutOfRange(_))
+        ));
+        assert!(bigint_to_int64_storage(&Integer::from(1), Aspect::Int).is_err());
+    }
 
-        let bytes = size_encode(0);
-        let (is_pos, size, idx) = size_decode(&bytes);
-        assert!(is_pos);
-        assert_eq!(size, 0);
-        assert_eq!(idx, 1);
+    #[test]
+    fn sorting_by_encoding_matches_natural_order() {
+        let mut numbers = vec![12_i64, -3, 0, i64::MAX, -400, 7, i64::MIN];
+        let mut items: Vec<(Value, Aspect)> = numbers
+            .iter()
+            .map(|i| (Value::Int64(*i), Aspect::Long))
+            .collect();
+        sort_by_encoding(&mut items).unwrap();
+        numbers.sort();
+        let expected: Vec<(Value, Aspect)> = numbers
+            .iter()
+            .map(|i| (Value::Int64(*i), Aspect::Long))
+            .collect();
+        assert_eq!(items, expected);
+        let mut words = vec![
+            (Value::from("pear"), Aspect::String),
+            (Value::from("apple"), Aspect::String),
+            (Value::from("fig"), Aspect::String),
+        ];
+        sort_by_encoding(&mut words).unwrap();
+        assert_eq!(words[0].0, Value::from("apple"));
+        assert_eq!(words[2].0, Value::from("pear"));
+        let mut bad = vec![
+            (Value::Int32(2), Aspect::Int),
+            (Value::Int32(1), Aspect::String),
+        ];
+        assert!(sort_by_encoding(&mut bad).is_err());
+        assert_eq!(bad[0].0, Value::Int32(2));
     }
 
     #[test]
-    fn bigint_roundtrip_negative() {
-        let minus_one = "-1".parse::<Integer>().unwrap();
-        let res = round_trip(Value::BigInt(minus_one.clone()), Aspect::Integer);
-        assert_eq!((Value::BigInt(minus_one), Aspect::Integer), res);
+    fn reversed_strings_cluster_by_suffix() {
+        let words = ["running", "jumped", "jumping", "singer", "ring", "héllo"];
+        let mut keys: Vec<Vec<u8>> = words
+            .iter()
+            .map(|w| encode_string_reversed(w, Aspect::String).unwrap())
+            .collect();
+        for (w, key) in words.iter().zip(&keys) {
+            assert_eq!(
+                decode_string_reversed(key).unwrap(),
+                (Value::from(*w), Aspect::String)
+            );
+        }
+        keys.sort();
+        let suffix = encode_string_reversed("ing", Aspect::String).unwrap();
+        let matching: Vec<Value> = keys
+            .iter()
+            .filter(|key| key.starts_with(&suffix))
+            .map(|key| decode_string_reversed(key).unwrap().0)
+            .collect();
+        assert_eq!(
+            matching,
+            [
+                Value::from("running"),
+                Value::from("jumping"),
+                Value::from("ring")
+            ]
+        );
+        // the name is checked as given, not as reversed
+        let id = encode_string_reversed("id1", Aspect::NCName).unwrap();
+        assert_eq!(
+            decode_string_reversed(&id).unwrap(),
+            (Value::from("id1"), Aspect::NCName)
+        );
+        assert!(encode_string_reversed("1d", Aspect::NCName).is_err());
+        assert!(encode_string_reversed("p:local", Aspect::QName).is_err());
+        assert!(encode_string_reversed("12", Aspect::Int).is_err());
+        let first = keys
+            .iter()
+            .position(|key| key.starts_with(&suffix))
+            .unwrap();
+        assert!(keys[first..first + 3]
+            .iter()
+            .all(|key| key.starts_with(&suffix)));
+    }
 
-        let minus_two_hundred = "-200".parse::<Integer>().unwrap();
-        let res = round_trip(Value::BigInt(minus_two_hundred.clone()), Aspect::Integer);
-        assert_eq!((Value::BigInt(minus_two_hundred), Aspect::Integer), res);
+    #[test]
+    fn checked_storage_sizes() {
+        assert!(matches!(
+            storage_size_checked(&[]),
+            Err(LexDataError::TruncatedInput(_))
+        ));
+        assert!(storage_size_checked(&[0]).is_err());
+        let decimal = value_to_storage(Value::from("-12345.678"), Aspect::Decimal).unwrap();
+        assert_eq!(storage_size_checked(&decimal).unwrap(), decimal.len());
+        for end in 1..decimal.len() {
+            assert!(storage_size_checked(&decimal[..end]).is_err(), "{end}");
+        }
+        let big =
+            value_to_storage(Value::BigInt(Integer::from(i64::MAX)), Aspect::Integer).unwrap();
+        assert!(storage_size_checked(&big[..big.len() - 1]).is_err());
+        let string = value_to_storage(Value::from("abc"), Aspect::String).unwrap();
+        assert_eq!(storage_size_checked(&string).unwrap(), 4);
+        let mut field = field_to_storage(Value::from("abc"), Aspect::String).unwrap();
+        field.extend(value_to_storage(Value::Int32(1), Aspect::Int).unwrap());
+        assert_eq!(storage_size_checked(&field).unwrap(), 5);
+        let int = value_to_storage(Value::Int32(1), Aspect::Int).unwrap();
+        assert!(storage_size_checked(&int[..3]).is_err());
+        assert!(storage_size_checked(&[aspect_byte(Aspect::Bitmask)]).is_err());
+        // a size prefix near u64::MAX must not overflow
+        let mut huge = vec![aspect_byte(Aspect::Integer), 0xff];
+        huge.extend([0xff; 8]);
+        huge.push(0x7f);
+        assert!(storage_size_checked(&huge).is_err());
+        assert!(field_ranges(&huge).is_err());
+        let version = value_to_storage(Value::from("1.20.300"), Aspect::Version).unwrap();
+        assert_eq!(storage_size_checked(&version).unwrap(), version.len());
+        for end in 1..version.len() {
+            assert!(
+                matches!(
+                    storage_size_checked(&version[..end]),
+                    Err(LexDataError::TruncatedInput(_))
+                ),
+                "{end}"
+            );
+        }
+        let rational = value_to_storage(
+            Value::Rational(Rational::from((-355, 113))),
+            Aspect::Rational,
+        )
+        .unwrap();
+        assert_eq!(storage_size_checked(&rational).unwrap(), rational.len());
+        for end in 1..rational.len() {
+            assert!(
+                matches!(
+                    storage_size_checked(&rational[..end]),
+                    Err(LexDataError::TruncatedInput(_))
+                ),
+                "{end}"
+            );
+        }
+        assert!(field_ranges(&version[..version.len() - 1]).is_err());
     }
 
     #[test]
-    fn bigint_ordering() {
-        let int_strings = [
-            "64",
-            "33464",
-            "164",
-            "-100",
-            "256",
-            "-923423234234322",
-            "22",
-            "0",
-            "1",
-            "-1",
-            "234987394839323",
+    fn numbers_decode_as_rationals() {
+        let cases = [
+            (Value::Int32(-7), Aspect::Int, Rational::from(-7)),
+            (
+                Value::Int64(1 << 40),
+                Aspect::Long,
+                Rational::from(1_i64 << 40),
+            ),
+            (
+                Value::BigInt(Integer::from(12)),
+                Aspect::Integer,
+                Rational::from(12),
+            ),
+            (
+                Value::from("-12.25"),
+                Aspect::Decimal,
+                Rational::from((-49, 4)),
+            ),
+            (
+                Value::from("0.100"),
+                Aspect::Decimal,
+                Rational::from((1, 10)),
+            ),
+            (
+                Value::Rational(Rational::from((2, 3))),
+                Aspect::Rational,
+                Rational::from((2, 3)),
+            ),
         ];
-        let int = int_strings.map(|s| s.parse::<Integer>().unwrap());
-        let mut byte_vec = Vec::with_capacity(int.len());
-        for i in int.iter() {
-            let storage = value_to_storage(Value::BigInt(i.clone()), Aspect::Integer).unwrap();
-            byte_vec.push(storage)
-        }
-        byte_vec.sort();
-        let mut result_vec = Vec::with_capacity(int.len());
-        for b in byte_vec.iter() {
-            let bytes = Bytes::from(b.clone());
-            let (value, _) = storage_to_value(bytes).unwrap();
-            if let Value::BigInt(v) = value {
-                let res = format!("{v:?}");
-                result_vec.push(res)
-            } else {
-                panic!("This can't happen")
-            }
+        for (v, a, expected) in cases {
+            let key = value_to_storage(v, a).unwrap();
+            assert_eq!(decode_number(&key).unwrap(), (expected, a));
         }
+        let float = value_to_storage(Value::Float64(0.5), Aspect::Double).unwrap();
+        assert!(decode_number(&float).is_err());
+        let string = value_to_storage(Value::from("12"), Aspect::String).unwrap();
+        assert!(decode_number(&string).is_err());
+    }
+
+    #[test]
+    fn invalid_utf8_is_replaced() {
+        // a lone surrogate as CESU-8 would write it, and a stray continuation byte
+        let input = b"ab\xed\xa0\x80c\x80d";
+        let key = string_to_storage_lossy(input, Aspect::String).unwrap();
+        let (v, a) = decode_exact(&key).unwrap();
+        assert_eq!(a, Aspect::String);
+        let Value::String(s) = v else {
+            panic!("strings decode to strings");
+        };
+        assert!(s.starts_with("ab\u{fffd}"));
+        assert!(s.ends_with("c\u{fffd}d"));
         assert_eq!(
-            vec![
-                "-923423234234322",
-                "-100",
-                "-1",
-                "0",
-                "1",
-                "22",
-                "64",
-                "164",
-                "256",
-                "33464",
-                "234987394839323"
-            ],
-            result_vec
-        )
+            string_to_storage_lossy(b"plain", Aspect::String).unwrap(),
+            value_to_storage(Value::from("plain"), Aspect::String).unwrap()
+        );
+        assert!(string_to_storage_lossy(b"\xffid", Aspect::NCName).is_err());
+        assert!(string_to_storage_lossy(b"12", Aspect::Int).is_err());
     }
 
     #[test]
-    fn float64_round_trip() {
-        let res = round_trip(Value::Float64(-10.87_f64), Aspect::Double);
-        assert_eq!((Value::Float64(-10.87_f64), Aspect::Double), res);
+    fn decimal_integer_parts_compare() {
+        let key = |s: &str| value_to_storage(Value::from(s), Aspect::Decimal).unwrap();
+        assert_eq!(
+            decimal_integer_cmp(&key("12.9"), &key("13.1")).unwrap(),
+            Ordering::Less
+        );
+        assert_eq!(
+            decimal_integer_cmp(&key("-2.5"), &key("-3")).unwrap(),
+            Ordering::Greater
+        );
+        assert_eq!(
+            decimal_integer_cmp(&key("1000"), &key("999.99")).unwrap(),
+            Ordering::Greater
+        );
+        assert_eq!(
+            decimal_integer_cmp(&key("-0.5"), &key("0.5")).unwrap(),
+            Ordering::Less
+        );
+        assert_eq!(
+            decimal_integer_cmp(&key("7.25"), &key("7.5")).unwrap(),
+            Ordering::Equal
+        );
+        assert!(key("7.25") < key("7.5"));
+        assert_eq!(
+            decimal_integer_cmp(&key("-7.25"), &key("-7.5")).unwrap(),
+            Ordering::Equal
+        );
+        let int = value_to_storage(Value::Int32(7), Aspect::Int).unwrap();
+        assert!(decimal_integer_cmp(&int, &key("7")).is_err());
+        assert!(decimal_integer_cmp(&key("123456789")[..3], &key("7")).is_err());
     }
 
     #[test]
-    fn fraction_round_trip() {
-        let s = "12325";
-        let f = encode_fraction(Some(s));
-        let original = decode_fraction(&f);
-        assert_eq!(s, original);
+    fn whitespace_facets_keep_the_original() {
+        assert_eq!(
+            normalize_whitespace(" a\t\n b ", Aspect::Token).unwrap(),
+            "a b"
+        );
+        assert_eq!(
+            normalize_whitespace(" a\t\n b ", Aspect::NormalizedString).unwrap(),
+            " a   b "
+        );
+        assert!(normalize_whitespace("a", Aspect::String).is_none());
+        let raw = encode_whitespace_preserving("a\t b", Aspect::Token).unwrap();
+        let plain = encode_whitespace_preserving("a b", Aspect::Token).unwrap();
+        let normalized = field_to_storage(Value::from("a b"), Aspect::Token).unwrap();
+        assert!(raw.starts_with(&normalized) && plain.starts_with(&normalized));
+        assert!(raw < encode_whitespace_preserving("a c", Aspect::Token).unwrap());
+        assert!(encode_whitespace_preserving("a a", Aspect::Token).unwrap() < raw);
+        assert_eq!(
+            decode_whitespace_preserving(&raw).unwrap(),
+            (Value::from("a\t b"), Aspect::Token)
+        );
+        assert_eq!(
+            decode_whitespace_preserving(&plain).unwrap().0,
+            Value::from("a b")
+        );
+        assert!(encode_whitespace_preserving("a", Aspect::String).is_err());
     }
 
     #[test]
-    fn fraction_round_trip_empty() {
-        let s = "";
-        let f = encode_fraction(Some(s));
-        let original = decode_fraction(&f);
-        assert_eq!(s, original);
+    fn lists_sort_element_wise() {
+        let lists: [&[i32]; 6] = [&[], &[1], &[1, 2], &[1, 2, 0], &[1, 3], &[2]];
+        let keys: Vec<Vec<u8>> = lists
+            .iter()
+            .map(|l| {
+                let values: Vec<Value> = l.iter().map(|i| Value::Int32(*i)).collect();
+                encode_list(&values, Aspect::Int).unwrap()
+            })
+            .collect();
+        for pair in keys.windows(2) {
+            assert!(pair[0] < pair[1], "{pair:?}");
+        }
+        for (l, key) in lists.iter().zip(&keys) {
+            let expected: Vec<Value> = l.iter().map(|i| Value::Int32(*i)).collect();
+            assert_eq!(decode_list(key).unwrap(), expected);
+        }
+        let words = [Value::from("a"), Value::from("ab"), Value::from("")];
+        let key = encode_list(&words, Aspect::String).unwrap();
+        assert_eq!(decode_list(&key).unwrap(), words);
+        assert!(
+            encode_list(&words, Aspect::String).unwrap()
+                < encode_list(&[Value::from("b")], Aspect::String).unwrap()
+        );
+        assert!(decode_list(&keys[2][..keys[2].len() - 1]).is_err());
+    }
+
+    #[test]
+    fn canonical_decimals_share_a_key() {
+        let short = decimal_to_storage_canonical("1.5", Aspect::Decimal).unwrap();
+        let long = decimal_to_storage_canonical("1.50", Aspect::Decimal).unwrap();
+        assert_eq!(short, long);
+        assert_ne!(
+            long,
+            value_to_storage(Value::from("1.50"), Aspect::Decimal).unwrap()
+        );
+        assert_eq!(
+            storage_to_value(Bytes::from(long)).unwrap(),
+            (Value::from("1.5"), Aspect::Decimal)
+        );
+        assert_eq!(
+            decimal_to_storage_canonical("-002.000", Aspect::Decimal).unwrap(),
+            value_to_storage(Value::from("-2"), Aspect::Decimal).unwrap()
+        );
+        assert!(decimal_to_storage_canonical("1.5x", Aspect::Decimal).is_err());
+    }
+
+    #[test]
+    fn record_len_walks_length_prefixed_strings() {
+        let mut buf = encode_string_length_prefixed("a\0b");
+        buf.extend(encode_string_length_prefixed(""));
+        buf.extend(value_to_storage(Value::Int32(3), Aspect::Int).unwrap());
+        let first = record_len(&buf, Aspect::PrefixedString).unwrap();
+        assert_eq!(first, 5);
+        assert_eq!(
+            decode_string_length_prefixed(&buf[..first]).unwrap(),
+            Value::from("a\0b")
+        );
+        let second = first + record_len(&buf[first..], Aspect::PrefixedString).unwrap();
+        assert_eq!(second, 7);
+        assert_eq!(
+            decode_string_length_prefixed(&buf[first..second]).unwrap(),
+            Value::from("")
+        );
+        assert_eq!(record_len(&buf[second..], Aspect::Int).unwrap(), 5);
+        assert!(record_len(&buf[second..], Aspect::PrefixedString).is_err());
+        assert!(record_len(&buf[..first - 1], Aspect::PrefixedString).is_err());
+        assert!(record_len(&buf, Aspect::String).is_err());
+        assert!(decode_string_length_prefixed(&buf[..second]).is_err());
+        // the records are ordinary values, so the whole buffer decodes
+        assert_eq!(
+            decode_tuple(&buf).unwrap(),
+            vec![
+                (Value::from("a\0b"), Aspect::PrefixedString),
+                (Value::from(""), Aspect::PrefixedString),
+                (Value::Int32(3), Aspect::Int)
+            ]
+        );
+        assert_eq!(
+            value_to_storage(Value::from("a\0b"), Aspect::PrefixedString).unwrap(),
+            buf[..first]
+        );
+        assert_eq!(
+            decode_ref(&buf[..first]).unwrap(),
+            (ValueRef::String("a\0b"), Aspect::PrefixedString)
+        );
+        // longer strings sort after shorter ones at every length
+        let lengths = [0, 63, 64, 8191, 8192, 16384, 1 << 16];
+        let keys: Vec<Vec<u8>> = lengths
+            .iter()
+            .map(|n| encode_string_length_prefixed(&"z".repeat(*n)))
+            .collect();
+        for (pair, n) in keys.windows(2).zip(&lengths[1..]) {
+            assert!(pair[0] < pair[1], "{n}");
+        }
+        assert!(
+            encode_string_length_prefixed(&"z".repeat(8191))
+                < encode_string_length_prefixed(&"a".repeat(8192))
+        );
+        assert_eq!(
+            record_len(&keys[4], Aspect::PrefixedString).unwrap(),
+            1 + 4 + 8192
+        );
+        let mut bad = buf[..first].to_vec();
+        bad[2] = 0xff;
+        assert!(matches!(
+            decode_exact(&bad),
+            Err(LexDataError::BadLexicalForm(_))
+        ));
     }
 
     #[test]
-    fn fraction_trailing_zero() {
-        let s = "10";
-        let f = encode_fraction(Some(s));
-        let original = decode_fraction(&f);
-        assert_eq!(s, original);
+    fn int_width_three_sorts() {
+        let values = [-8_388_608, -65_536, -256, -1, 0, 1, 255, 65_536, 8_388_607];
+        let keys: Vec<Vec<u8>> = values
+            .iter()
+            .map(|i| int_to_storage_width(*i, 3).unwrap())
+            .collect();
+        for pair in keys.windows(2) {
+            assert!(pair[0] < pair[1], "{pair:?}");
+        }
+        for (i, key) in values.iter().zip(&keys) {
+            assert_eq!(key.len(), 5);
+            assert_eq!(storage_to_int_width(key).unwrap(), *i);
+            assert_eq!(
+                decode_exact(key).unwrap(),
+                (Value::Int64(*i), Aspect::SizedInt)
+            );
+        }
+        assert!(int_to_storage_width(8_388_608, 3).is_err());
+        assert!(int_to_storage_width(-8_388_609, 3).is_err());
+        assert!(int_to_storage_width(0, 9).is_err());
+        assert!(storage_to_int_width(&keys[0][..4]).is_err());
+        assert!(storage_size_checked(&keys[0][..4]).is_err());
+        // the width lets a sized integer be one field of a compound key
+        let key = [keys[1].clone(), keys[2].clone()].concat();
+        assert_eq!(field_ranges(&key).unwrap(), vec![0..5, 5..10]);
+        let long = int_to_storage_width(i64::MIN, 8).unwrap();
+        assert_eq!(
+            long,
+            value_to_storage(Value::Int64(i64::MIN), Aspect::SizedInt).unwrap()
+        );
+        assert_eq!(storage_to_int_width(&long).unwrap(), i64::MIN);
+        let plain = value_to_storage(Value::Int64(5), Aspect::Long).unwrap();
+        assert!(storage_to_int_width(&plain).is_err());
     }
 
     #[test]
-    fn fraction_mid_one() {
-        let s = "101";
-        let f = encode_fraction(Some(s));
-        let original = decode_fraction(&f);
-        assert_eq!(s, original);
+    fn recanonicalize_decimal_keys() {
+        let long = value_to_storage(Value::from("1.50"), Aspect::Decimal).unwrap();
+        let short = value_to_storage(Value::from("1.5"), Aspect::Decimal).unwrap();
+        assert_eq!(recanonicalize(&long).unwrap(), short);
+        assert_eq!(recanonicalize(&short).unwrap(), short);
+        let negative_zero = value_to_storage(Value::from("-0.00"), Aspect::Decimal).unwrap();
+        assert_eq!(
+            recanonicalize(&negative_zero).unwrap(),
+            value_to_storage(Value::from("0"), Aspect::Decimal).unwrap()
+        );
+        let int = value_to_storage(Value::Int32(150), Aspect::Int).unwrap();
+        assert_eq!(recanonicalize(&int).unwrap(), int);
     }
 
     #[test]
-    fn fraction_leading_zeros() {
-        let s = "001";
-        let f = encode_fraction(Some(s));
-        let original = decode_fraction(&f);
-        assert_eq!(s, original);
+    fn directed_tuple_round_trip() {
+        let dirs = [SortDir::Ascending, SortDir::Descending];
+        let key = |name: &str, score: i32| {
+            let mut key =
+                field_to_storage_directed(Value::from(name), Aspect::String, dirs[0]).unwrap();
+            key.extend(
+                field_to_storage_directed(Value::Int32(score), Aspect::Int, dirs[1]).unwrap(),
+            );
+            key
+        };
+        let keys = [key("a", 10), key("a", 3), key("a", -5), key("b", 7)];
+        for pair in keys.windows(2) {
+            assert!(pair[0] < pair[1], "{pair:?}");
+        }
+        assert_eq!(
+            decode_tuple_directed(&keys[2], &dirs).unwrap(),
+            vec![
+                (Value::from("a"), Aspect::String),
+                (Value::Int32(-5), Aspect::Int)
+            ]
+        );
+        assert_ne!(
+            decode_tuple(&keys[2]).ok(),
+            Some(vec![
+                (Value::from("a"), Aspect::String),
+                (Value::Int32(-5), Aspect::Int)
+            ])
+        );
+        let strings = [
+            field_to_storage_directed(Value::from("ab"), Aspect::String, SortDir::Descending)
+                .unwrap(),
+            field_to_storage_directed(Value::from("a"), Aspect::String, SortDir::Descending)
+                .unwrap(),
+        ];
+        assert!(strings[0] < strings[1]);
+        assert_eq!(
+            decode_tuple_directed(&strings[0], &[SortDir::Descending]).unwrap(),
+            vec![(Value::from("ab"), Aspect::String)]
+        );
+        assert!(decode_tuple_directed(&keys[0], &dirs[..1]).is_err());
+        assert!(decode_tuple_directed(&keys[0][..3], &dirs).is_err());
     }
 
     #[test]
-    fn centary_encoding() {
-        let s = "0";
-        let r = centary_decimal_encode(s);
-        let d = centary_decimal_decode(r);
-        assert_eq!(s, d);
-
-        let s = "00";
-        let r = centary_decimal_encode(s);
-        let d = centary_decimal_decode(r);
-        assert_eq!(s, d);
-
-        let s = "01";
-        let r = centary_decimal_encode(s);
-        let d = centary_decimal_decode(r);
-        assert_eq!(s, d);
-
-        let s = "11";
-        let r = centary_decimal_encode(s);
-        let d = centary_decimal_decode(r);
-        assert_eq!(s, d);
-
-        let s = "22";
-        let r = centary_decimal_encode(s);
-        let d = centary_decimal_decode(r);
-        assert_eq!(s, d);
-
-        let s = "9";
-        let r = centary_decimal_encode(s);
-        let d = centary_decimal_decode(r);
-        assert_eq!(s, d);
-
-        let s = "99";
-        let r = centary_decimal_encode(s);
-        let d = centary_decimal_decode(r);
-        assert_eq!(s, d);
+    fn aspects_sharing_a_storage_type() {
+        let strings = aspects_for_storage(StorageType::String);
+        assert!(strings.contains(&Aspect::Token));
+        assert!(strings.contains(&Aspect::ID));
+        assert!(strings.contains(&Aspect::String));
+        assert!(!strings.contains(&Aspect::Decimal));
+        assert_eq!(
+            aspects_for_storage(StorageType::Int32),
+            vec![Aspect::Byte, Aspect::Short, Aspect::Int]
+        );
+        for a in aspects_for_storage(StorageType::Boolean) {
+            assert_eq!(aspect_storage(a), StorageType::Boolean);
+        }
     }
 
     #[test]
-    fn order_fractions() {
-        let fractions = vec!["1234", "123", "100", "10000", "32"];
-        let mut encodes: Vec<_> = fractions.iter().map(|x| encode_fraction(Some(x))).collect();
-        encodes.sort();
-        let results: Vec<_> = encodes.iter().map(|x| decode_fraction(x)).collect();
-        assert_eq!(vec!["100", "10000", "123", "1234", "32"], results);
+    fn decimal_fraction_running_off_the_buffer() {
+        for decimal in ["1.2345", "-1.2345", "0.5"] {
+            let key = value_to_storage(Value::from(decimal), Aspect::Decimal).unwrap();
+            assert_eq!(storage_size_checked(&key).unwrap(), key.len());
+            // drop the last fraction byte, leaving one that continues
+            let cut = &key[..key.len() - 1];
+            assert!(matches!(
+                storage_size_checked(cut),
+                Err(LexDataError::TruncatedInput(_))
+            ));
+            assert!(storage_size(Bytes::copy_from_slice(cut)) > cut.len());
+            assert!(matches!(
+                storage_to_decimal_parts(&cut[1..]),
+                Err(LexDataError::TruncatedInput(_))
+            ));
+            assert!(matches!(
+                decode_exact(cut),
+                Err(LexDataError::TruncatedInput(_))
+            ));
+        }
     }
 
     #[test]
-    fn negative_zero() {
-        let negative_zero = vec![NEGATIVE_ZERO];
-        let (is_pos, size, idx) = size_decode(&negative_zero);
-        assert!(!is_pos);
-        assert_eq!(size, 0);
-        assert_eq!(idx, 1);
+    fn date_time_value_round_trip() {
+        let dt = DateTime::parse_from_rfc3339("2024-02-29T23:59:58.25+05:30").unwrap();
+        let stamp = value_to_storage(Value::DateTime(dt), Aspect::DateTimeStamp).unwrap();
+        assert_eq!(
+            stamp,
+            chrono_to_storage(&dt, Aspect::DateTimeStamp).unwrap()
+        );
+        // chrono compares instants only, so check the offset as well
+        let offset = |v: Value| match v {
+            Value::DateTime(back) => back.offset().local_minus_utc(),
+            other => panic!("expected a date time, got {other:?}"),
+        };
+        let (v, a) = decode_date_time(&stamp).unwrap();
+        assert_eq!((&v, a), (&Value::DateTime(dt), Aspect::DateTimeStamp));
+        assert_eq!(offset(v), dt.offset().local_minus_utc());
+        // plain date times keep whole seconds
+        let key = value_to_storage(Value::from(dt), Aspect::DateTime).unwrap();
+        assert_eq!(
+            key,
+            timestamp_to_storage(dt.timestamp(), Aspect::DateTime).unwrap()
+        );
+        let (v, a) = decode_date_time(&key).unwrap();
+        assert_eq!(a, Aspect::DateTime);
+        assert_eq!(v, Value::DateTime(dt.with_nanosecond(0).unwrap()));
+        assert_eq!(offset(v), 0);
+        // the lexical form is still accepted, and decodes to the variant
+        let lexical = "2024-02-29T18:29:58Z";
+        assert_eq!(
+            value_to_storage(Value::from(lexical), Aspect::DateTime).unwrap(),
+            key
+        );
+        assert_eq!(
+            storage_to_value(Bytes::from(key)).unwrap(),
+            (date_time_value(lexical).unwrap(), Aspect::DateTime)
+        );
+        let (v, a, size) = decode_one(&stamp).unwrap();
+        assert_eq!(
+            (&v, a, size),
+            (&Value::DateTime(dt), Aspect::DateTimeStamp, 17)
+        );
+        assert_eq!(offset(v), 5 * 3600 + 30 * 60);
+        assert_eq!(
+            date_time_value("2024-02-28T24:00:00Z").unwrap(),
+            date_time_value("2024-02-29T00:00:00Z").unwrap()
+        );
+        assert!(value_to_storage(Value::DateTime(dt), Aspect::Long).is_err());
+        assert!(decode_date_time(&stamp[..16]).is_err());
     }
 
     #[test]
-    fn bignum_round_trip() {
-        let fractions = vec![
-            "1234.2343",
-            "987.23",
-            "-0.001",
-            "-10.3",
-            "-3233.23423",
-            "-0.0",
-            "0",
-            "0.0",
-            "0.100",
-            "10000.33",
-            "0.333",
-            "-9871234.1928374",
+    fn numeric_floats_interleave_with_integers() {
+        let values = [
+            (Value::Float64(-1e300), Aspect::Double),
+            (
+                Value::BigInt("-1000000000000000000000".parse().unwrap()),
+                Aspect::Integer,
+            ),
+            (Value::Float32(-2.5), Aspect::Float),
+            (Value::Int32(-2), Aspect::Int),
+            (Value::Float64(-1.0), Aspect::Double),
+            (Value::Float64(-1e-300), Aspect::Double),
+            (Value::Int64(0), Aspect::Long),
+            (Value::Float64(f64::from_bits(1)), Aspect::Double),
+            (Value::Float64(0.25), Aspect::Double),
+            (Value::Float64(0.5), Aspect::Double),
+            // equal values are ordered by aspect byte
+            (Value::Float64(5.0), Aspect::Double),
+            (Value::Int32(5), Aspect::Int),
+            (Value::Int64(5), Aspect::Long),
+            (Value::Float64(5.5), Aspect::Double),
+            (Value::Float32(5.75), Aspect::Float),
+            (Value::Int64(6), Aspect::Long),
+            (Value::Float64(1e300), Aspect::Double),
         ];
-        let mut encodes: Vec<_> = fractions
-            .iter()
-            .map(|x| bignum_to_storage(x.to_string(), Aspect::Decimal).unwrap())
-            .collect();
-        encodes.sort();
-        let results: Vec<_> = encodes
+        let keys: Vec<Vec<u8>> = values
             .iter()
-            .map(|x| {
-                let res = storage_to_bignum(&x[1..x.len()]).unwrap();
-                match res {
-                    Value::String(s) => s,
-                    _ => panic!("Can't be here"),
-                }
-            })
+            .map(|(v, a)| encode_numeric(v, *a).unwrap())
             .collect();
+        for pair in keys.windows(2) {
+            assert!(pair[0] < pair[1], "{pair:?}");
+        }
+        for ((v, a), key) in values.iter().zip(&keys) {
+            assert_eq!(decode_exact(key).unwrap(), (v.clone(), *a));
+            assert_eq!(peek_aspect(key).unwrap(), *a);
+            assert!(estimated_size(v, Aspect::Numeric) >= key.len());
+        }
         assert_eq!(
-            vec![
-                "-9871234.1928374",
-                "-3233.23423",
-                "-10.3",
-                "-0.001",
-                "-0.0",
-                "0",
-                "0.0",
-                "0.100",
-                "0.333",
-                "987.23",
-                "1234.2343",
-                "10000.33"
-            ],
-            results
+            value_to_storage(Value::Float64(5.5), Aspect::Numeric).unwrap(),
+            keys[13]
         );
+        assert_eq!(encoded_sign(&keys[7]).unwrap(), Ordering::Greater);
+        assert_eq!(encoded_sign(&keys[5]).unwrap(), Ordering::Less);
+        assert!(encode_numeric(&Value::Float64(f64::NAN), Aspect::Double).is_err());
+        assert!(decode_exact(&keys[13][..keys[13].len() - 2]).is_err());
     }
 
     #[test]
-    fn date_time_round_trip() {
-        let res = round_trip(
-            Value::String("2007-03-01T13:00:00Z".to_string()),
-            Aspect::DateTime,
+    fn same_aspect_collapses_booleans() {
+        let yes = value_to_storage(Value::Boolean(true), Aspect::Boolean).unwrap();
+        let no = value_to_storage(Value::Boolean(false), Aspect::Boolean).unwrap();
+        assert!(same_aspect(&yes, &no).unwrap());
+        let int = value_to_storage(Value::Int32(1), Aspect::Int).unwrap();
+        let string = value_to_storage(Value::from("1"), Aspect::String).unwrap();
+        assert!(!same_aspect(&int, &string).unwrap());
+        assert!(same_aspect(
+            &int,
+            &value_to_storage(Value::Int32(-9), Aspect::Int).unwrap()
+        )
+        .unwrap());
+        let narrow = encode_numeric(&Value::Int32(1), Aspect::Int).unwrap();
+        let wide = encode_numeric(&Value::Int64(2), Aspect::Long).unwrap();
+        assert!(same_aspect(&narrow, &wide).unwrap());
+        assert!(same_aspect(&[], &int).is_err());
+    }
+
+    #[cfg(feature = "url")]
+    #[test]
+    fn uri_ordered_clusters_by_host() {
+        let uris = [
+            "http://b.com/a",
+            "https://a.com/a",
+            "https://a.com/a?q=1",
+            "https://a.com/z",
+            "https://a.com.au/",
+            "https://b.com/",
+        ];
+        let keys: Vec<Vec<u8>> = uris
+            .iter()
+            .map(|u| encode_uri_ordered(u).unwrap())
+            .collect();
+        for pair in keys.windows(2) {
+            assert!(pair[0] < pair[1], "{pair:?}");
+        }
+        // the raw text would put a.com.au before a.com/z
+        assert!(uris[4] < uris[3]);
+        let shouted = encode_uri_ordered("HTTPS://A.COM/z").unwrap();
+        assert_eq!(shouted[..20], keys[3][..20]);
+        for (u, key) in uris.iter().zip(&keys) {
+            assert_eq!(decode_uri_ordered(key).unwrap(), Value::from(*u));
+            assert_eq!(
+                value_to_storage(Value::from(*u), Aspect::OrderedURI).unwrap(),
+                *key
+            );
+        }
+        assert!(encode_uri_ordered("not a uri").is_err());
+        assert!(decode_uri_ordered(&keys[0][..keys[0].len() - 1]).is_err());
+        assert!(
+            decode_uri_ordered(&value_to_storage(Value::from("x"), Aspect::String).unwrap())
+                .is_err()
         );
+        // the key ends with its text, so further fields may follow it
+        let mut tuple = keys[1].clone();
+        tuple.extend(value_to_storage(Value::Int32(7), Aspect::Int).unwrap());
         assert_eq!(
-            (
-                Value::String("2007-03-01T13:00:00Z".to_string()),
-                Aspect::DateTime
-            ),
-            res
+            decode_tuple(&tuple).unwrap(),
+            vec![
+                (Value::from(uris[1]), Aspect::OrderedURI),
+                (Value::Int32(7), Aspect::Int)
+            ]
+        );
+        assert_eq!(
+            decode_ref(&keys[1]).unwrap(),
+            (ValueRef::String(uris[1]), Aspect::OrderedURI)
         );
     }
 
     #[test]
-    fn date_time_ordering() {
-        let dates = vec![
-            "2525-03-01T12:00:00Z",
-            "2007-03-01T13:00:00Z",
-            "1977-05-07T11:30:20Z",
+    fn huge_decimal_round_trip_and_order() {
+        let int: String = "9876543210".repeat(20);
+        let fraction: String = "0123456789".repeat(10);
+        let base = format!("{int}.{fraction}");
+        let bigger_fraction = format!("{int}.{}9{}", &fraction[..98], &fraction[99..]);
+        let bigger_int = format!("{}1.{fraction}", &int[..199]);
+        let longer_fraction = format!("{base}5");
+        let mut ascending = vec![
+            base.clone(),
+            longer_fraction.clone(),
+            bigger_fraction.clone(),
+            bigger_int.clone(),
         ];
-        let mut date_bytes: Vec<_> = dates
-            .iter()
-            .map(|x| value_to_storage(Value::String(x.to_string()), Aspect::DateTime).unwrap())
-            .collect();
-        date_bytes.sort();
-        let dates_sorted: Vec<_> = date_bytes
+        let negatives: Vec<String> = ascending.iter().rev().map(|d| format!("-{d}")).collect();
+        ascending.splice(0..0, negatives);
+        let keys: Vec<Vec<u8>> = ascending
             .iter()
-            .map(|x| {
-                let res = &*x;
-                let bytes: Bytes = Bytes::from(res.clone());
-                let (res, _aspect) = storage_to_value(bytes).unwrap();
-                match res {
-                    Value::String(date) => date,
-                    _ => panic!("Didn't work"),
-                }
-            })
+            .map(|d| value_to_storage(Value::from(d.as_str()), Aspect::Decimal).unwrap())
             .collect();
+        for pair in keys.windows(2) {
+            assert!(pair[0] < pair[1]);
+        }
+        for (d, key) in ascending.iter().zip(&keys) {
+            assert_eq!(
+                storage_to_value(Bytes::from(key.clone())).unwrap(),
+                (Value::from(d.as_str()), Aspect::Decimal)
+            );
+            assert_eq!(storage_size_checked(key).unwrap(), key.len());
+            let (is_neg, magnitude, digits) = storage_to_decimal_parts(&key[1..]).unwrap();
+            assert_eq!(is_neg, d.starts_with('-'));
+            assert_eq!(magnitude.to_string().len(), 200);
+            assert!(digits.len() >= 100);
+        }
+        let huge: Integer = int.parse().unwrap();
+        let key = value_to_storage(Value::BigInt(huge.clone()), Aspect::Integer).unwrap();
         assert_eq!(
-            vec![
-                "1977-05-07T11:30:20Z",
-                "2007-03-01T13:00:00Z",
-                "2525-03-01T12:00:00Z"
-            ],
-            dates_sorted
+            storage_to_value(Bytes::from(key)).unwrap(),
+            (Value::BigInt(huge), Aspect::Integer)
         );
     }
 
     #[test]
-    fn self_termination_tests() {
-        // we need to know we are not relying on the length of an array anywhere.
-        let mut num = value_to_storage(
-            Value::BigInt("-3233".parse::<Integer>().unwrap()),
-            Aspect::Integer,
-        )
-        .unwrap();
-        let garbage = vec![23, 35, 128];
-        num.extend(garbage);
-        let (res, _) = storage_to_value(Bytes::from(num)).unwrap();
-        match res {
-            Value::BigInt(i) => {
-                let rendered = format!("{i:?}");
-                assert_eq!(rendered, "-3233");
+    fn placed_booleans_sort_before_or_after_integers() {
+        let ints: Vec<Vec<u8>> = [
+            value_to_storage(Value::Int32(i32::MIN), Aspect::Int).unwrap(),
+            value_to_storage(Value::BigInt(Integer::from(-7)), Aspect::Integer).unwrap(),
+            value_to_storage(Value::Int64(i64::MAX), Aspect::Long).unwrap(),
+            encode_numeric(&Value::Int32(3), Aspect::Int).unwrap(),
+        ]
+        .into();
+        for tag in [BOOLEAN_TAG_FIRST, BOOLEAN_TAG_MIN, CUSTOM_TAG_MIN - 1] {
+            let no = encode_boolean_placed(false, tag).unwrap();
+            let yes = encode_boolean_placed(true, tag).unwrap();
+            assert!(no < yes);
+            let before = tag == BOOLEAN_TAG_FIRST;
+            for int in &ints {
+                assert_eq!(yes < *int, before);
+                assert_eq!(no < *int, before);
             }
-            _ => panic!("This is not good"),
+            assert_eq!(
+                decode_exact(&yes).unwrap(),
+                (Value::Boolean(true), Aspect::Boolean)
+            );
+            assert_eq!(
+                decode_ref(&no).unwrap(),
+                (ValueRef::Boolean(false), Aspect::Boolean)
+            );
+            assert_eq!(peek_aspect(&no).unwrap(), Aspect::Boolean);
+            assert!(same_aspect(&no, &yes).unwrap());
+            assert!(storage_size_checked(&yes[..1]).is_err());
         }
+        assert!(encode_boolean_placed(true, aspect_byte(Aspect::True)).is_err());
+        assert!(encode_boolean_placed(true, CUSTOM_TAG_MIN).is_err());
+    }
 
-        let mut num =
-            value_to_storage(Value::String("-3233.23423".to_string()), Aspect::Decimal).unwrap();
-        let garbage = vec![183, 35, 128];
-        num.extend(garbage);
-        let (res, _) = storage_to_value(Bytes::from(num)).unwrap();
-        match res {
-            Value::String(n) => {
-                assert_eq!("-3233.23423".to_string(), n);
+    #[test]
+    fn truncated_date_time_is_reported() {
+        let key = value_to_storage(Value::from("2001-02-03T04:05:06Z"), Aspect::DateTime).unwrap();
+        match storage_to_value(Bytes::copy_from_slice(&key[..5])) {
+            Err(LexDataError::TruncatedInput(msg)) => assert_eq!(
+                msg,
+                "DateTime values need 8 bytes after the aspect but only 4 were given"
+            ),
+            other => panic!("expected a truncated input error, got {other:?}"),
+        }
+        assert!(storage_to_value(Bytes::from(key)).is_ok());
+    }
+
+    #[test]
+    fn typed_values_are_checked_against_their_aspect() {
+        assert_eq!(
+            typed_value(Aspect::Short, TypedInput::Int(-300)).unwrap(),
+            Value::Int32(-300)
+        );
+        assert!(matches!(
+            typed_value(Aspect::Byte, TypedInput::Int(300)),
+            Err(LexDataError::OutOfRange(_))
+        ));
+        assert!(matches!(
+            typed_value(Aspect::Int, TypedInput::Int(1 << 40)),
+            Err(LexDataError::OutOfRange(_))
+        ));
+        assert_eq!(
+            typed_value(Aspect::Long, TypedInput::Int(1 << 40)).unwrap(),
+            Value::Int64(1 << 40)
+        );
+        assert_eq!(
+            typed_value(Aspect::Integer, TypedInput::Int(-5)).unwrap(),
+            Value::BigInt(Integer::from(-5))
+        );
+        assert!(typed_value(Aspect::PositiveInteger, TypedInput::Int(0)).is_err());
+        assert_eq!(
+            typed_value(Aspect::Decimal, TypedInput::Float(2.5)).unwrap(),
+            Value::from("2.5")
+        );
+        assert!(typed_value(Aspect::Decimal, TypedInput::String("2.5.1".to_string())).is_err());
+        assert!(matches!(
+            value_to_storage(Value::from("1.2x"), Aspect::Decimal),
+            Err(LexDataError::BadDecimalFormat(_))
+        ));
+        assert_eq!(
+            value_to_storage(Value::from("-.5"), Aspect::Decimal).unwrap(),
+            value_to_storage(Value::from("-0.5"), Aspect::Decimal).unwrap()
+        );
+        assert_eq!(
+            typed_value(Aspect::Double, TypedInput::Float(0.1)).unwrap(),
+            Value::Float64(0.1)
+        );
+        assert_eq!(
+            typed_value(Aspect::Float, TypedInput::Float(f64::INFINITY)).unwrap(),
+            Value::Float32(f32::INFINITY)
+        );
+        assert_eq!(
+            typed_value(Aspect::Float, TypedInput::Float(f64::NEG_INFINITY)).unwrap(),
+            Value::Float32(f32::NEG_INFINITY)
+        );
+        assert_eq!(
+            typed_value(Aspect::Float, TypedInput::Float(0.5)).unwrap(),
+            Value::Float32(0.5)
+        );
+        assert!(matches!(
+            typed_value(Aspect::Float, TypedInput::Float(1e300)),
+            Err(LexDataError::OutOfRange(_))
+        ));
+        assert!(typed_value(Aspect::Decimal, TypedInput::Float(f64::INFINITY)).is_err());
+        assert_eq!(
+            typed_value(Aspect::Boolean, TypedInput::Bool(true)).unwrap(),
+            Value::Boolean(true)
+        );
+        assert_eq!(
+            typed_value(
+                Aspect::DateTime,
+                TypedInput::String("2020-01-01T00:00:00Z".to_string())
+            )
+            .unwrap(),
+            Value::from("2020-01-01T00:00:00Z")
+        );
+        assert!(matches!(
+            typed_value(
+                Aspect::DateTime,
+                TypedInput::String("2020-13-01T00:00:00Z".to_string())
+            ),
+            Err(LexDataError::BadDateFormat(_))
+        ));
+        assert!(typed_value(Aspect::Int, TypedInput::Bool(true)).is_err());
+        assert!(matches!(
+            typed_value(Aspect::NCName, TypedInput::String("a:b".to_string())),
+            Err(LexDataError::BadName(_))
+        ));
+    }
+
+    #[test]
+    fn durations_sort_by_months_then_time() {
+        let ascending = [
+            "-P1Y",
+            "-P1M",
+            "-PT0.5S",
+            "PT0S",
+            "PT0.000000001S",
+            "PT59S",
+            "PT1M",
+            "P1DT1S",
+            "P100D",
+            "P1M",
+            "P1MT1S",
+            "P1Y",
+            "P1Y1M",
+        ];
+        let keys: Vec<Vec<u8>> = ascending
+            .iter()
+            .map(|s| duration_sort_key(&parse_duration(s).unwrap()))
+            .collect();
+        for pair in keys.windows(2) {
+            assert!(pair[0] < pair[1], "{pair:?}");
+        }
+        // the key is a function of the totals alone
+        let key = |s: &str| duration_sort_key(&parse_duration(s).unwrap());
+        assert_eq!(key("PT60S"), key("PT1M"));
+        // untagged: two i128 totals and no aspect byte
+        assert_eq!(key("P1D").len(), 32);
+        assert_eq!(key("P12M"), key("P1Y"));
+        assert_eq!(key("-PT0S"), key("P0D"));
+        assert_eq!(key("PT1.5S"), key("PT1.500S"));
+        assert_eq!(
+            parse_duration("-P1Y2M3DT4H5M6.7S").unwrap(),
+            ParsedDuration {
+                is_negative: true,
+                years: 1,
+                months: 2,
+                days: 3,
+                hours: 4,
+                minutes: 5,
+                seconds: 6,
+                nanos: 700_000_000,
             }
-            _ => panic!("This is not good"),
+        );
+        for bad in [
+            "P",
+            "PT",
+            "P1",
+            "1Y",
+            "P1D2Y",
+            "PT1.S",
+            "P-1D",
+            "PT1.0000000001S",
+            "P1H",
+        ] {
+            assert!(parse_duration(bad).is_err(), "{bad}");
         }
     }
 }
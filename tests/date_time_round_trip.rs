@@ -0,0 +1,92 @@
+use bytes::Bytes;
+use chrono::{DateTime, FixedOffset, TimeZone};
+use lexdata::{
+    chrono_to_storage, storage_to_chrono, storage_to_value, value_to_storage, Aspect, Value,
+};
+
+// A spread of instants from before 1900 to after 2100, with sub-second
+// parts down to the nanosecond and offsets across the whole range.
+fn date_times() -> Vec<DateTime<FixedOffset>> {
+    let offsets = [
+        -86_399,
+        -14 * 3600,
+        -9 * 3600 - 30 * 60,
+        -1,
+        0,
+        1,
+        5 * 3600 + 45 * 60,
+        14 * 3600,
+        86_399,
+    ];
+    let nanos = [0, 1, 999, 1_000_000, 123_456_789, 500_000_000, 999_999_999];
+    let mut date_times = Vec::new();
+    let mut secs = -2_500_000_000_i64;
+    while secs < 4_200_000_000 {
+        for (i, offset) in offsets.iter().enumerate() {
+            let offset = FixedOffset::east_opt(*offset).unwrap();
+            let nano = nanos[(secs.unsigned_abs() as usize + i) % nanos.len()];
+            date_times.push(offset.timestamp_opt(secs, nano).unwrap());
+        }
+        secs += 7_919_993;
+    }
+    date_times
+}
+
+#[test]
+fn chrono_date_times_survive_storage() {
+    for dt in date_times() {
+        let key = chrono_to_storage(&dt, Aspect::DateTimeStamp).unwrap();
+        assert_eq!(key.len(), 17);
+        let back = storage_to_chrono(&key).unwrap();
+        assert_eq!(back, dt);
+        assert_eq!(back.offset(), dt.offset());
+        assert_eq!(back.timestamp_subsec_nanos(), dt.timestamp_subsec_nanos());
+    }
+}
+
+#[test]
+fn lexical_date_time_stamps_survive_storage() {
+    // the lexical form only has offsets in whole minutes
+    let whole_minutes = date_times()
+        .into_iter()
+        .filter(|dt| dt.offset().local_minus_utc() % 60 == 0);
+    for dt in whole_minutes {
+        let lexical = dt.to_rfc3339();
+        let key = value_to_storage(Value::String(lexical.clone()), Aspect::DateTimeStamp).unwrap();
+        assert_eq!(key, chrono_to_storage(&dt, Aspect::DateTimeStamp).unwrap());
+        let (v, a) = storage_to_value(Bytes::from(key)).unwrap();
+        assert_eq!(a, Aspect::DateTimeStamp);
+        let Value::DateTime(back) = v else {
+            panic!("date time stamps decode to chrono date times");
+        };
+        assert_eq!((back, back.offset()), (dt, dt.offset()));
+    }
+}
+
+#[test]
+fn date_time_stamps_sort_by_instant() {
+    let mut date_times = date_times();
+    date_times.sort_by_key(|dt| (dt.timestamp(), dt.timestamp_subsec_nanos()));
+    let keys: Vec<Vec<u8>> = date_times
+        .iter()
+        .map(|dt| chrono_to_storage(dt, Aspect::DateTimeStamp).unwrap())
+        .collect();
+    for pair in keys.windows(2) {
+        assert!(pair[0] <= pair[1]);
+    }
+}
+
+#[test]
+fn plain_date_times_decode_to_utc_seconds() {
+    let key = value_to_storage(
+        Value::String("2007-03-01T14:00:00.25+01:00".to_string()),
+        Aspect::DateTime,
+    )
+    .unwrap();
+    let dt = storage_to_chrono(&key).unwrap();
+    assert_eq!(
+        dt,
+        DateTime::parse_from_rfc3339("2007-03-01T13:00:00Z").unwrap()
+    );
+    assert_eq!(dt.offset().local_minus_utc(), 0);
+}
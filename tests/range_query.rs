@@ -0,0 +1,47 @@
+use std::collections::BTreeMap;
+
+use bytes::Bytes;
+use lexdata::{range_bounds, storage_to_value, value_to_storage, Aspect, Value};
+use rug::Integer;
+
+fn key(v: Value, a: Aspect) -> Vec<u8> {
+    value_to_storage(v, a).unwrap()
+}
+
+#[test]
+fn integers_between_bounds() {
+    let mut index: BTreeMap<Vec<u8>, Value> = BTreeMap::new();
+    for i in (-300..300).step_by(7) {
+        index.insert(key(Value::Int32(i), Aspect::Int), Value::Int32(i));
+        index.insert(
+            key(Value::Int64(i.into()), Aspect::Long),
+            Value::Int64(i.into()),
+        );
+        let big = Value::BigInt(Integer::from(i) * 1_000_000_000_000_i64);
+        index.insert(key(big.clone(), Aspect::Integer), big);
+        let s = Value::String(format!("{i}"));
+        index.insert(key(s.clone(), Aspect::String), s);
+        let d = Value::String(format!("{i}.5"));
+        index.insert(key(d.clone(), Aspect::Decimal), d);
+    }
+
+    let bounds = range_bounds(&Value::Int32(10), &Value::Int32(100), Aspect::Int).unwrap();
+    let ranged: Vec<Value> = index
+        .range(bounds)
+        .map(|(k, v)| {
+            // every key in the range decodes to what was stored under it
+            assert_eq!(storage_to_value(Bytes::from(k.clone())).unwrap().0, *v);
+            v.clone()
+        })
+        .collect();
+
+    let brute_force: Vec<Value> = index
+        .iter()
+        .filter(|(k, _)| k[0] == Aspect::Int as u8)
+        .filter(|(_, v)| matches!(v, Value::Int32(i) if (10..=100).contains(i)))
+        .map(|(_, v)| v.clone())
+        .collect();
+
+    assert!(!ranged.is_empty());
+    assert_eq!(ranged, brute_force);
+}